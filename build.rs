@@ -0,0 +1,51 @@
+// Copyright 2025 Google
+// SPDX-License-Identifier: MIT
+
+//! Embeds the templates of `src/generator/templates` into the generator, so that an installed
+//! binary does not look for them in the tree it was built from.
+
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+const TEMPLATE_DIR: &str = "src/generator/templates";
+
+fn collect_templates(dir: &Path, templates: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_templates(&path, templates)?;
+        } else {
+            templates.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn main() -> io::Result<()> {
+    println!("cargo:rerun-if-changed={}", TEMPLATE_DIR);
+
+    let manifest_dir = PathBuf::from(env::var_os("CARGO_MANIFEST_DIR").unwrap());
+    let template_dir = manifest_dir.join(TEMPLATE_DIR);
+    let mut templates = Vec::new();
+    collect_templates(&template_dir, &mut templates)?;
+    templates.sort();
+
+    let mut table = String::from("&[\n");
+    for path in &templates {
+        // Template names always use `/`, whatever the separator of the host.
+        let name = path
+            .strip_prefix(&template_dir)
+            .unwrap()
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        table += &format!("    ({:?}, include_str!({:?})),\n", name, path);
+    }
+    table += "]\n";
+
+    let out_dir = PathBuf::from(env::var_os("OUT_DIR").unwrap());
+    fs::write(out_dir.join("templates.rs"), table)
+}
@@ -0,0 +1,192 @@
+// Copyright 2025 Google
+// SPDX-License-Identifier: MIT
+
+//! `cargo apigen`: regenerates (or checks) the XML-described APIs of a crate.
+//!
+//! Generation is driven by `apigen.toml` at the crate root when present. Otherwise every XML
+//! file in the crate's `xml/` directory is generated into `$OUT_DIR`, or `src/generated` when
+//! not invoked from a build script.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use clap::Parser;
+
+use apigen_xml::common::config::{
+    parse_config, Config, CopyrightOverrides, GenerateEntry, CONFIG_FILE_NAME,
+};
+use apigen_xml::common::lockfile::{
+    frozen_lock_path, frozen_lock_up_to_date, opcode_lock_path, opcode_lock_up_to_date,
+    stype_lock_path, stype_lock_up_to_date, write_frozen_lock, write_opcode_lock, write_stype_lock,
+};
+use apigen_xml::common::ApiGenError;
+use apigen_xml::{generator, parser};
+
+#[derive(Parser, Debug)]
+#[command(name = "cargo", bin_name = "cargo")]
+enum Cargo {
+    Apigen(Args),
+}
+
+#[derive(clap::Args, Debug)]
+#[command(version, about = "Generate the XML-described APIs of a crate", long_about = None)]
+struct Args {
+    /// Path to the Cargo.toml of the crate, defaults to the current directory
+    #[arg(long)]
+    manifest_path: Option<PathBuf>,
+
+    /// Verify that the generated files are up to date instead of writing them
+    #[arg(long)]
+    check: bool,
+
+    /// Command run on each generated file, overriding the `post_process` of apigen.toml
+    #[arg(long)]
+    post_process: Option<String>,
+
+    /// Directory of templates used instead of the built-in ones of the same name, overriding the
+    /// `template_dir` of apigen.toml
+    #[arg(long)]
+    template_dir: Option<PathBuf>,
+
+    /// Delete files an earlier run generated that are no longer produced
+    #[arg(long, conflicts_with = "check")]
+    prune: bool,
+
+    #[command(flatten)]
+    copyright: CopyrightOverrides,
+}
+
+fn default_out_dir(crate_root: &Path) -> PathBuf {
+    env::var_os("OUT_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| crate_root.join("src/generated"))
+}
+
+fn discover(crate_root: &Path) -> Result<Config, ApiGenError> {
+    let config_path = crate_root.join(CONFIG_FILE_NAME);
+    if config_path.exists() {
+        return parse_config(&config_path);
+    }
+
+    let mut config = Config::default();
+    let xml_dir = crate_root.join("xml");
+    if !xml_dir.is_dir() {
+        return Ok(config);
+    }
+    config.generate = fs::read_dir(xml_dir)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "xml"))
+        .map(|filename| GenerateEntry {
+            filename,
+            ..Default::default()
+        })
+        .collect();
+    config.generate.sort_by(|a, b| a.filename.cmp(&b.filename));
+    Ok(config)
+}
+
+fn main() -> ExitCode {
+    let Cargo::Apigen(args) = Cargo::parse();
+    match run(args) {
+        Ok(code) => code,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(args: Args) -> Result<ExitCode, ApiGenError> {
+    let crate_root = match &args.manifest_path {
+        Some(manifest_path) => manifest_path
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_default(),
+        None => env::current_dir()?,
+    };
+
+    let config = discover(&crate_root)?;
+    if config.generate.is_empty() {
+        eprintln!(
+            "no {} or xml/*.xml found in {}",
+            CONFIG_FILE_NAME,
+            crate_root.display()
+        );
+        return Ok(ExitCode::FAILURE);
+    }
+
+    // Command line overrides take precedence over apigen.toml, whose paths are crate-relative.
+    let mut config_copyright = config.copyright.clone();
+    config_copyright.banner = config_copyright
+        .banner
+        .map(|banner| crate_root.join(banner));
+    let copyright = args.copyright.clone().or(&config_copyright);
+
+    let mut up_to_date = true;
+    for entry in config.generate {
+        let filename = crate_root.join(&entry.filename);
+        let out_dir = match &entry.out_dir {
+            Some(out_dir) => crate_root.join(out_dir),
+            None => default_out_dir(&crate_root),
+        };
+        let mut api = parser::parse_api(&filename)?;
+        for warning in api.warnings() {
+            eprintln!("warning: {}: {}", filename.display(), warning);
+        }
+        copyright.apply(&mut api)?;
+        api.set_post_process(args.post_process.clone().or(entry.post_process));
+        api.set_template_dir(
+            args.template_dir
+                .clone()
+                .or(entry.template_dir.map(|dir| crate_root.join(dir))),
+        );
+        if let Some(namespace) = &entry.namespace {
+            api.set_header_namespace(namespace);
+        }
+
+        if args.check {
+            for path in generator::check_api(&api, &out_dir, false)? {
+                eprintln!("out of date: {}", path.display());
+                up_to_date = false;
+            }
+            let lock_path = stype_lock_path(&filename);
+            if !stype_lock_up_to_date(&lock_path, api.stype_lock()) {
+                eprintln!("out of date: {}", lock_path.display());
+                up_to_date = false;
+            }
+            let opcode_path = opcode_lock_path(&filename);
+            if !opcode_lock_up_to_date(&opcode_path, api.opcode_lock()) {
+                eprintln!("out of date: {}", opcode_path.display());
+                up_to_date = false;
+            }
+            let frozen_path = frozen_lock_path(&filename);
+            if !frozen_lock_up_to_date(&frozen_path, api.frozen_lock()) {
+                eprintln!("out of date: {}", frozen_path.display());
+                up_to_date = false;
+            }
+        } else {
+            fs::create_dir_all(&out_dir)?;
+            for path in generator::generate_api(&api, &out_dir, args.prune)? {
+                if args.prune {
+                    eprintln!("removed: {}", path.display());
+                } else {
+                    eprintln!(
+                        "warning: {} is no longer generated, --prune deletes it",
+                        path.display()
+                    );
+                }
+            }
+            write_stype_lock(&stype_lock_path(&filename), api.stype_lock())?;
+            write_opcode_lock(&opcode_lock_path(&filename), api.opcode_lock())?;
+            write_frozen_lock(&frozen_lock_path(&filename), api.frozen_lock())?;
+        }
+    }
+
+    Ok(if up_to_date {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    })
+}
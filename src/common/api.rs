@@ -1,25 +1,580 @@
 // Copyright 2025 Google
 // SPDX-License-Identifier: MIT
 
-use crate::common::utils::to_pascal_case;
+use crate::common::lockfile::{fnv1a_32, stype_auto_value, stype_next_value};
+use crate::common::utils::{
+    escape_c_keyword, escape_rust_keyword, eval_int_expr, parse_int_literal, split_array_type,
+    to_pascal_case,
+};
 use crate::common::*;
-use regex::Regex;
 use serde::Serialize;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::path::{Path, PathBuf};
 
 const NUM_BYTES_IN_U64: usize = 8;
 const NUM_BYTES_IN_U32: usize = 4;
 
+/// A type every description can use without defining it.
+pub struct PrimitiveType {
+    /// Name written in descriptions.
+    pub name: &'static str,
+    /// Spelling in generated Rust, a path for types outside the prelude.
+    pub rust: &'static str,
+    pub size: usize,
+    pub c: &'static str,
+}
+
+const fn primitive(
+    name: &'static str,
+    rust: &'static str,
+    size: usize,
+    c: &'static str,
+) -> PrimitiveType {
+    PrimitiveType {
+        name,
+        rust,
+        size,
+        c,
+    }
+}
+
+/// The built-in types. Pointer-sized types assume a 64-bit target. Atomics are their plain integer
+/// in C, since `_Atomic` is not C++ and need not have the layout of the Rust type.
+pub const PRIMITIVE_TYPES: &[PrimitiveType] = &[
+    primitive("u8", "u8", 1, "uint8_t"),
+    primitive("i8", "i8", 1, "int8_t"),
+    primitive("u16", "u16", 2, "uint16_t"),
+    primitive("i16", "i16", 2, "int16_t"),
+    primitive("u32", "u32", 4, "uint32_t"),
+    primitive("i32", "i32", 4, "int32_t"),
+    primitive("u64", "u64", 8, "uint64_t"),
+    primitive("i64", "i64", 8, "int64_t"),
+    primitive("f32", "f32", 4, "float"),
+    primitive("f64", "f64", 8, "double"),
+    primitive("bool", "bool", 1, "bool"),
+    primitive("c_char", "core::ffi::c_char", 1, "char"),
+    primitive("usize", "usize", 8, "size_t"),
+    primitive("isize", "isize", 8, "intptr_t"),
+    primitive("AtomicU8", "core::sync::atomic::AtomicU8", 1, "uint8_t"),
+    primitive("AtomicI8", "core::sync::atomic::AtomicI8", 1, "int8_t"),
+    primitive("AtomicU16", "core::sync::atomic::AtomicU16", 2, "uint16_t"),
+    primitive("AtomicI16", "core::sync::atomic::AtomicI16", 2, "int16_t"),
+    primitive("AtomicU32", "core::sync::atomic::AtomicU32", 4, "uint32_t"),
+    primitive("AtomicI32", "core::sync::atomic::AtomicI32", 4, "int32_t"),
+    primitive("AtomicU64", "core::sync::atomic::AtomicU64", 8, "uint64_t"),
+    primitive("AtomicI64", "core::sync::atomic::AtomicI64", 8, "int64_t"),
+    primitive("*mut std::ffi::c_void", "*mut std::ffi::c_void", 8, "void*"),
+];
+
+/// File types whose items name each other and can import them from sibling files.
+const LINKED_FILE_TYPES: &[&str] = &["protocol", "Rust", "header"];
+
+/// File types declaring or naming the command structs of their protocols in one namespace.
+const COMMAND_TYPE_FILE_TYPES: &[&str] = &[
+    "protocol",
+    "header",
+    "decoder",
+    "conformance",
+    "log_dump",
+    "dump",
+    "fidl",
+    "aidl",
+];
+
 #[derive(Debug, Default, Serialize, Clone)]
 pub struct Api {
     name: String,
     copyright: Copyright,
     version: u32,
+    /// Wraps members with `units` in unit newtypes in the wire structs.
+    strict_units: bool,
+    /// Keeps XML comments preceding items and emits them above the generated items.
+    preserve_comments: bool,
     definitions: HashMap<String, Definition>,
     definition_items: HashMap<String, DefinitionItem>,
     type_sizes: HashMap<String, usize>,
     rust_to_c_typemap: HashMap<String, String>,
     generated_files: Vec<GeneratedFile>,
+    external_types: Vec<ExternalType>,
+    stype_lock: BTreeMap<String, u32>,
+    /// Layout fingerprints of frozen items, recorded the first time each is generated frozen.
+    frozen_lock: BTreeMap<String, u32>,
+    /// Auto-assigned opcode values, keyed by `protocol::opcode` or `protocol::events::opcode`.
+    opcode_lock: BTreeMap<String, u32>,
+    /// API version introducing each item declaring `since`.
+    item_since: HashMap<String, u32>,
+    /// Command run on each generated file, set by the caller rather than the XML.
+    post_process: Option<String>,
+    /// Directory whose templates take precedence over the built-in ones, set by the caller.
+    template_dir: Option<PathBuf>,
+    /// Patterns naming the count member of an array member, tried in order. `{name}` stands for
+    /// the array member's name and `{singular}` for it without a trailing `s`.
+    count_patterns: Vec<String>,
+    /// Non-fatal problems found while building the model, reported by the binaries.
+    warnings: Vec<String>,
+    /// Formats of constant, enum and flag values; `None` keeps them as written.
+    constant_literals: Option<LiteralFormat>,
+    enum_literals: Option<LiteralFormat>,
+    flag_literals: Option<LiteralFormat>,
+    /// Naming of the items of each file type that renames them, keyed by file type.
+    namings: HashMap<String, Naming>,
+}
+
+/// Replaces the identifiers of `text`, a type or an integer expression, that `names` renames.
+fn rename_identifiers(text: &str, names: &HashMap<String, String>) -> String {
+    let mut renamed = String::new();
+    let mut identifier = String::new();
+    for c in text.chars() {
+        if c.is_ascii_alphanumeric() || c == '_' {
+            identifier.push(c);
+            continue;
+        }
+        renamed += names.get(&identifier).unwrap_or(&identifier);
+        identifier.clear();
+        renamed.push(c);
+    }
+    renamed += names.get(&identifier).unwrap_or(&identifier);
+    renamed
+}
+
+fn rename_members(members: &mut [Member], names: &HashMap<String, String>) {
+    for member in members {
+        member.type_name = rename_identifiers(&member.type_name, names);
+        let expressions = [
+            &mut member.enum_name,
+            &mut member.flag_name,
+            &mut member.min,
+            &mut member.max,
+            &mut member.max_len,
+        ];
+        for expression in expressions.into_iter().flatten() {
+            *expression = rename_identifiers(expression, names);
+        }
+        // String defaults are kept as written.
+        if let Some(default) = member.default.as_mut().filter(|d| !d.starts_with('"')) {
+            *default = rename_identifiers(default, names);
+        }
+        if let Some(inline) = &mut member.inline_struct {
+            rename_struct(&mut inline.common, names);
+        }
+    }
+}
+
+fn rename_struct(common: &mut StructCommon, names: &HashMap<String, String>) {
+    common.name = rename_identifiers(&common.name, names);
+    rename_members(&mut common.members, names);
+    for info in &mut common.array_info {
+        info.array_base_type = rename_identifiers(&info.array_base_type, names);
+    }
+}
+
+fn rename_trailing(trailing: &mut Option<TrailingArray>, names: &HashMap<String, String>) {
+    if let Some(trailing) = trailing {
+        trailing.type_name = rename_identifiers(&trailing.type_name, names);
+    }
+}
+
+/// Applies `names` to an item and to the types and constants it uses.
+fn rename_item(item: &mut DefinitionItem, names: &HashMap<String, String>) {
+    match item {
+        DefinitionItem::Constant(c) => {
+            c.name = rename_identifiers(&c.name, names);
+            c.type_name = rename_identifiers(&c.type_name, names);
+            c.value = rename_identifiers(&c.value, names);
+        }
+        DefinitionItem::Typedef(t) => {
+            t.name = rename_identifiers(&t.name, names);
+            t.type_name = rename_identifiers(&t.type_name, names);
+        }
+        DefinitionItem::Struct(s) => rename_struct(&mut s.common, names),
+        DefinitionItem::Union(u) => rename_struct(&mut u.common, names),
+        DefinitionItem::Enum(e) => {
+            e.name = rename_identifiers(&e.name, names);
+            e.type_name = rename_identifiers(&e.type_name, names);
+        }
+        DefinitionItem::Flag(f) => {
+            f.name = rename_identifiers(&f.name, names);
+            f.type_name = rename_identifiers(&f.type_name, names);
+        }
+        DefinitionItem::ExtensibleStruct(s) => rename_struct(&mut s.common, names),
+        DefinitionItem::ExtensibleStructs(c) => {
+            for s in &mut c.structs {
+                rename_struct(&mut s.common, names);
+            }
+        }
+        DefinitionItem::Function(f) => {
+            rename_members(&mut f.members, names);
+            f.ret = rename_identifiers(&f.ret, names);
+        }
+        DefinitionItem::Protocol(p) => {
+            p.protocol_struct_name = rename_identifiers(&p.protocol_struct_name, names);
+            for req in &mut p.requests {
+                rename_members(&mut req.members, names);
+                rename_trailing(&mut req.trailing, names);
+            }
+            for resp in &mut p.responses {
+                rename_members(&mut resp.members, names);
+                rename_trailing(&mut resp.trailing, names);
+            }
+            for event in &mut p.events {
+                rename_members(&mut event.members, names);
+                rename_trailing(&mut event.trailing, names);
+            }
+        }
+        DefinitionItem::Object(_) => {}
+    }
+}
+
+fn calculate_type_size(
+    type_name: &str,
+    type_sizes: &HashMap<String, usize>,
+) -> Result<usize, ApiGenError> {
+    if let Some(size) = type_sizes.get(type_name) {
+        return Ok(*size);
+    }
+    if !type_name.starts_with('[') {
+        return Err(ApiGenError::TypeNotFound(type_name.to_string()));
+    }
+
+    // Nested arrays recurse through their element type.
+    let (element, count_name) = split_array_type(type_name)
+        .ok_or_else(|| ApiGenError::InvalidArrayTypeFormat(type_name.to_string()))?;
+    let element_size = calculate_type_size(element, type_sizes)?;
+    let count = match count_name.parse::<usize>() {
+        Ok(count) => count,
+        Err(_) => *type_sizes
+            .get(count_name)
+            .ok_or_else(|| ApiGenError::ConstantNotFound(count_name.to_string()))?,
+    };
+    Ok(element_size * count)
+}
+
+/// Returns the alignment C and Rust give a type: primitives are aligned to their size, arrays to
+/// their element, enums, flags and typedefs to their underlying type, and structs and unions to
+/// their `align` or else to their most aligned member.
+fn calculate_type_align(
+    type_name: &str,
+    definition_items: &HashMap<String, DefinitionItem>,
+    type_sizes: &HashMap<String, usize>,
+) -> Result<usize, ApiGenError> {
+    if let Some((element, _)) = split_array_type(type_name) {
+        return calculate_type_align(element, definition_items, type_sizes);
+    }
+    let members_align = |common: &StructCommon| {
+        common
+            .members
+            .iter()
+            .try_fold(common.align.unwrap_or(1), |align, member| {
+                Ok::<_, ApiGenError>(align.max(calculate_type_align(
+                    &member.type_name,
+                    definition_items,
+                    type_sizes,
+                )?))
+            })
+    };
+    match definition_items.get(type_name) {
+        Some(DefinitionItem::Struct(s)) => members_align(&s.common),
+        Some(DefinitionItem::Union(u)) => members_align(&u.common),
+        Some(DefinitionItem::ExtensibleStruct(s)) => members_align(&s.common),
+        Some(DefinitionItem::Typedef(t)) => {
+            calculate_type_align(&t.type_name, definition_items, type_sizes)
+        }
+        Some(DefinitionItem::Enum(e)) => {
+            calculate_type_align(&e.type_name, definition_items, type_sizes)
+        }
+        Some(DefinitionItem::Flag(f)) => {
+            calculate_type_align(&f.type_name, definition_items, type_sizes)
+        }
+        // Primitives and handles are scalars, aligned to their size.
+        _ => calculate_type_size(type_name, type_sizes),
+    }
+}
+
+/// Fails when two of `opcodes`, which share an opcode space of `protocol`, have the same value.
+fn check_unique_opcodes<'a>(
+    protocol: &str,
+    opcodes: impl Iterator<Item = &'a Opcode>,
+) -> Result<(), ApiGenError> {
+    let mut seen: HashMap<i128, &Opcode> = HashMap::new();
+    for opcode in opcodes {
+        let value = parse_int_literal(&opcode.value)
+            .ok_or_else(|| ApiGenError::InvalidIntegerValue(opcode.value.clone()))?;
+        if let Some(first) = seen.insert(value, opcode) {
+            return Err(ApiGenError::OpcodeCollision {
+                protocol: protocol.to_string(),
+                value: opcode.value.clone(),
+                first: first.name.clone(),
+                second: opcode.name.clone(),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Share of the opcode range spanned by a protocol's requests they must fill, as a fraction, for
+/// decoders to dispatch through a table indexed by opcode instead of a match.
+const DENSE_OPCODE_RATIO: (u64, u64) = (3, 4);
+
+/// Lays out the request opcodes of `protocol` as a dispatch table if they are dense enough. Fails
+/// for a protocol marked `dense` whose opcodes are not.
+fn dense_opcodes(protocol: &Protocol) -> Result<Option<DenseOpcodes>, ApiGenError> {
+    let mut values = Vec::new();
+    for req in &protocol.requests {
+        let value = parse_int_literal(&req.opcode.value)
+            .and_then(|value| u32::try_from(value).ok())
+            .ok_or_else(|| ApiGenError::InvalidIntegerValue(req.opcode.value.clone()))?;
+        values.push((value, &req.opcode.name));
+    }
+    let (Some(base), Some(last)) = (
+        values.iter().map(|(value, _)| *value).min(),
+        values.iter().map(|(value, _)| *value).max(),
+    ) else {
+        return Ok(None);
+    };
+    let span = u64::from(last - base) + 1;
+    let (filled, of) = DENSE_OPCODE_RATIO;
+    if (values.len() as u64) * of < span * filled {
+        if protocol.dense {
+            return Err(ApiGenError::SparseOpcodes {
+                protocol: protocol.name.clone(),
+                count: values.len(),
+                span,
+            });
+        }
+        return Ok(None);
+    }
+    let mut slots = vec![None; span as usize];
+    for (value, name) in values {
+        slots[(value - base) as usize] = Some(name.clone());
+    }
+    Ok(Some(DenseOpcodes { base, slots }))
+}
+
+/// Fails for bitfield members left unpacked, i.e. outside of structs.
+fn check_unpacked_bitfields(members: &[Member]) -> Result<(), ApiGenError> {
+    match members.iter().find(|member| member.bits.is_some()) {
+        Some(member) => Err(ApiGenError::InvalidBitfield {
+            member: member.name.clone(),
+            reason: "bitfields are only supported in structs".to_string(),
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Fails for platform-specific members outside of structs, whose size is checked on every platform
+/// they name.
+fn check_platform_independent(members: &[Member]) -> Result<(), ApiGenError> {
+    match members.iter().find(|member| member.platform.is_some()) {
+        Some(member) => Err(ApiGenError::InvalidPlatformMember {
+            member: member.name.clone(),
+            reason: "platform-specific members are only supported in structs".to_string(),
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Fails for sensitive members of `owner`, a union or an extensible struct, which have no
+/// redacting output of their own; the member holding one can be marked sensitive instead.
+fn check_not_sensitive(owner: &str, members: &[Member]) -> Result<(), ApiGenError> {
+    match members.iter().find(|member| member.sensitive) {
+        Some(member) => Err(ApiGenError::InvalidAttributeValue {
+            attribute: format!("sensitive of {}.{}", owner, member.name),
+            value: "true".to_string(),
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Adds to `ranges` the offset and length of each sensitive member laid out from `offset`, and of
+/// those of the structs members hold. A struct whose layout depends on the platform is redacted
+/// whole if it holds any.
+fn sensitive_ranges(
+    members: &[Member],
+    mut offset: usize,
+    definition_items: &HashMap<String, DefinitionItem>,
+    type_sizes: &HashMap<String, usize>,
+    ranges: &mut Vec<(usize, usize)>,
+) -> Result<(), ApiGenError> {
+    for member in members {
+        let size = calculate_type_size(&member.type_name, type_sizes)?;
+        let element =
+            split_array_type(&member.type_name).map_or(member.type_name.as_str(), |(base, _)| base);
+        if member.sensitive {
+            ranges.push((offset, size));
+        } else if let Some(DefinitionItem::Struct(s)) = definition_items.get(element) {
+            let mut nested = Vec::new();
+            sensitive_ranges(
+                &s.common.members,
+                0,
+                definition_items,
+                type_sizes,
+                &mut nested,
+            )?;
+            let whole = s.common.members.iter().any(|m| m.platform.is_some());
+            if !nested.is_empty() && s.common.size > 0 {
+                for start in (offset..offset + size).step_by(s.common.size) {
+                    if whole {
+                        ranges.push((start, s.common.size));
+                    } else {
+                        ranges.extend(nested.iter().map(|&(at, len)| (start + at, len)));
+                    }
+                }
+            }
+        }
+        offset += size;
+    }
+    Ok(())
+}
+
+/// Visibility of the Rust type generated for an item; items without a `visibility` attribute are
+/// public.
+fn item_visibility(item: &DefinitionItem) -> Visibility {
+    match item {
+        DefinitionItem::Struct(s) => s.common.visibility,
+        DefinitionItem::Union(u) => u.common.visibility,
+        DefinitionItem::Enum(e) => e.visibility,
+        _ => Visibility::Pub,
+    }
+}
+
+/// Checks that the types of `members`, whose fields and accessors are public, are at least as
+/// visible as their owner.
+fn check_visibility(
+    owner: &str,
+    visibility: Visibility,
+    members: &[Member],
+    definition_items: &HashMap<String, DefinitionItem>,
+) -> Result<(), ApiGenError> {
+    for member in members {
+        let mut type_name = member.type_name.as_str();
+        while let Some((element, _)) = split_array_type(type_name) {
+            type_name = element;
+        }
+        let used = [
+            Some(type_name),
+            member.enum_name.as_deref(),
+            member.flag_name.as_deref(),
+        ];
+        for used in used.into_iter().flatten() {
+            if definition_items
+                .get(used)
+                .is_some_and(|item| item_visibility(item) < visibility)
+            {
+                return Err(ApiGenError::VisibilityLeak {
+                    owner: owner.to_string(),
+                    member: member.name.clone(),
+                    type_name: used.to_string(),
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Fails for dynamic array members outside of requests, responses and events, which take them out
+/// of the fixed-size members.
+fn check_fixed_size(members: &[Member]) -> Result<(), ApiGenError> {
+    match members.iter().find(|member| member.dynamic) {
+        Some(member) => Err(ApiGenError::InvalidDynamicArray {
+            member: member.name.clone(),
+            reason: "dynamic arrays are only supported in requests, responses and events"
+                .to_string(),
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Folds each run of adjacent bitfield members into one storage member of their base type,
+/// named `bitfield{n}`. A run continues while the fields share a base type and fit in it; a
+/// field may start a new storage unit only once the previous one is full, so the layout matches
+/// what C compilers give the header's bitfield declarations.
+fn pack_bitfields(members: Vec<Member>) -> Result<Vec<Member>, ApiGenError> {
+    let mut packed: Vec<Member> = Vec::new();
+    let mut units = 0;
+    // Bits used in the storage member last pushed, while it has room for more fields.
+    let mut used: Option<u32> = None;
+    for member in members {
+        let Some(bits) = member.bits else {
+            packed.push(member);
+            used = None;
+            continue;
+        };
+        let invalid = |reason: String| ApiGenError::InvalidBitfield {
+            member: member.name.clone(),
+            reason,
+        };
+        let width = match member.type_name.as_str() {
+            "u8" => 8,
+            "u16" => 16,
+            "u32" => 32,
+            "u64" => 64,
+            other => {
+                return Err(invalid(format!(
+                    "{} is not an unsigned integer type",
+                    other
+                )))
+            }
+        };
+        if bits == 0 || bits > width {
+            return Err(invalid(format!(
+                "{} bits do not fit in {}",
+                bits, member.type_name
+            )));
+        }
+        if !member.qualifier.is_empty()
+            || member.atomic
+            || member.min.is_some()
+            || member.max.is_some()
+            || member.power_of_two
+            || member.default.is_some()
+        {
+            return Err(invalid(
+                "bitfields cannot have qualifiers, constraints, atomics or defaults".to_string(),
+            ));
+        }
+        if member.sensitive {
+            return Err(invalid("bitfields cannot be sensitive".to_string()));
+        }
+
+        let shift = match (used, packed.last()) {
+            (Some(_), Some(storage)) if storage.type_name != member.type_name => {
+                return Err(invalid(format!(
+                    "adjacent bitfields must share a base type, {} follows {}",
+                    member.type_name, storage.type_name
+                )))
+            }
+            (Some(used), _) if used + bits > width => {
+                return Err(invalid(format!(
+                    "{} bits do not fit in the {} bits left in its {}",
+                    bits,
+                    width - used,
+                    member.type_name
+                )))
+            }
+            (used, _) => used,
+        };
+        let field = Bitfield {
+            name: member.name,
+            bits,
+            shift: shift.unwrap_or(0),
+            mask: format!("{:#x}", u64::MAX >> (64 - bits)),
+            description: member.description,
+        };
+        match (shift, packed.last_mut()) {
+            (Some(_), Some(storage)) => storage.bitfields.push(field),
+            _ => {
+                packed.push(Member {
+                    type_name: member.type_name,
+                    name: format!("bitfield{}", units),
+                    bitfields: vec![field],
+                    ..Default::default()
+                });
+                units += 1;
+            }
+        }
+        used = Some(shift.unwrap_or(0) + bits).filter(|&used| used < width);
+    }
+    Ok(packed)
 }
 
 // Free functions that were causing borrow checker issues as methods.
@@ -27,83 +582,482 @@ fn calculate_member_size(
     members: &[Member],
     type_sizes: &HashMap<String, usize>,
 ) -> Result<usize, ApiGenError> {
-    let mut size = 0;
+    check_unpacked_bitfields(members)?;
+    check_fixed_size(members)?;
+    check_platform_independent(members)?;
+    members.iter().try_fold(0, |size, member| {
+        Ok(size + calculate_type_size(&member.type_name, type_sizes)?)
+    })
+}
+
+/// Size of the members of struct `name`. A struct with platform-specific members has one layout
+/// per platform they name, made of its other members and that platform's own, and all of these
+/// layouts must have the same size.
+fn calculate_layout_size(
+    name: &str,
+    members: &[Member],
+    type_sizes: &HashMap<String, usize>,
+) -> Result<usize, ApiGenError> {
+    let platforms: BTreeSet<Platform> = members.iter().filter_map(|m| m.platform).collect();
+    if platforms.is_empty() {
+        return calculate_member_size(members, type_sizes);
+    }
+    check_unpacked_bitfields(members)?;
+    check_fixed_size(members)?;
+    let mut first: Option<(Platform, usize)> = None;
+    for platform in platforms {
+        let size = members
+            .iter()
+            .filter(|member| member.platform.is_none_or(|p| p == platform))
+            .try_fold(0, |size, member| {
+                Ok::<_, ApiGenError>(size + calculate_type_size(&member.type_name, type_sizes)?)
+            })?;
+        match first {
+            Some((first, first_size)) if first_size != size => {
+                return Err(ApiGenError::PlatformLayoutMismatch {
+                    name: name.to_string(),
+                    first: first.target_os().to_string(),
+                    first_size,
+                    platform: platform.target_os().to_string(),
+                    size,
+                })
+            }
+            Some(_) => {}
+            None => first = Some((platform, size)),
+        }
+    }
+    Ok(first.map_or(0, |(_, size)| size))
+}
+
+/// Range of the values of an integer type, assuming pointer-sized types are 64 bits wide.
+fn integer_range(type_name: &str) -> Option<(i128, i128)> {
+    Some(match type_name {
+        "u8" => (0, u8::MAX.into()),
+        "i8" => (i8::MIN.into(), i8::MAX.into()),
+        "u16" => (0, u16::MAX.into()),
+        "i16" => (i16::MIN.into(), i16::MAX.into()),
+        "u32" => (0, u32::MAX.into()),
+        "i32" => (i32::MIN.into(), i32::MAX.into()),
+        "u64" | "usize" => (0, u64::MAX.into()),
+        "i64" | "isize" => (i64::MIN.into(), i64::MAX.into()),
+        _ => return None,
+    })
+}
+
+/// Fails for `min` and `max` constraints outside the range of their member's type, and drops
+/// those at the ends of that range: they always hold, and comparing against them would trip
+/// `unused_comparisons` in the generated checks.
+fn check_constraint_ranges(members: &mut [Member]) -> Result<(), ApiGenError> {
     for member in members {
-        if let Some(s) = type_sizes.get(&member.type_name) {
-            size += s;
-        } else if member.type_name.starts_with('[') {
-            let re = Regex::new(r"\[([^;]+);\s*([^\]]+)\]").unwrap();
-            let caps = re
-                .captures(&member.type_name)
-                .ok_or_else(|| ApiGenError::InvalidArrayTypeFormat(member.type_name.clone()))?;
-            let base_type = caps.get(1).unwrap().as_str();
-            let count_name = caps.get(2).unwrap().as_str();
-
-            let base_type_size = type_sizes
-                .get(base_type)
-                .ok_or_else(|| ApiGenError::TypeNotFound(base_type.to_string()))?;
-
-            let count = type_sizes
-                .get(count_name)
-                .ok_or_else(|| ApiGenError::ConstantNotFound(count_name.to_string()))?;
-            size += base_type_size * count;
+        let Some((type_min, type_max)) = integer_range(&member.type_name) else {
+            continue;
+        };
+        for (bound, is_min) in [(&mut member.min, true), (&mut member.max, false)] {
+            let Some(value) = bound.as_deref().and_then(parse_int_literal) else {
+                continue;
+            };
+            if value < type_min || value > type_max {
+                return Err(ApiGenError::InvalidConstraint {
+                    member: member.name.clone(),
+                    reason: format!(
+                        "{} {} is outside the range of {}",
+                        if is_min { "min" } else { "max" },
+                        value,
+                        member.type_name
+                    ),
+                });
+            }
+            if value == if is_min { type_min } else { type_max } {
+                *bound = None;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Fills in the object each `handle` member of `owner` refers to, which must already be declared.
+fn resolve_handles(
+    owner: &str,
+    members: &mut [Member],
+    definition_items: &HashMap<String, DefinitionItem>,
+) -> Result<(), ApiGenError> {
+    for member in members {
+        let Some(handle) = &mut member.handle else {
+            continue;
+        };
+        match definition_items.get(&handle.name) {
+            Some(DefinitionItem::Object(object)) => *handle = object.clone(),
+            _ => {
+                return Err(ApiGenError::UnknownObject {
+                    member: format!("{}.{}", owner, member.name),
+                    object: handle.name.clone(),
+                })
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Checks the `<default>` of each member against its type and turns it into a Rust expression:
+/// integer and `true`/`false` literals are kept, and an entry of the member's enum becomes
+/// `Enum::ENTRY`.
+fn resolve_defaults(
+    owner: &str,
+    members: &mut [Member],
+    definition_items: &HashMap<String, DefinitionItem>,
+) -> Result<(), ApiGenError> {
+    for member in members {
+        let Some(value) = member.default.take() else {
+            continue;
+        };
+        let resolved = match (
+            member.type_name.as_str(),
+            definition_items.get(&member.type_name),
+        ) {
+            ("bool", _) if value == "true" || value == "false" => Some(value.clone()),
+            (
+                "u8" | "u16" | "u32" | "u64" | "usize" | "i8" | "i16" | "i32" | "i64" | "isize",
+                _,
+            ) => parse_int_literal(&value).map(|_| value.trim().to_string()),
+            (_, Some(DefinitionItem::Enum(e))) => e
+                .entries
+                .iter()
+                .find(|entry| entry.name == value.trim())
+                .map(|entry| format!("{}::{}", e.name, entry.name)),
+            _ => None,
+        };
+        member.default = Some(resolved.ok_or_else(|| ApiGenError::InvalidAttributeValue {
+            attribute: format!("default of {}.{}", owner, member.name),
+            value,
+        })?);
+    }
+    Ok(())
+}
+
+/// Replaces entry values written as expressions, such as `1 << 3` or `READ | WRITE`, by their
+/// value. Expressions may name earlier entries of the same enum or flag. Flag values are written
+/// in hex, enum values in decimal.
+fn resolve_entries(entries: &mut [EnumEntry], hex: bool) -> Result<(), ApiGenError> {
+    for index in 0..entries.len() {
+        if parse_int_literal(&entries[index].value).is_some() {
+            continue;
+        }
+        let (earlier, rest) = entries.split_at_mut(index);
+        let entry = &mut rest[0];
+        let lookup = |name: &str| {
+            earlier
+                .iter()
+                .find(|e| e.name == name)
+                .and_then(|e| parse_int_literal(&e.value))
+        };
+        let value = eval_int_expr(&entry.value, &lookup).ok_or_else(|| {
+            ApiGenError::InvalidConstantValue {
+                name: entry.name.clone(),
+                value: entry.value.clone(),
+            }
+        })?;
+        entry.value = if hex && value >= 0 {
+            format!("{:#x}", value)
         } else {
-            return Err(ApiGenError::TypeNotFound(member.type_name.clone()));
+            value.to_string()
+        };
+    }
+    Ok(())
+}
+
+/// Rewrites the values of enum or flag entries in the API's literal format.
+fn format_entries(entries: &mut [EnumEntry], format: LiteralFormat) -> Result<(), ApiGenError> {
+    for entry in entries {
+        let value = parse_int_literal(&entry.value)
+            .ok_or_else(|| ApiGenError::InvalidIntegerValue(entry.value.clone()))?;
+        entry.value = format.format(value);
+    }
+    Ok(())
+}
+
+fn calculate_padding(size: usize, align: Option<usize>) -> Option<Member> {
+    let align = align.unwrap_or(NUM_BYTES_IN_U64);
+    let padding = (align - (size % align)) % align;
+    let type_name = if padding == 0 {
+        return None;
+    } else if padding == NUM_BYTES_IN_U32 {
+        "u32".to_string()
+    } else if padding.is_multiple_of(NUM_BYTES_IN_U64) {
+        format!("[u64; {}]", padding / NUM_BYTES_IN_U64)
+    } else {
+        format!("[u8; {}]", padding)
+    };
+    Some(Member {
+        type_name,
+        qualifier: String::new(),
+        name: "padding".to_string(),
+        ..Default::default()
+    })
+}
+
+/// Rounds `size` up to the declared alignment of a struct, if any.
+fn aligned_size(name: &str, align: Option<usize>, size: usize) -> Result<usize, ApiGenError> {
+    match align {
+        Some(align) if !align.is_power_of_two() => Err(ApiGenError::InvalidAlignment {
+            name: name.to_string(),
+            align,
+        }),
+        Some(align) => Ok(size.next_multiple_of(align)),
+        None => Ok(size),
+    }
+}
+
+/// Normalizes an integer literal, since literal formats only change how values are written.
+fn literal_layout(value: &str) -> String {
+    parse_int_literal(value).map_or(value.to_string(), |v| v.to_string())
+}
+
+/// Describes the layout of a member type: primitives and handles by name, arrays by their length
+/// and element, and types of the API by their own layout, so that a change nested in a member
+/// changes the layout of its holder as well. Typedefs stand for their underlying type.
+fn type_layout(type_name: &str, items: &HashMap<String, DefinitionItem>) -> String {
+    if let Some((element, len)) = split_array_type(type_name) {
+        let len = match items.get(len) {
+            Some(DefinitionItem::Constant(c)) => literal_layout(&c.value),
+            _ => len.to_string(),
+        };
+        return format!("[{}; {}]", type_layout(element, items), len);
+    }
+    match items.get(type_name) {
+        Some(DefinitionItem::Typedef(t)) => type_layout(&t.type_name, items),
+        Some(item) => layout_description(item, items)
+            .map_or_else(|| type_name.to_string(), |layout| format!("{{{}}}", layout)),
+        None => type_name.to_string(),
+    }
+}
+
+/// Describes what makes up the layout of an item: its size and alignment and the qualifiers,
+/// types and bits of its members and bitfields, or the values of its entries. Names are left out
+/// along with descriptions and comments: neither changes a byte on the wire, so members, entries
+/// and the types of members can be renamed and documented. Items without a layout of their own
+/// return `None`.
+fn layout_description(
+    item: &DefinitionItem,
+    items: &HashMap<String, DefinitionItem>,
+) -> Option<String> {
+    let members = |common: &StructCommon| {
+        let mut layout = format!("size {} align {:?}", common.size, common.align);
+        for m in &common.members {
+            let member_type = type_layout(&m.type_name, items);
+            layout += &format!(";{} {} {:?}", m.qualifier, member_type, m.bits);
+            for b in &m.bitfields {
+                layout += &format!(",{} {}", b.bits, b.shift);
+            }
+        }
+        layout
+    };
+    let entries = |type_name: &str, entries: &[EnumEntry]| {
+        let mut layout = type_name.to_string();
+        for e in entries {
+            layout += &format!(";{}", literal_layout(&e.value));
+        }
+        layout
+    };
+    Some(match item {
+        DefinitionItem::Struct(s) => format!("struct {}", members(&s.common)),
+        DefinitionItem::Union(u) => format!("union {}", members(&u.common)),
+        DefinitionItem::ExtensibleStruct(s) => {
+            format!("extensible {} {}", s.stype.name, members(&s.common))
         }
+        DefinitionItem::Enum(e) => format!("enum {}", entries(&e.type_name, &e.entries)),
+        DefinitionItem::Flag(f) => format!("flag {}", entries(&f.type_name, &f.entries)),
+        _ => return None,
+    })
+}
+
+/// Returns whether an item is declared frozen, and the hash of its layout description. Items
+/// without a layout of their own return `None`.
+fn layout_fingerprint(
+    item: &DefinitionItem,
+    items: &HashMap<String, DefinitionItem>,
+) -> Option<(bool, u32)> {
+    let frozen = match item {
+        DefinitionItem::Struct(s) => s.common.frozen,
+        DefinitionItem::Union(u) => u.common.frozen,
+        DefinitionItem::ExtensibleStruct(s) => s.common.frozen,
+        DefinitionItem::Enum(e) => e.frozen,
+        DefinitionItem::Flag(f) => f.frozen,
+        _ => return None,
+    };
+    Some((frozen, fnv1a_32(&layout_description(item, items)?)))
+}
+
+/// Opcodes of one opcode space of a protocol: its events, or its requests and responses.
+fn opcode_space(protocol: &Protocol, events: bool) -> Vec<&Opcode> {
+    if events {
+        protocol.events.iter().map(|e| &e.opcode).collect()
+    } else {
+        protocol
+            .requests
+            .iter()
+            .map(|r| &r.opcode)
+            .chain(protocol.responses.iter().map(|r| &r.opcode))
+            .collect()
     }
-    Ok(size)
 }
 
-fn calculate_padding(size: usize) -> Option<Member> {
-    let padding = (NUM_BYTES_IN_U64 - (size % NUM_BYTES_IN_U64)) % NUM_BYTES_IN_U64;
-    if padding == NUM_BYTES_IN_U32 {
-        Some(Member {
-            type_name: format!("u32"),
-            qualifier: String::new(),
-            name: "padding".to_string(),
-        })
-    } else if padding > 0 {
-        Some(Member {
-            type_name: format!("[u8; {}]", padding),
-            qualifier: String::new(),
-            name: "padding".to_string(),
-        })
+/// Rejects opcode values that do not fit in the u32 opcode field of the wire format.
+fn check_opcode_range(protocol: &Protocol) -> Result<(), ApiGenError> {
+    for opcode in opcode_space(protocol, false)
+        .into_iter()
+        .chain(opcode_space(protocol, true))
+    {
+        let value = parse_int_literal(&opcode.value)
+            .ok_or_else(|| ApiGenError::InvalidIntegerValue(opcode.value.clone()))?;
+        if u32::try_from(value).is_err() {
+            return Err(ApiGenError::OpcodeOutOfRange {
+                protocol: protocol.name.clone(),
+                opcode: opcode.name.clone(),
+                value: opcode.value.clone(),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Key of an auto-assigned opcode in the opcode lock.
+fn opcode_lock_key(protocol: &str, opcode: &str, events: bool) -> String {
+    if events {
+        format!("{}::events::{}", protocol, opcode)
     } else {
-        None
+        format!("{}::{}", protocol, opcode)
     }
 }
 
+/// Fills in opcodes declared without a value from the lock, or with the value following the
+/// highest one in use in their opcode space, skipping the protocol's reserved ranges. Values
+/// recorded in the lock count as in use even once their opcode is gone, so they are not reused.
+fn assign_opcode_values(
+    definition_items: &HashMap<String, DefinitionItem>,
+    opcode_lock: &mut BTreeMap<String, u32>,
+    protocol: &mut Protocol,
+) -> Result<(), ApiGenError> {
+    let mut reserved = Vec::new();
+    for range in &protocol.reserved {
+        let from = parse_int_literal(&range.from)
+            .ok_or_else(|| ApiGenError::InvalidIntegerValue(range.from.clone()))?;
+        let to = parse_int_literal(&range.to)
+            .ok_or_else(|| ApiGenError::InvalidIntegerValue(range.to.clone()))?;
+        reserved.push(from..=to);
+    }
+    // An extension shares the opcode spaces of its base and of the base's other extensions.
+    let related: Vec<&Protocol> = definition_items
+        .values()
+        .filter_map(|item| match item {
+            DefinitionItem::Protocol(p)
+                if protocol.extends.is_some()
+                    && (protocol.extends.as_ref() == Some(&p.name)
+                        || p.extends == protocol.extends) =>
+            {
+                Some(p)
+            }
+            _ => None,
+        })
+        .collect();
+
+    for events in [false, true] {
+        let mut used: HashSet<i128> = HashSet::new();
+        for p in related.iter().copied().chain(std::iter::once(&*protocol)) {
+            for opcode in opcode_space(p, events) {
+                if opcode.value.is_empty() {
+                    continue;
+                }
+                used.insert(
+                    parse_int_literal(&opcode.value)
+                        .ok_or_else(|| ApiGenError::InvalidIntegerValue(opcode.value.clone()))?,
+                );
+            }
+            let prefix = format!("{}::", p.name);
+            for (key, value) in opcode_lock.iter() {
+                if let Some(rest) = key.strip_prefix(&prefix) {
+                    if rest.starts_with("events::") == events {
+                        used.insert(*value as i128);
+                    }
+                }
+            }
+        }
+
+        let opcodes: Vec<&mut Opcode> = if events {
+            protocol.events.iter_mut().map(|e| &mut e.opcode).collect()
+        } else {
+            protocol
+                .requests
+                .iter_mut()
+                .map(|r| &mut r.opcode)
+                .chain(protocol.responses.iter_mut().map(|r| &mut r.opcode))
+                .collect()
+        };
+        for opcode in opcodes.into_iter().filter(|o| o.value.is_empty()) {
+            let key = opcode_lock_key(&protocol.name, &opcode.name, events);
+            let value = match opcode_lock.get(&key) {
+                Some(value) => *value,
+                None => {
+                    let mut value = used.iter().max().map_or(1, |max| max + 1);
+                    while let Some(range) = reserved.iter().find(|r| r.contains(&value)) {
+                        value = range.end() + 1;
+                    }
+                    let value = u32::try_from(value)
+                        .map_err(|_| ApiGenError::InvalidIntegerValue(value.to_string()))?;
+                    opcode_lock.insert(key, value);
+                    value
+                }
+            };
+            used.insert(value as i128);
+            opcode.value = format!("{:#x}", value);
+        }
+    }
+    Ok(())
+}
+
+/// Rejects any of the named values that falls inside one of the reserved ranges.
+fn check_reserved<'a>(
+    reserved: &[ReservedRange],
+    items: impl Iterator<Item = (&'a String, &'a String)>,
+) -> Result<(), ApiGenError> {
+    let mut ranges = Vec::new();
+    for range in reserved {
+        let from = parse_int_literal(&range.from)
+            .ok_or_else(|| ApiGenError::InvalidIntegerValue(range.from.clone()))?;
+        let to = parse_int_literal(&range.to)
+            .ok_or_else(|| ApiGenError::InvalidIntegerValue(range.to.clone()))?;
+        ranges.push((from..=to, range));
+    }
+    for (name, value) in items {
+        let Some(parsed) = parse_int_literal(value) else {
+            continue;
+        };
+        if let Some((_, range)) = ranges.iter().find(|(r, _)| r.contains(&parsed)) {
+            return Err(ApiGenError::ReservedValue {
+                item: name.clone(),
+                value: value.clone(),
+                from: range.from.clone(),
+                to: range.to.clone(),
+                reason: range.reason.clone(),
+            });
+        }
+    }
+    Ok(())
+}
+
 impl Api {
     pub fn new() -> Self {
-        let type_sizes: HashMap<String, usize> = HashMap::from([
-            ("u8".to_string(), 1),
-            ("i8".to_string(), 1),
-            ("u16".to_string(), 2),
-            ("i16".to_string(), 2),
-            ("i32".to_string(), 4),
-            ("u32".to_string(), 4),
-            ("u64".to_string(), 8),
-            ("i64".to_string(), 8),
-            ("f64".to_string(), 8),
-            ("usize".to_string(), 8), // Assuming 64-bit target
-            ("*mut std::ffi::c_void".to_string(), 8),
-        ]);
-        let rust_to_c_typemap: HashMap<String, String> = HashMap::from([
-            ("u8".to_string(), "uint8_t".to_string()),
-            ("i8".to_string(), "int8_t".to_string()),
-            ("u16".to_string(), "uint16_t".to_string()),
-            ("i16".to_string(), "int16_t".to_string()),
-            ("i32".to_string(), "int32_t".to_string()),
-            ("u32".to_string(), "uint32_t".to_string()),
-            ("u64".to_string(), "uint64_t".to_string()),
-            ("i64".to_string(), "int64_t".to_string()),
-            ("f64".to_string(), "double".to_string()),
-            ("usize".to_string(), "size_t".to_string()),
-            ("*mut std::ffi::c_void".to_string(), "void*".to_string()),
-        ]);
+        let type_sizes = PRIMITIVE_TYPES
+            .iter()
+            .map(|t| (t.rust.to_string(), t.size))
+            .collect();
+        let rust_to_c_typemap = PRIMITIVE_TYPES
+            .iter()
+            .map(|t| (t.rust.to_string(), t.c.to_string()))
+            .collect();
         Api {
             type_sizes,
             rust_to_c_typemap,
+            count_patterns: vec!["{singular}_count".to_string()],
             ..Default::default()
         }
     }
@@ -121,6 +1075,21 @@ impl Api {
         self.version
     }
 
+    /// Returns true when any item of the API is deprecated, or has deprecated requests or members.
+    pub fn has_deprecations(&self) -> bool {
+        self.definition_items
+            .values()
+            .any(DefinitionItem::has_deprecations)
+    }
+
+    pub fn strict_units(&self) -> bool {
+        self.strict_units
+    }
+
+    pub fn preserve_comments(&self) -> bool {
+        self.preserve_comments
+    }
+
     pub fn definitions(&self) -> &HashMap<String, Definition> {
         &self.definitions
     }
@@ -129,10 +1098,52 @@ impl Api {
         &self.definition_items
     }
 
+    /// Returns the wire size of a type, including arrays of known types.
+    pub fn type_size(&self, type_name: &str) -> Result<usize, ApiGenError> {
+        calculate_type_size(type_name, &self.type_sizes)
+    }
+
+    pub fn rust_to_c_typemap(&self) -> &HashMap<String, String> {
+        &self.rust_to_c_typemap
+    }
+
     pub fn generated_files(&self) -> &[GeneratedFile] {
         &self.generated_files
     }
 
+    pub fn stype_lock(&self) -> &BTreeMap<String, u32> {
+        &self.stype_lock
+    }
+
+    pub fn frozen_lock(&self) -> &BTreeMap<String, u32> {
+        &self.frozen_lock
+    }
+
+    pub fn opcode_lock(&self) -> &BTreeMap<String, u32> {
+        &self.opcode_lock
+    }
+
+    /// Returns the API version introducing an item, 0 if it was always there.
+    pub fn item_since(&self, item_name: &str) -> u32 {
+        self.item_since.get(item_name).copied().unwrap_or_default()
+    }
+
+    pub fn post_process(&self) -> Option<&str> {
+        self.post_process.as_deref()
+    }
+
+    pub fn template_dir(&self) -> Option<&Path> {
+        self.template_dir.as_deref()
+    }
+
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+
+    pub fn external_types(&self) -> &[ExternalType] {
+        &self.external_types
+    }
+
     // Setters/mutators for parser
     pub fn set_name(&mut self, name: String) {
         self.name = name;
@@ -146,16 +1157,377 @@ impl Api {
         self.version = version;
     }
 
+    pub fn set_strict_units(&mut self, strict_units: bool) {
+        self.strict_units = strict_units;
+    }
+
+    pub fn set_preserve_comments(&mut self, preserve_comments: bool) {
+        self.preserve_comments = preserve_comments;
+    }
+
+    pub fn set_literal_formats(
+        &mut self,
+        constants: Option<LiteralFormat>,
+        enums: Option<LiteralFormat>,
+        flags: Option<LiteralFormat>,
+    ) {
+        self.constant_literals = constants;
+        self.enum_literals = enums;
+        self.flag_literals = flags;
+    }
+
+    pub fn set_stype_lock(&mut self, stype_lock: BTreeMap<String, u32>) {
+        self.stype_lock = stype_lock;
+    }
+
+    pub fn set_frozen_lock(&mut self, frozen_lock: BTreeMap<String, u32>) {
+        self.frozen_lock = frozen_lock;
+    }
+
+    pub fn set_opcode_lock(&mut self, opcode_lock: BTreeMap<String, u32>) {
+        self.opcode_lock = opcode_lock;
+    }
+
+    pub fn set_item_since(&mut self, item_name: &str, since: u32) {
+        self.item_since.insert(item_name.to_string(), since);
+    }
+
+    /// Rejects layout changes to items recorded in the frozen lock, whether or not they are still
+    /// declared frozen, and records the items newly declared frozen.
+    pub fn check_frozen(&mut self) -> Result<(), ApiGenError> {
+        for (name, item) in &self.definition_items {
+            let Some((frozen, fingerprint)) = layout_fingerprint(item, &self.definition_items)
+            else {
+                continue;
+            };
+            match self.frozen_lock.get(name) {
+                Some(&recorded) if recorded != fingerprint => {
+                    return Err(ApiGenError::FrozenLayoutChanged(name.clone()));
+                }
+                None if frozen => {
+                    self.frozen_lock.insert(name.clone(), fingerprint);
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    pub fn set_post_process(&mut self, command: Option<String>) {
+        self.post_process = command;
+    }
+
+    pub fn set_template_dir(&mut self, dir: Option<PathBuf>) {
+        self.template_dir = dir;
+    }
+
+    /// Puts every C header of the API in a C++ namespace, overriding their `<namespace>`.
+    pub fn set_header_namespace(&mut self, namespace: &str) {
+        for file in &mut self.generated_files {
+            if file.file_type == "header" {
+                file.namespace = Some(namespace.to_string());
+            }
+        }
+    }
+
+    pub fn set_count_patterns(&mut self, patterns: Vec<String>) {
+        self.count_patterns = patterns;
+    }
+
+    /// Warns about every member named after a Rust, C or C++ keyword, with the escaped names the
+    /// generated code uses instead.
+    pub fn check_keywords(&mut self) {
+        let mut member_lists: Vec<(&str, &[Member])> = Vec::new();
+        for item in self.definition_items.values() {
+            match item {
+                DefinitionItem::Struct(s) => {
+                    member_lists.push((s.common.name.as_str(), &s.common.members[..]))
+                }
+                DefinitionItem::Union(u) => {
+                    member_lists.push((u.common.name.as_str(), &u.common.members[..]))
+                }
+                DefinitionItem::ExtensibleStructs(c) => member_lists.extend(
+                    c.structs
+                        .iter()
+                        .map(|s| (s.common.name.as_str(), &s.common.members[..])),
+                ),
+                DefinitionItem::Function(f) => member_lists.push((f.name.as_str(), &f.members[..])),
+                DefinitionItem::Protocol(p) => member_lists.extend(
+                    p.requests
+                        .iter()
+                        .map(|r| (r.opcode.name.as_str(), &r.members[..]))
+                        .chain(
+                            p.responses
+                                .iter()
+                                .map(|r| (r.opcode.name.as_str(), &r.members[..])),
+                        )
+                        .chain(
+                            p.events
+                                .iter()
+                                .map(|e| (e.opcode.name.as_str(), &e.members[..])),
+                        ),
+                ),
+                _ => {}
+            }
+        }
+        let mut warnings: Vec<String> = member_lists
+            .into_iter()
+            .flat_map(|(owner, members)| members.iter().map(move |member| (owner, member)))
+            .filter_map(|(owner, member)| {
+                let escapes: Vec<String> = [
+                    ("Rust", escape_rust_keyword(&member.name)),
+                    ("C", escape_c_keyword(&member.name)),
+                ]
+                .into_iter()
+                .filter(|(_, escaped)| *escaped != member.name)
+                .map(|(language, escaped)| format!("`{}` in {}", escaped, language))
+                .collect();
+                (!escapes.is_empty()).then(|| {
+                    format!(
+                        "member {}.{} is a keyword and is generated as {}",
+                        owner,
+                        member.name,
+                        escapes.join(" and ")
+                    )
+                })
+            })
+            .collect();
+        warnings.sort();
+        self.warnings.extend(warnings);
+    }
+
+    pub fn set_naming(&mut self, file_type: String, naming: Naming) {
+        self.namings.insert(file_type, naming);
+    }
+
+    /// Returns the API as files of `file_type` name it, or `None` if the file type keeps the names
+    /// of the description. Structs, unions, enums, flags and typedefs are renamed along with their
+    /// uses, and so are constants.
+    pub fn named_for(&self, file_type: &str) -> Result<Option<Api>, ApiGenError> {
+        let Some(naming) = self.namings.get(file_type) else {
+            return Ok(None);
+        };
+        let mut names = HashMap::new();
+        for (name, item) in &self.definition_items {
+            let new_name = match item {
+                DefinitionItem::Struct(_)
+                | DefinitionItem::Union(_)
+                | DefinitionItem::Enum(_)
+                | DefinitionItem::Flag(_)
+                | DefinitionItem::Typedef(_) => naming.type_name(name),
+                DefinitionItem::Constant(_) => naming.constant_name(name),
+                _ => continue,
+            };
+            if new_name == *name {
+                continue;
+            }
+            // Members may use the raw wire type of an enum accepting unknown values.
+            if let DefinitionItem::Enum(e) = item {
+                if e.unknown_values == UnknownValues::Raw {
+                    names.insert(format!("Raw{}", name), format!("Raw{}", new_name));
+                }
+            }
+            names.insert(name.clone(), new_name);
+        }
+        let mut taken: HashMap<&String, &String> = HashMap::new();
+        for name in self.definition_items.keys().collect::<BTreeSet<_>>() {
+            let new_name = names.get(name).unwrap_or(name);
+            if let Some(first) = taken.insert(new_name, name) {
+                return Err(ApiGenError::NameCollision {
+                    file_type: file_type.to_string(),
+                    name: new_name.clone(),
+                    first: first.clone(),
+                    second: name.clone(),
+                });
+            }
+        }
+
+        let rekey = |name: &String| names.get(name).unwrap_or(name).clone();
+        let mut api = self.clone();
+        api.definition_items = self
+            .definition_items
+            .iter()
+            .map(|(name, item)| {
+                let mut item = item.clone();
+                rename_item(&mut item, &names);
+                (rekey(name), item)
+            })
+            .collect();
+        for definition in api.definitions.values_mut() {
+            for item in &mut definition.items {
+                *item = rekey(item);
+            }
+        }
+        api.type_sizes = self
+            .type_sizes
+            .iter()
+            .map(|(name, &size)| (rekey(name), size))
+            .collect();
+        api.item_since = self
+            .item_since
+            .iter()
+            .map(|(name, &since)| (rekey(name), since))
+            .collect();
+        Ok(Some(api))
+    }
+
     pub fn add_generated_file(&mut self, file: GeneratedFile) {
         self.generated_files.push(file);
     }
 
+    /// Names of the items a generated file instantiates, in declaration order.
+    pub fn instantiated_item_names<'a>(
+        &'a self,
+        file: &'a GeneratedFile,
+    ) -> impl Iterator<Item = &'a String> {
+        file.instantiations
+            .iter()
+            .filter_map(|def_name| self.definitions.get(def_name))
+            .flat_map(|def| def.items.iter())
+    }
+
+    /// Returns true if every file type generates the item the same way. Structs and unions are
+    /// not: Rust files declare them as builders with a lifetime, protocol files as wire types.
+    fn is_shared_across_file_types(&self, item_name: &str) -> bool {
+        match self.definition_items.get(item_name) {
+            Some(
+                DefinitionItem::Struct(_)
+                | DefinitionItem::Union(_)
+                | DefinitionItem::ExtensibleStruct(_)
+                | DefinitionItem::ExtensibleStructs(_),
+            ) => false,
+            Some(DefinitionItem::Typedef(t)) => {
+                let base = split_array_type(&t.type_name).map_or(t.type_name.as_str(), |(b, _)| b);
+                self.is_shared_across_file_types(base)
+            }
+            _ => true,
+        }
+    }
+
+    /// Returns the items instantiated by the files `file` includes, which it uses from there.
+    /// Items of included files of another file type are only used if generated the same way.
+    fn included_item_names<'a>(&'a self, file: &'a GeneratedFile) -> HashSet<&'a str> {
+        self.generated_files
+            .iter()
+            .filter(|included| {
+                let path = format!("{}/{}", included.out_path, included.file_name);
+                file.includes
+                    .iter()
+                    .any(|include| *include == included.file_name || *include == path)
+            })
+            .flat_map(|included| {
+                self.instantiated_item_names(included)
+                    .map(String::as_str)
+                    .filter(move |name| {
+                        included.file_type == file.file_type
+                            || self.is_shared_across_file_types(name)
+                    })
+            })
+            .collect()
+    }
+
+    /// Assigns every type to the first generated file of each type that instantiates it, and
+    /// records that file as a reference of the files of the same type using it without
+    /// instantiating it or including a file that does. Fails on a used type that neither an
+    /// included file nor a file of that type instantiates.
+    pub fn link_generated_files(&mut self) -> Result<(), ApiGenError> {
+        let mut homes: HashMap<(&str, &str), String> = HashMap::new();
+        for file in &self.generated_files {
+            let path = format!("{}/{}", file.out_path, file.file_name);
+            for item_name in self.instantiated_item_names(file) {
+                homes
+                    .entry((file.file_type.as_str(), item_name.as_str()))
+                    .or_insert_with(|| path.clone());
+            }
+        }
+
+        let mut all_references = Vec::new();
+        for file in &self.generated_files {
+            let mut references: Vec<String> = Vec::new();
+            if LINKED_FILE_TYPES.contains(&file.file_type.as_str()) {
+                let own: HashSet<&str> = self
+                    .instantiated_item_names(file)
+                    .map(String::as_str)
+                    .collect();
+                let included = self.included_item_names(file);
+                for item_name in &own {
+                    let Some(item) = self.definition_items.get(*item_name) else {
+                        continue;
+                    };
+                    for name in item.referenced_names() {
+                        if !self.definition_items.contains_key(name)
+                            || own.contains(name)
+                            || included.contains(name)
+                        {
+                            continue;
+                        }
+                        let home =
+                            homes.get(&(file.file_type.as_str(), name)).ok_or_else(|| {
+                                ApiGenError::UninstantiatedType {
+                                    type_name: name.to_string(),
+                                    file: file.file_name.clone(),
+                                    file_type: file.file_type.clone(),
+                                }
+                            })?;
+                        if !references.contains(home) {
+                            references.push(home.clone());
+                        }
+                    }
+                }
+                references.sort();
+            }
+            all_references.push(references);
+        }
+
+        for (file, references) in self.generated_files.iter_mut().zip(all_references) {
+            file.references = references;
+        }
+        Ok(())
+    }
+
+    /// Fails when two protocols generated into the same file, or into files including each other,
+    /// name a command struct the same. Protocols that never share a file may reuse names.
+    pub fn check_command_types(&self) -> Result<(), ApiGenError> {
+        for file in &self.generated_files {
+            if !COMMAND_TYPE_FILE_TYPES.contains(&file.file_type.as_str()) {
+                continue;
+            }
+            let mut protocols: Vec<&Protocol> = Vec::new();
+            let names = self
+                .instantiated_item_names(file)
+                .map(String::as_str)
+                .chain(self.included_item_names(file));
+            for name in names {
+                if let Some(DefinitionItem::Protocol(p)) = self.definition_items.get(name) {
+                    if !protocols.iter().any(|known| known.name == p.name) {
+                        protocols.push(p);
+                    }
+                }
+            }
+            let mut seen: HashMap<String, &Protocol> = HashMap::new();
+            for protocol in protocols {
+                for name in protocol.command_type_names() {
+                    if let Some(first) = seen.get(&name) {
+                        return Err(ApiGenError::CommandTypeCollision {
+                            name,
+                            file: file.file_name.clone(),
+                            first: first.name.clone(),
+                            second: protocol.name.clone(),
+                        });
+                    }
+                    seen.insert(name, protocol);
+                }
+            }
+        }
+        Ok(())
+    }
+
     pub fn add_definition(&mut self, definition: Definition) {
         self.definitions.insert(definition.name.clone(), definition);
     }
 
     // Methods with logic moved from parser
-    pub fn add_constant(&mut self, constant: Constant) -> Result<(), ApiGenError> {
+    pub fn add_constant(&mut self, mut constant: Constant) -> Result<(), ApiGenError> {
         let item_name = constant.name.clone();
         let value =
             constant
@@ -165,61 +1537,240 @@ impl Api {
                     name: constant.name.clone(),
                     value: constant.value.clone(),
                 })?;
+        if let Some(format) = self.constant_literals {
+            constant.value = format.format(value as i128);
+        }
         self.type_sizes.insert(item_name.clone(), value);
         self.definition_items
             .insert(item_name.clone(), DefinitionItem::Constant(constant));
         Ok(())
     }
 
+    /// Registers a type of the `<typemap>` with its size and C spelling, failing if the name is
+    /// already taken by a built-in or earlier type.
+    pub fn add_external_type(&mut self, external: ExternalType) -> Result<(), ApiGenError> {
+        if self.type_sizes.contains_key(&external.name) {
+            return Err(ApiGenError::DuplicateType(external.name));
+        }
+        self.type_sizes.insert(external.name.clone(), external.size);
+        self.rust_to_c_typemap
+            .insert(external.name.clone(), external.c.clone());
+        self.external_types.push(external);
+        Ok(())
+    }
+
+    pub fn add_typedef(&mut self, typedef: Typedef) -> Result<(), ApiGenError> {
+        let size = self.type_size(&typedef.type_name)?;
+        self.type_sizes.insert(typedef.name.clone(), size);
+        self.definition_items
+            .insert(typedef.name.clone(), DefinitionItem::Typedef(typedef));
+        Ok(())
+    }
+
     pub fn add_struct(&mut self, mut struct_def: StructDef) -> Result<(), ApiGenError> {
+        struct_def.common.members = pack_bitfields(std::mem::take(&mut struct_def.common.members))?;
+
         // Post-process to find array and count members.
-        let re = Regex::new(r"\[([^;]+);\s*([^\]]+)\]").unwrap();
+        let struct_name = struct_def.common.name.clone();
+        // Counts must exist on every platform.
+        let has_member = |name: &str| {
+            struct_def
+                .common
+                .members
+                .iter()
+                .any(|m| m.name == name && m.platform.is_none())
+        };
+        let mut array_info = Vec::new();
         for member in &struct_def.common.members {
-            if let Some(caps) = re.captures(&member.type_name) {
-                let base_type = caps.get(1).unwrap().as_str().to_string();
-                let array_member_name = member.name.clone();
-
-                // Find the corresponding count member. Convention is singular name + "Count".
-                let singular_name = array_member_name
-                    .strip_suffix('s')
-                    .unwrap_or(&array_member_name);
-                let count_member_name = format!("{}_count", singular_name);
-
-                if struct_def
-                    .common
-                    .members
-                    .iter()
-                    .any(|m| m.name == count_member_name)
-                {
-                    struct_def.common.array_info.push(ArrayInfo {
-                        array_member_name,
-                        array_base_type: base_type,
-                        count_member_name,
-                    });
-                }
+            let Some((base_type, _)) = split_array_type(&member.type_name) else {
+                continue;
+            };
+            // Strings are NUL-padded rather than counted, and platform-specific arrays are plain
+            // fields.
+            if member.max_len.is_some() || member.platform.is_some() {
+                continue;
             }
+
+            // An explicit `count` attribute wins over the API's naming patterns.
+            let count_member_name = match member.count.as_deref() {
+                Some("none") => continue,
+                Some(count) if has_member(count) => count.to_string(),
+                Some(count) => {
+                    return Err(ApiGenError::InvalidAttributeValue {
+                        attribute: format!("count of {}.{}", struct_name, member.name),
+                        value: count.to_string(),
+                    })
+                }
+                None => {
+                    let singular = member.name.strip_suffix('s').unwrap_or(&member.name);
+                    let candidate = self
+                        .count_patterns
+                        .iter()
+                        .map(|pattern| {
+                            pattern
+                                .replace("{name}", &member.name)
+                                .replace("{singular}", singular)
+                        })
+                        .find(|candidate| has_member(candidate));
+                    match candidate {
+                        Some(candidate) => candidate,
+                        None => {
+                            self.warnings.push(format!(
+                                "array member {}.{} has no count member; name one with \
+                                 count=\"...\" or mark it count=\"none\"",
+                                struct_name, member.name
+                            ));
+                            continue;
+                        }
+                    }
+                }
+            };
+            let per_heap = matches!(
+                self.definition_items.get(base_type),
+                Some(DefinitionItem::Struct(element)) if element.common.per_heap
+            );
+            array_info.push(ArrayInfo {
+                array_member_name: member.name.clone(),
+                array_base_type: base_type.to_string(),
+                count_member_name,
+                per_heap,
+            });
         }
+        struct_def.common.array_info = array_info;
+        resolve_handles(
+            &struct_name,
+            &mut struct_def.common.members,
+            &self.definition_items,
+        )?;
+        check_constraint_ranges(&mut struct_def.common.members)?;
+        check_visibility(
+            &struct_name,
+            struct_def.common.visibility,
+            &struct_def.common.members,
+            &self.definition_items,
+        )?;
+        resolve_defaults(
+            &struct_name,
+            &mut struct_def.common.members,
+            &self.definition_items,
+        )?;
         let item_name = struct_def.common.name.clone();
-        let size = calculate_member_size(&struct_def.common.members, &self.type_sizes)?;
+        let size = calculate_layout_size(&item_name, &struct_def.common.members, &self.type_sizes)?;
+        let size = aligned_size(&item_name, struct_def.common.align, size)?;
+        struct_def.common.size = size;
         self.type_sizes.insert(item_name.clone(), size);
         self.definition_items
             .insert(item_name.clone(), DefinitionItem::Struct(struct_def));
         Ok(())
     }
 
-    pub fn add_enum(&mut self, new_enum: Enum) -> Result<(), ApiGenError> {
+    pub fn add_union(&mut self, mut union_def: UnionDef) -> Result<(), ApiGenError> {
+        let item_name = union_def.common.name.clone();
+        check_unpacked_bitfields(&union_def.common.members)?;
+        check_fixed_size(&union_def.common.members)?;
+        check_platform_independent(&union_def.common.members)?;
+        check_not_sensitive(&item_name, &union_def.common.members)?;
+        resolve_handles(
+            &item_name,
+            &mut union_def.common.members,
+            &self.definition_items,
+        )?;
+        check_constraint_ranges(&mut union_def.common.members)?;
+        check_visibility(
+            &item_name,
+            union_def.common.visibility,
+            &union_def.common.members,
+            &self.definition_items,
+        )?;
+        // Rust unions need `Copy` fields, which extensible structs and their builders are not.
+        if let Some(member) = union_def.common.members.iter().find(|m| {
+            let base = split_array_type(&m.type_name).map_or(m.type_name.as_str(), |(b, _)| b);
+            matches!(
+                self.definition_items.get(base),
+                Some(DefinitionItem::ExtensibleStruct(_))
+            )
+        }) {
+            return Err(ApiGenError::InvalidUnionMember {
+                member: format!("{}.{}", item_name, member.name),
+                reason: format!("extensible struct {} is not Copy", member.type_name),
+            });
+        }
+        if let Some(member) = union_def
+            .common
+            .members
+            .iter()
+            .find(|m| m.default.is_some())
+        {
+            return Err(ApiGenError::InvalidAttributeValue {
+                attribute: format!("default of {}.{}", item_name, member.name),
+                value: member.default.clone().unwrap_or_default(),
+            });
+        }
+        let size = union_def
+            .common
+            .members
+            .iter()
+            .try_fold(0, |size, member| {
+                Ok::<_, ApiGenError>(size.max(self.type_size(&member.type_name)?))
+            })?;
+        // Like C and Rust, pad the largest member to the alignment of the most aligned one.
+        let members_align = union_def
+            .common
+            .members
+            .iter()
+            .try_fold(1, |align, member| {
+                Ok::<_, ApiGenError>(align.max(calculate_type_align(
+                    &member.type_name,
+                    &self.definition_items,
+                    &self.type_sizes,
+                )?))
+            })?;
+        let size = aligned_size(
+            &item_name,
+            union_def.common.align,
+            size.next_multiple_of(members_align),
+        )?;
+        union_def.common.size = size;
+        self.type_sizes.insert(item_name.clone(), size);
+        self.definition_items
+            .insert(item_name, DefinitionItem::Union(union_def));
+        Ok(())
+    }
+
+    pub fn add_enum(&mut self, mut new_enum: Enum) -> Result<(), ApiGenError> {
+        resolve_entries(&mut new_enum.entries, false)?;
+        check_reserved(
+            &new_enum.reserved,
+            new_enum.entries.iter().map(|e| (&e.name, &e.value)),
+        )?;
+        if let Some(format) = self.enum_literals {
+            format_entries(&mut new_enum.entries, format)?;
+        }
         let item_name = new_enum.name.clone();
-        let size = self
+        let size = *self
             .type_sizes
             .get(&new_enum.type_name)
             .ok_or_else(|| ApiGenError::TypeNotFound(new_enum.type_name.clone()))?;
-        self.type_sizes.insert(item_name.clone(), *size);
+        if new_enum.unknown_values == UnknownValues::Raw {
+            self.type_sizes.insert(format!("Raw{}", item_name), size);
+        }
+        self.type_sizes.insert(item_name.clone(), size);
         self.definition_items
             .insert(item_name.clone(), DefinitionItem::Enum(new_enum));
         Ok(())
     }
 
-    pub fn add_flag(&mut self, new_flag: Flag) -> Result<(), ApiGenError> {
+    pub fn add_flag(&mut self, mut new_flag: Flag) -> Result<(), ApiGenError> {
+        resolve_entries(&mut new_flag.entries, true)?;
+        let mut mask = 0;
+        for entry in &new_flag.entries {
+            mask |= parse_int_literal(&entry.value)
+                .ok_or_else(|| ApiGenError::InvalidIntegerValue(entry.value.clone()))?;
+        }
+        new_flag.mask = format!("{:#x}", mask);
+        if let Some(format) = self.flag_literals {
+            format_entries(&mut new_flag.entries, format)?;
+        }
         let item_name = new_flag.name.clone();
         let size = self
             .type_sizes
@@ -239,62 +1790,495 @@ impl Api {
             .insert(item_name.clone(), DefinitionItem::Object(object));
     }
 
-    pub fn add_function(&mut self, function: Function) {
+    pub fn add_function(&mut self, mut function: Function) -> Result<(), ApiGenError> {
         let item_name = function.name.clone();
+        resolve_handles(&item_name, &mut function.members, &self.definition_items)?;
+        check_constraint_ranges(&mut function.members)?;
         self.definition_items
             .insert(item_name.clone(), DefinitionItem::Function(function));
+        Ok(())
+    }
+
+    /// Checks that every request other than one-way ones names an existing response, and that
+    /// every response other than one-way ones answers at least one request. Requests of an
+    /// extension may also name responses of its `base`.
+    fn check_request_pairing(
+        protocol: &Protocol,
+        base: Option<&Protocol>,
+    ) -> Result<(), ApiGenError> {
+        let invalid = |opcode: &str, reason: String| ApiGenError::InvalidPairing {
+            protocol: protocol.name.clone(),
+            opcode: opcode.to_string(),
+            reason,
+        };
+
+        for req in &protocol.requests {
+            match (&req.response, req.oneway) {
+                (Some(_), true) => {
+                    return Err(invalid(
+                        &req.opcode.name,
+                        "one-way requests cannot have a response".to_string(),
+                    ))
+                }
+                (None, false) => {
+                    return Err(invalid(
+                        &req.opcode.name,
+                        "missing 'response' (or oneway=\"true\")".to_string(),
+                    ))
+                }
+                (Some(response), false)
+                    if !protocol
+                        .responses
+                        .iter()
+                        .chain(base.iter().flat_map(|b| &b.responses))
+                        .any(|r| &r.opcode.name == response) =>
+                {
+                    return Err(invalid(
+                        &req.opcode.name,
+                        format!("unknown response {}", response),
+                    ))
+                }
+                _ => {}
+            }
+        }
+
+        for res in &protocol.responses {
+            let answered = protocol
+                .requests
+                .iter()
+                .any(|r| r.response.as_ref() == Some(&res.opcode.name));
+            if !res.oneway && !answered {
+                return Err(invalid(
+                    &res.opcode.name,
+                    "no request names this response (or oneway=\"true\")".to_string(),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Rejects protocols whose proto id is already taken and opcodes sharing a value. Extensions
+    /// share the proto id and opcode space of their base and of its other extensions.
+    fn check_protocol_collisions(&self, protocol: &Protocol) -> Result<(), ApiGenError> {
+        if let (Some(id), None) = (protocol.proto_id, &protocol.extends) {
+            let existing = self.definition_items.values().find_map(|item| match item {
+                DefinitionItem::Protocol(p) if p.proto_id == Some(id) && p.extends.is_none() => {
+                    Some(p)
+                }
+                _ => None,
+            });
+            if let Some(existing) = existing {
+                return Err(ApiGenError::ProtocolIdCollision {
+                    id,
+                    first: existing.name.clone(),
+                    second: protocol.name.clone(),
+                });
+            }
+        }
+
+        let related: Vec<&Protocol> = self
+            .definition_items
+            .values()
+            .filter_map(|item| match item {
+                DefinitionItem::Protocol(p)
+                    if protocol.extends.is_some()
+                        && (protocol.extends.as_ref() == Some(&p.name)
+                            || p.extends == protocol.extends) =>
+                {
+                    Some(p)
+                }
+                _ => None,
+            })
+            .chain(std::iter::once(protocol))
+            .collect();
+        // Events have an opcode space of their own, apart from requests and responses.
+        let commands = related.iter().flat_map(|p| {
+            p.requests
+                .iter()
+                .map(|r| &r.opcode)
+                .chain(p.responses.iter().map(|r| &r.opcode))
+        });
+        check_unique_opcodes(&protocol.name, commands)?;
+        check_unique_opcodes(
+            &protocol.name,
+            related
+                .iter()
+                .flat_map(|p| p.events.iter().map(|e| &e.opcode)),
+        )
+    }
+
+    /// Replaces the type of members naming a declared enum or flag by its wire type, keeping the
+    /// enum or flag name for the generated accessors. Members naming another protocol become the
+    /// size of a payload of that protocol's commands. `bool` members are carried as a `u8`, since
+    /// not every byte is a valid `bool`.
+    fn resolve_typed_members(&self, members: &mut [Member]) {
+        for member in members {
+            if member.type_name == "bool" {
+                member.boolean = true;
+                member.type_name = "u8".to_string();
+                continue;
+            }
+            match self.definition_items.get(&member.type_name) {
+                Some(DefinitionItem::Enum(e)) => {
+                    member.enum_name = Some(e.name.clone());
+                    member.type_name = e.type_name.clone();
+                }
+                Some(DefinitionItem::Flag(f)) => {
+                    member.flag_name = Some(f.name.clone());
+                    member.type_name = f.type_name.clone();
+                }
+                Some(DefinitionItem::Protocol(p)) => {
+                    member.payload_protocol = Some(p.name.clone());
+                    member.type_name = "u32".to_string();
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Checks that the transitions of a protocol's state machine name its states and requests, and
+    /// that every request and state has one way to go: a request allowed in any state cannot have
+    /// transitions from given states too, and final states have no transitions.
+    fn check_state_machine(protocol: &Protocol) -> Result<(), ApiGenError> {
+        let Some(state_machine) = &protocol.state_machine else {
+            return Ok(());
+        };
+        let invalid = |reason: String| ApiGenError::InvalidStateMachine {
+            protocol: protocol.name.clone(),
+            reason,
+        };
+        if state_machine.states.is_empty() {
+            return Err(invalid("no states".to_string()));
+        }
+        let mut states = HashMap::new();
+        for state in &state_machine.states {
+            if states.insert(state.name.as_str(), state.is_final).is_some() {
+                return Err(invalid(format!("duplicate state {}", state.name)));
+            }
+        }
+        let mut seen: HashSet<(Option<&str>, &str)> = HashSet::new();
+        for transition in &state_machine.transitions {
+            let request = transition.request.as_str();
+            if !protocol.requests.iter().any(|r| r.opcode.name == request) {
+                return Err(invalid(format!("unknown request {}", request)));
+            }
+            for state in transition.from.iter().chain(&transition.to) {
+                if !states.contains_key(state.as_str()) {
+                    return Err(invalid(format!("unknown state {}", state)));
+                }
+            }
+            let from = transition.from.as_deref();
+            if let Some(from) = from.filter(|from| states[*from]) {
+                return Err(invalid(format!("{} leaves final state {}", request, from)));
+            }
+            let conflict = seen.contains(&(from, request))
+                || seen.contains(&(None, request))
+                || (from.is_none() && seen.iter().any(|&(_, seen)| seen == request));
+            if conflict {
+                return Err(invalid(format!(
+                    "{} has more than one transition from {}",
+                    request,
+                    from.unwrap_or("any state")
+                )));
+            }
+            seen.insert((from, request));
+        }
+        Ok(())
+    }
+
+    /// Rejects commands carrying more than one nested payload, since a payload extends to the
+    /// end of the command.
+    fn check_payloads(opcode: &Opcode, members: &[Member]) -> Result<(), ApiGenError> {
+        if members
+            .iter()
+            .filter(|m| m.payload_protocol.is_some())
+            .count()
+            > 1
+        {
+            return Err(ApiGenError::InvalidPayload {
+                opcode: opcode.name.clone(),
+                reason: "a command carries at most one nested payload".to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Takes the dynamic array out of a command's members, where it must come last, and resolves
+    /// the member counting it like the count member of a fixed-size array.
+    fn take_trailing_array(
+        &self,
+        opcode: &Opcode,
+        members: &mut Vec<Member>,
+    ) -> Result<Option<TrailingArray>, ApiGenError> {
+        let Some(index) = members.iter().position(|m| m.dynamic) else {
+            return Ok(None);
+        };
+        let member = members.remove(index);
+        let invalid = |reason: String| ApiGenError::InvalidDynamicArray {
+            member: format!("{}.{}", opcode.name, member.name),
+            reason,
+        };
+        if index != members.len() {
+            return Err(invalid(
+                "a dynamic array must be the last member of its command".to_string(),
+            ));
+        }
+        if members.iter().any(|m| m.payload_protocol.is_some()) {
+            return Err(invalid(
+                "a command cannot carry both a payload and a dynamic array".to_string(),
+            ));
+        }
+        calculate_type_size(&member.type_name, &self.type_sizes)?;
+
+        let has_member = |name: &str| members.iter().any(|m| m.name == name);
+        let singular = member.name.strip_suffix('s').unwrap_or(&member.name);
+        let count_member_name = match member.count.as_deref() {
+            Some(count) => count.to_string(),
+            None => self
+                .count_patterns
+                .iter()
+                .map(|pattern| {
+                    pattern
+                        .replace("{name}", &member.name)
+                        .replace("{singular}", singular)
+                })
+                .find(|candidate| has_member(candidate))
+                .ok_or_else(|| {
+                    invalid("no count member found; name one with count=\"...\"".to_string())
+                })?,
+        };
+        match members.iter().find(|m| m.name == count_member_name) {
+            Some(count) if matches!(count.type_name.as_str(), "u8" | "u16" | "u32" | "u64") => {}
+            _ => {
+                return Err(invalid(format!(
+                    "count member {} is not an unsigned integer member",
+                    count_member_name
+                )))
+            }
+        }
+        Ok(Some(TrailingArray {
+            name: member.name,
+            type_name: member.type_name,
+            count_member_name,
+            description: member.description,
+        }))
     }
 
     pub fn add_protocol(&mut self, mut protocol: Protocol) -> Result<(), ApiGenError> {
-        // Create and add the protocol header struct.
-        let protocol_struct_name = format!("{}CommandHdr", to_pascal_case(&protocol.name));
-        protocol.protocol_struct_name = protocol_struct_name.clone();
-
-        let protocol_struct = StructDef {
-            common: StructCommon {
-                name: protocol_struct_name.clone(),
-                members: vec![
-                    Member {
-                        type_name: "u32".to_string(),
-                        qualifier: String::new(),
-                        name: "proto".to_string(),
-                    },
-                    Member {
-                        type_name: "u32".to_string(),
-                        qualifier: String::new(),
-                        name: "size".to_string(),
-                    },
-                ],
-                ..Default::default()
+        let base = match &protocol.extends {
+            Some(base_name) => match self.definition_items.get(base_name) {
+                Some(DefinitionItem::Protocol(base)) if base.extends.is_none() => Some(base),
+                _ => {
+                    return Err(ApiGenError::UnknownBaseProtocol {
+                        protocol: protocol.name.clone(),
+                        base: base_name.clone(),
+                    })
+                }
             },
+            None => None,
         };
-        let size = calculate_member_size(&protocol_struct.common.members, &self.type_sizes)?;
-        self.type_sizes.insert(protocol_struct_name.clone(), size);
-        self.definition_items.insert(
-            protocol_struct_name.clone(),
-            DefinitionItem::Struct(protocol_struct),
-        );
+        if let Some(base) = base {
+            if let Some(id) = protocol.proto_id {
+                return Err(ApiGenError::InvalidAttributeValue {
+                    attribute: "id".to_string(),
+                    value: format!("{} (extensions use the id of {})", id, base.name),
+                });
+            }
+            // Extension commands travel on the wire of their base protocol.
+            protocol.proto_id = base.proto_id;
+            protocol.protocol_struct_name = base.protocol_struct_name.clone();
+        }
+        if protocol.cancellable {
+            if protocol.requests.iter().any(|r| r.opcode.name == "cancel") {
+                return Err(ApiGenError::InvalidAttributeValue {
+                    attribute: "cancellable".to_string(),
+                    value: format!("true ({} already has a cancel request)", protocol.name),
+                });
+            }
+            // Declared without a value, so it gets the next free opcode.
+            protocol.requests.push(Request {
+                opcode: Opcode {
+                    name: "cancel".to_string(),
+                    ..Default::default()
+                },
+                members: vec![Member {
+                    type_name: "u64".to_string(),
+                    name: "seqno".to_string(),
+                    ..Default::default()
+                }],
+                oneway: true,
+                description: vec![
+                    "Asks the host to abandon the request sent with sequence number `seqno`."
+                        .to_string(),
+                ],
+                ..Default::default()
+            });
+        }
+        assign_opcode_values(&self.definition_items, &mut self.opcode_lock, &mut protocol)?;
+        check_opcode_range(&protocol)?;
+        self.check_protocol_collisions(&protocol)?;
+        protocol.dense_requests = dense_opcodes(&protocol)?;
+        Self::check_request_pairing(&protocol, base)?;
+        Self::check_state_machine(&protocol)?;
+        if let Some(state_machine) = &mut protocol.state_machine {
+            for transition in &state_machine.transitions {
+                let request = &transition.request;
+                if transition.from.is_some() && !state_machine.restricted.contains(request) {
+                    state_machine.restricted.push(request.clone());
+                }
+            }
+        }
+        check_reserved(
+            &protocol.reserved,
+            protocol
+                .requests
+                .iter()
+                .map(|r| (&r.opcode.name, &r.opcode.value))
+                .chain(
+                    protocol
+                        .responses
+                        .iter()
+                        .map(|r| (&r.opcode.name, &r.opcode.value)),
+                ),
+        )?;
+        // Command structs are public, and so must be the types of their members.
+        for (opcode, members) in protocol
+            .requests
+            .iter()
+            .map(|r| (&r.opcode, &r.members))
+            .chain(protocol.responses.iter().map(|r| (&r.opcode, &r.members)))
+            .chain(protocol.events.iter().map(|e| (&e.opcode, &e.members)))
+        {
+            check_visibility(
+                &opcode.name,
+                Visibility::Pub,
+                members,
+                &self.definition_items,
+            )?;
+        }
+
+        // Create and add the protocol header struct, unless inherited from the base.
+        if protocol.extends.is_none() {
+            let protocol_struct_name = format!("{}CommandHdr", to_pascal_case(&protocol.name));
+            protocol.protocol_struct_name = protocol_struct_name.clone();
+
+            let protocol_struct = StructDef {
+                common: StructCommon {
+                    name: protocol_struct_name.clone(),
+                    members: vec![
+                        Member {
+                            type_name: "u32".to_string(),
+                            qualifier: String::new(),
+                            name: "proto".to_string(),
+                            ..Default::default()
+                        },
+                        Member {
+                            type_name: "u32".to_string(),
+                            qualifier: String::new(),
+                            name: "size".to_string(),
+                            ..Default::default()
+                        },
+                    ],
+                    ..Default::default()
+                },
+            };
+            let size = calculate_member_size(&protocol_struct.common.members, &self.type_sizes)?;
+            self.type_sizes.insert(protocol_struct_name.clone(), size);
+            self.definition_items.insert(
+                protocol_struct_name.clone(),
+                DefinitionItem::Struct(protocol_struct),
+            );
+        }
 
-        // Prepend the header member to all requests and responses.
+        // Prepend the header member to all requests, responses and events.
         let header_member = Member {
-            type_name: protocol_struct_name,
+            type_name: protocol.protocol_struct_name.clone(),
             qualifier: String::new(),
             name: "hdr".to_string(),
+            ..Default::default()
         };
 
         for req in &mut protocol.requests {
+            self.resolve_typed_members(&mut req.members);
+            resolve_handles(&req.opcode.name, &mut req.members, &self.definition_items)?;
+            check_constraint_ranges(&mut req.members)?;
+            Self::check_payloads(&req.opcode, &req.members)?;
+            req.trailing = self.take_trailing_array(&req.opcode, &mut req.members)?;
             req.members.insert(0, header_member.clone());
+            // Commands follow each other 8-byte aligned, which a declared alignment may only raise.
+            if let Some(align) = req.align.filter(|&align| align < NUM_BYTES_IN_U64) {
+                return Err(ApiGenError::InvalidAlignment {
+                    name: req.opcode.name.clone(),
+                    align,
+                });
+            }
             let size = calculate_member_size(&req.members, &self.type_sizes)?;
-            if let Some(padding) = calculate_padding(size) {
+            if let Some(padding) = calculate_padding(size, req.align) {
                 req.members.push(padding);
             }
+            let size = calculate_member_size(&req.members, &self.type_sizes)?;
+            req.size = aligned_size(&req.opcode.name, req.align, size)?;
+            sensitive_ranges(
+                &req.members,
+                0,
+                &self.definition_items,
+                &self.type_sizes,
+                &mut req.redacted,
+            )?;
         }
         for res in &mut protocol.responses {
+            if protocol.credit_window.is_some() {
+                // Right after the header, so that the field sits at the same offset in every
+                // response; the reserved word keeps the members after it 8-byte aligned.
+                let credits = |name: &str, description: &str| Member {
+                    type_name: "u32".to_string(),
+                    name: name.to_string(),
+                    description: vec![description.to_string()],
+                    ..Default::default()
+                };
+                res.members.splice(
+                    0..0,
+                    [
+                        credits("credits", "Flow control credits the host grants back."),
+                        credits("credits_reserved", "Reserved, zero."),
+                    ],
+                );
+            }
+            self.resolve_typed_members(&mut res.members);
+            resolve_handles(&res.opcode.name, &mut res.members, &self.definition_items)?;
+            check_constraint_ranges(&mut res.members)?;
+            Self::check_payloads(&res.opcode, &res.members)?;
+            res.trailing = self.take_trailing_array(&res.opcode, &mut res.members)?;
             res.members.insert(0, header_member.clone());
             let size = calculate_member_size(&res.members, &self.type_sizes)?;
-            if let Some(padding) = calculate_padding(size) {
+            if let Some(padding) = calculate_padding(size, None) {
                 res.members.push(padding);
             }
+            res.size = calculate_member_size(&res.members, &self.type_sizes)?;
+            sensitive_ranges(
+                &res.members,
+                0,
+                &self.definition_items,
+                &self.type_sizes,
+                &mut res.redacted,
+            )?;
+        }
+        for event in &mut protocol.events {
+            self.resolve_typed_members(&mut event.members);
+            resolve_handles(
+                &event.opcode.name,
+                &mut event.members,
+                &self.definition_items,
+            )?;
+            check_constraint_ranges(&mut event.members)?;
+            Self::check_payloads(&event.opcode, &event.members)?;
+            event.trailing = self.take_trailing_array(&event.opcode, &mut event.members)?;
+            event.members.insert(0, header_member.clone());
+            let size = calculate_member_size(&event.members, &self.type_sizes)?;
+            if let Some(padding) = calculate_padding(size, None) {
+                event.members.push(padding);
+            }
+            event.size = calculate_member_size(&event.members, &self.type_sizes)?;
         }
 
         let item_name = protocol.name.clone();
@@ -303,6 +2287,60 @@ impl Api {
         Ok(())
     }
 
+    /// Fills in sTypes declared without a value from the lock, or by hashing the name
+    /// into the auto-assigned range, probing linearly past values already in use.
+    fn assign_stype_values(&mut self, structs: &mut [ExtensibleStruct]) -> Result<(), ApiGenError> {
+        let mut used: HashMap<u32, String> = HashMap::new();
+        for s in structs.iter().filter(|s| !s.stype.value.is_empty()) {
+            let value = parse_int_literal(&s.stype.value)
+                .and_then(|v| u32::try_from(v).ok())
+                .ok_or_else(|| ApiGenError::InvalidIntegerValue(s.stype.value.clone()))?;
+            if let Some(first) = used.insert(value, s.stype.name.clone()) {
+                return Err(ApiGenError::StypeCollision {
+                    value: s.stype.value.clone(),
+                    first,
+                    second: s.stype.name.clone(),
+                });
+            }
+        }
+
+        let auto_names: HashSet<&str> = structs
+            .iter()
+            .filter(|s| s.stype.value.is_empty())
+            .map(|s| s.stype.name.as_str())
+            .collect();
+        for (name, value) in &self.stype_lock {
+            if let Some(first) = used.get(value) {
+                if auto_names.contains(name.as_str()) {
+                    return Err(ApiGenError::StypeCollision {
+                        value: format!("{:#x}", value),
+                        first: first.clone(),
+                        second: name.clone(),
+                    });
+                }
+            } else {
+                used.insert(*value, name.clone());
+            }
+        }
+
+        for s in structs.iter_mut().filter(|s| s.stype.value.is_empty()) {
+            let value = match self.stype_lock.get(&s.stype.name) {
+                Some(value) => *value,
+                None => {
+                    let mut value = stype_auto_value(&s.stype.name);
+                    while used.contains_key(&value) {
+                        value = stype_next_value(value);
+                    }
+                    used.insert(value, s.stype.name.clone());
+                    self.stype_lock.insert(s.stype.name.clone(), value);
+                    value
+                }
+            };
+            s.stype.value = format!("{:#x}", value);
+        }
+        Ok(())
+    }
+
     pub fn add_extensible_structs(
         &mut self,
         stypes_name: String,
@@ -314,6 +2352,8 @@ impl Api {
             ..Default::default()
         };
 
+        self.assign_stype_values(&mut parsed_structs)?;
+
         // Add the individual extensible structs as struct definitions and collect stypes.
         for s in &mut parsed_structs {
             stype_enum.entries.push(s.stype.clone().into());
@@ -330,11 +2370,13 @@ impl Api {
                     type_name: stypes_name.clone(),
                     qualifier: String::new(),
                     name: "stype".to_string(),
+                    ..Default::default()
                 },
                 Member {
                     type_name: "u32".to_string(),
                     qualifier: String::new(),
                     name: "size".to_string(),
+                    ..Default::default()
                 },
             ],
             ..Default::default()
@@ -352,11 +2394,13 @@ impl Api {
                     type_name: stypes_name.clone(),
                     qualifier: String::new(),
                     name: "stype".to_string(),
+                    ..Default::default()
                 },
                 Member {
                     type_name: "*mut std::ffi::c_void".to_string(),
                     qualifier: String::new(),
                     name: "pNext".to_string(),
+                    ..Default::default()
                 },
             ],
             ..Default::default()
@@ -365,13 +2409,18 @@ impl Api {
         // Add the individual extensible structs as struct definitions and collect stypes.
         for s in &mut parsed_structs {
             let item_name = s.common.name.clone();
+            check_not_sensitive(&item_name, &s.common.members)?;
+            resolve_handles(&item_name, &mut s.common.members, &self.definition_items)?;
+            check_constraint_ranges(&mut s.common.members)?;
+            resolve_defaults(&item_name, &mut s.common.members, &self.definition_items)?;
             let size = calculate_member_size(&s.common.members, &self.type_sizes)?;
             let total_size = size + protocol_struct_size;
-            self.type_sizes.insert(item_name.clone(), total_size);
-            s.padding = calculate_padding(total_size);
+            s.common.size = aligned_size(&item_name, s.common.align, total_size)?;
+            s.padding = calculate_padding(total_size, s.common.align);
+            self.type_sizes.insert(item_name.clone(), s.common.size);
             self.definition_items.insert(
                 item_name.clone(),
-                DefinitionItem::ExtensibleStruct(s.clone()),
+                DefinitionItem::ExtensibleStruct(Box::new(s.clone())),
             );
         }
 
@@ -0,0 +1,208 @@
+// Copyright 2025 Google
+// SPDX-License-Identifier: MIT
+
+use std::collections::HashSet;
+
+use crate::common::*;
+
+/// Kind of a definition item, as written in changelog entries.
+fn kind_name(item: &DefinitionItem) -> &'static str {
+    match item {
+        DefinitionItem::Constant(_) => "constant",
+        DefinitionItem::Typedef(_) => "typedef",
+        DefinitionItem::Struct(_) => "struct",
+        DefinitionItem::Union(_) => "union",
+        DefinitionItem::Enum(_) => "enum",
+        DefinitionItem::Flag(_) => "flag",
+        DefinitionItem::ExtensibleStruct(_) => "extensible struct",
+        DefinitionItem::ExtensibleStructs(_) => "sType enum",
+        DefinitionItem::Object(_) => "object",
+        DefinitionItem::Function(_) => "function",
+        DefinitionItem::Protocol(_) => "protocol",
+    }
+}
+
+/// Members of a struct-like item, keyed by the owner named in changelog entries.
+fn member_lists(item: &DefinitionItem) -> Vec<(String, &[Member])> {
+    match item {
+        DefinitionItem::Struct(s) => vec![(s.common.name.clone(), &s.common.members[..])],
+        DefinitionItem::Union(u) => vec![(u.common.name.clone(), &u.common.members[..])],
+        DefinitionItem::ExtensibleStruct(s) => vec![(s.common.name.clone(), &s.common.members[..])],
+        DefinitionItem::Function(f) => vec![(f.name.clone(), &f.members[..])],
+        DefinitionItem::Protocol(p) => {
+            let owner = |opcode: &Opcode| format!("{}::{}", p.name, opcode.name);
+            p.requests
+                .iter()
+                .map(|r| (owner(&r.opcode), &r.members[..]))
+                .chain(
+                    p.responses
+                        .iter()
+                        .map(|r| (owner(&r.opcode), &r.members[..])),
+                )
+                .chain(p.events.iter().map(|e| (owner(&e.opcode), &e.members[..])))
+                .collect()
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// Values of an enum-like item.
+fn entries(item: &DefinitionItem) -> Vec<EnumEntry> {
+    match item {
+        DefinitionItem::Enum(e) => e.entries.clone(),
+        DefinitionItem::Flag(f) => f.entries.clone(),
+        DefinitionItem::ExtensibleStructs(c) => {
+            c.structs.iter().map(|s| s.stype.clone().into()).collect()
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// Requests, responses and events of a protocol, with the word naming their kind.
+fn opcodes(protocol: &Protocol) -> Vec<(&'static str, &Opcode)> {
+    protocol
+        .requests
+        .iter()
+        .map(|r| ("request", &r.opcode))
+        .chain(protocol.responses.iter().map(|r| ("response", &r.opcode)))
+        .chain(protocol.events.iter().map(|e| ("event", &e.opcode)))
+        .collect()
+}
+
+/// Lists the additions of one item version over another in `added`, and its removals in
+/// `removed`.
+fn diff_item(
+    old: &DefinitionItem,
+    new: &DefinitionItem,
+    added: &mut Vec<String>,
+    removed: &mut Vec<String>,
+) {
+    let name = new.name();
+    let old_entries = entries(old);
+    let new_entries = entries(new);
+    for entry in &new_entries {
+        if !old_entries.iter().any(|e| e.name == entry.name) {
+            added.push(format!(
+                "`{}`: new value `{}` = {}",
+                name, entry.name, entry.value
+            ));
+        }
+    }
+    for entry in &old_entries {
+        if !new_entries.iter().any(|e| e.name == entry.name) {
+            removed.push(format!("`{}`: value `{}` removed", name, entry.name));
+        }
+    }
+
+    if let (DefinitionItem::Protocol(old), DefinitionItem::Protocol(new)) = (old, new) {
+        let old_opcodes = opcodes(old);
+        let new_opcodes = opcodes(new);
+        for (kind, opcode) in &new_opcodes {
+            if !old_opcodes
+                .iter()
+                .any(|(k, o)| k == kind && o.name == opcode.name)
+            {
+                let since = match opcode.min_version {
+                    0 => String::new(),
+                    version => format!(", since version {}", version),
+                };
+                added.push(format!(
+                    "`{}`: new {} `{}` (opcode {}{})",
+                    name, kind, opcode.name, opcode.value, since
+                ));
+            }
+        }
+        for (kind, opcode) in &old_opcodes {
+            if !new_opcodes
+                .iter()
+                .any(|(k, o)| k == kind && o.name == opcode.name)
+            {
+                removed.push(format!("`{}`: {} `{}` removed", name, kind, opcode.name));
+            }
+        }
+    }
+
+    // Padding comes and goes with the size of the other fields.
+    let fields = |members: &[Member]| -> Vec<(String, String)> {
+        members
+            .iter()
+            .filter(|m| m.name != "padding")
+            .map(|m| (m.name.clone(), m.type_name.clone()))
+            .collect()
+    };
+    let old_lists = member_lists(old);
+    for (owner, members) in member_lists(new) {
+        // Members of new commands are part of the command's own entry.
+        let Some(&(_, old_members)) = old_lists.iter().find(|(o, _)| *o == owner) else {
+            continue;
+        };
+        let (old_fields, new_fields) = (fields(old_members), fields(members));
+        for (field, type_name) in &new_fields {
+            if !old_fields.iter().any(|(f, _)| f == field) {
+                added.push(format!("`{}`: new field `{}: {}`", owner, field, type_name));
+            }
+        }
+        for (field, _) in &old_fields {
+            if !new_fields.iter().any(|(f, _)| f == field) {
+                removed.push(format!("`{}`: field `{}` removed", owner, field));
+            }
+        }
+    }
+}
+
+/// Returns a Markdown changelog section listing what changed from `old` to `new`, another
+/// version of the same API: added definitions, opcodes, enum and flag values and fields, and,
+/// under a heading of their own, removals. Entries are sorted so that the section only changes
+/// with the API.
+pub fn changelog(old: &Api, new: &Api) -> String {
+    // Command headers come with their protocol.
+    let headers: HashSet<&str> = new
+        .definition_items()
+        .values()
+        .chain(old.definition_items().values())
+        .filter_map(|item| match item {
+            DefinitionItem::Protocol(p) => Some(p.protocol_struct_name.as_str()),
+            _ => None,
+        })
+        .collect();
+
+    let mut added = Vec::new();
+    let mut removed = Vec::new();
+    for (name, item) in new.definition_items() {
+        match old.definition_items().get(name) {
+            Some(old_item) => diff_item(old_item, item, &mut added, &mut removed),
+            None if !headers.contains(name.as_str()) => {
+                added.push(format!("New {} `{}`", kind_name(item), name))
+            }
+            None => {}
+        }
+    }
+    for (name, item) in old.definition_items() {
+        if !new.definition_items().contains_key(name) && !headers.contains(name.as_str()) {
+            removed.push(format!("Removed {} `{}`", kind_name(item), name));
+        }
+    }
+    added.sort();
+    removed.sort();
+
+    let mut section = format!("## {} version {}\n", new.name(), new.version());
+    if old.version() != new.version() {
+        section += &format!("\nChanges since version {}.\n", old.version());
+    }
+    if added.is_empty() && removed.is_empty() {
+        section += "\nNo changes.\n";
+    }
+    if !added.is_empty() {
+        section += "\n### Added\n\n";
+        for entry in &added {
+            section += &format!("- {}\n", entry);
+        }
+    }
+    if !removed.is_empty() {
+        section += "\n### Removed\n\n";
+        for entry in &removed {
+            section += &format!("- {}\n", entry);
+        }
+    }
+    section
+}
@@ -0,0 +1,166 @@
+// Copyright 2025 Google
+// SPDX-License-Identifier: MIT
+
+//! Reader for `apigen.toml`, the per-crate generator configuration: an optional `[copyright]`
+//! table and a `[[generate]]` table per XML description. Unknown keys are rejected.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::common::{Api, ApiGenError};
+
+pub const CONFIG_FILE_NAME: &str = "apigen.toml";
+
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GenerateEntry {
+    /// The XML description, relative to the crate root.
+    pub filename: PathBuf,
+    /// The output directory, relative to the crate root. Defaults to the caller's choice.
+    pub out_dir: Option<PathBuf>,
+    /// Command run on each generated file, with the file's path appended.
+    pub post_process: Option<String>,
+    /// Directory of templates overriding the built-in ones, relative to the crate root.
+    pub template_dir: Option<PathBuf>,
+    /// C++ namespace of every C header generated from the description.
+    pub namespace: Option<String>,
+}
+
+/// Replacements for the copyright block of the XML description, for downstream repositories that
+/// require their own standardized headers.
+#[derive(Debug, Default, Clone, clap::Args, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CopyrightOverrides {
+    /// Overrides the copyright holder of the XML description
+    #[arg(long = "copyright-holder")]
+    pub holder: Option<String>,
+
+    /// Overrides the copyright year of the XML description
+    #[arg(long = "copyright-year")]
+    pub year: Option<u32>,
+
+    /// Overrides the SPDX license identifier of the XML description
+    #[arg(long = "spdx")]
+    pub spdx: Option<String>,
+
+    /// A text file whose lines replace the "DO NOT EDIT" banner of generated files
+    #[arg(long = "banner")]
+    pub banner: Option<PathBuf>,
+}
+
+impl CopyrightOverrides {
+    /// Fills the unset fields from `other`.
+    pub fn or(self, other: &CopyrightOverrides) -> CopyrightOverrides {
+        CopyrightOverrides {
+            holder: self.holder.or_else(|| other.holder.clone()),
+            year: self.year.or(other.year),
+            spdx: self.spdx.or_else(|| other.spdx.clone()),
+            banner: self.banner.or_else(|| other.banner.clone()),
+        }
+    }
+
+    pub fn apply(&self, api: &mut Api) -> Result<(), ApiGenError> {
+        let mut copyright = api.copyright().clone();
+        if let Some(holder) = &self.holder {
+            copyright.holder = holder.clone();
+        }
+        if let Some(year) = self.year {
+            copyright.year = year;
+        }
+        if let Some(spdx) = &self.spdx {
+            copyright.spdx = spdx.clone();
+        }
+        if let Some(banner) = &self.banner {
+            copyright.banner = fs::read_to_string(banner)?
+                .lines()
+                .map(|line| line.to_string())
+                .collect();
+        }
+        api.set_copyright(copyright);
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub copyright: CopyrightOverrides,
+    pub generate: Vec<GenerateEntry>,
+}
+
+pub fn parse_config_str(text: &str) -> Result<Config, ApiGenError> {
+    toml::from_str(text).map_err(|err| ApiGenError::InvalidConfig {
+        line: err
+            .span()
+            .map_or(1, |span| text[..span.start].matches('\n').count() + 1),
+        message: err.message().to_string(),
+    })
+}
+
+pub fn parse_config(path: &Path) -> Result<Config, ApiGenError> {
+    parse_config_str(&fs::read_to_string(path)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_tables() {
+        let config = parse_config_str(
+            r#"
+# Generated files of the crate.
+[copyright]
+holder = "Example Corp"
+year = 2025
+
+[[generate]]
+filename = "xml/magma.xml"
+out_dir = "src/generated"
+
+[[generate]]
+filename = "xml/virtio.xml"
+namespace = "virtio"
+"#,
+        )
+        .unwrap();
+        assert_eq!(config.copyright.holder.as_deref(), Some("Example Corp"));
+        assert_eq!(config.copyright.year, Some(2025));
+        assert_eq!(config.copyright.spdx, None);
+        assert_eq!(config.generate.len(), 2);
+        assert_eq!(config.generate[0].filename, PathBuf::from("xml/magma.xml"));
+        assert_eq!(
+            config.generate[0].out_dir,
+            Some(PathBuf::from("src/generated"))
+        );
+        assert_eq!(config.generate[1].namespace.as_deref(), Some("virtio"));
+    }
+
+    #[test]
+    fn keeps_hashes_inside_strings() {
+        let config = parse_config_str(
+            r#"
+[[generate]]
+filename = "xml/#1.xml" # The first API.
+post_process = "rustfmt --config 'a#b'"
+"#,
+        )
+        .unwrap();
+        assert_eq!(config.generate[0].filename, PathBuf::from("xml/#1.xml"));
+        assert_eq!(
+            config.generate[0].post_process.as_deref(),
+            Some("rustfmt --config 'a#b'")
+        );
+    }
+
+    #[test]
+    fn reports_line_of_errors() {
+        let err = parse_config_str("[copyright]\nholder = \"a\"\nauthor = \"b\"\n").unwrap_err();
+        assert!(matches!(err, ApiGenError::InvalidConfig { line: 3, .. }));
+
+        let err = parse_config_str("[[generate]]\nfilename = 3\n").unwrap_err();
+        assert!(matches!(err, ApiGenError::InvalidConfig { line: 2, .. }));
+    }
+}
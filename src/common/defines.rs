@@ -3,13 +3,20 @@
 
 use serde::Serialize;
 
+use crate::common::utils::{parse_int_literal, split_array_type, to_pascal_case, to_snake_case};
+
+/// Alignment used for structures that live in shared rings and must not straddle cachelines.
+pub const CACHELINE_SIZE: usize = 64;
+
 #[derive(Debug, Serialize, Clone)]
 pub enum DefinitionItem {
     Constant(Constant),
+    Typedef(Typedef),
     Struct(StructDef),
+    Union(UnionDef),
     Enum(Enum),
     Flag(Flag),
-    ExtensibleStruct(ExtensibleStruct),
+    ExtensibleStruct(Box<ExtensibleStruct>),
     ExtensibleStructs(ExtensibleStructs),
     Object(Object),
     Function(Function),
@@ -22,13 +29,339 @@ pub struct Definition {
     pub items: Vec<String>,
 }
 
+impl DefinitionItem {
+    pub fn name(&self) -> &str {
+        match self {
+            DefinitionItem::Constant(c) => &c.name,
+            DefinitionItem::Typedef(t) => &t.name,
+            DefinitionItem::Struct(s) => &s.common.name,
+            DefinitionItem::Union(u) => &u.common.name,
+            DefinitionItem::Enum(e) => &e.name,
+            DefinitionItem::Flag(f) => &f.name,
+            DefinitionItem::ExtensibleStruct(s) => &s.common.name,
+            DefinitionItem::ExtensibleStructs(collection) => &collection.stypes_name,
+            DefinitionItem::Object(o) => &o.name,
+            DefinitionItem::Function(f) => &f.name,
+            DefinitionItem::Protocol(p) => &p.name,
+        }
+    }
+
+    /// Position of the item's kind when items are grouped by kind. Kinds come before the kinds
+    /// that may refer to them, so grouped C headers still declare types before their use.
+    pub fn kind_rank(&self) -> usize {
+        match self {
+            DefinitionItem::Constant(_) => 0,
+            DefinitionItem::Typedef(_) => 1,
+            DefinitionItem::Enum(_) => 2,
+            DefinitionItem::Flag(_) => 3,
+            DefinitionItem::Object(_) => 4,
+            DefinitionItem::Struct(_) | DefinitionItem::Union(_) => 5,
+            DefinitionItem::ExtensibleStructs(_) => 6,
+            DefinitionItem::ExtensibleStruct(_) => 7,
+            DefinitionItem::Function(_) => 8,
+            DefinitionItem::Protocol(_) => 9,
+        }
+    }
+
+    /// Drops the opcodes and the enum and flag values introduced after API version
+    /// `max_version`, so that the item reads as it did in that version.
+    pub fn restrict_to_version(&mut self, max_version: u32) {
+        match self {
+            DefinitionItem::Enum(e) => e.entries.retain(|entry| entry.since <= max_version),
+            DefinitionItem::Flag(f) => {
+                f.entries.retain(|entry| entry.since <= max_version);
+                let mask = f
+                    .entries
+                    .iter()
+                    .filter_map(|entry| parse_int_literal(&entry.value))
+                    .fold(0, |mask, value| mask | value);
+                f.mask = format!("{:#x}", mask);
+            }
+            DefinitionItem::Protocol(p) => {
+                p.requests.retain(|r| r.opcode.min_version <= max_version);
+                p.responses.retain(|r| r.opcode.min_version <= max_version);
+                p.events.retain(|e| e.opcode.min_version <= max_version);
+            }
+            _ => {}
+        }
+    }
+
+    /// Returns true when a member of the item declares a range or power-of-two constraint, or
+    /// must only hold the bits of a flag type, or when a struct has counted arrays whose setters
+    /// report overflows as constraint violations.
+    pub fn has_constraints(&self) -> bool {
+        let constrained = |members: &[Member]| {
+            members.iter().any(|m| {
+                m.min.is_some() || m.max.is_some() || m.power_of_two || m.flag_name.is_some()
+            })
+        };
+        match self {
+            DefinitionItem::Struct(s) => {
+                constrained(&s.common.members) || !s.common.array_info.is_empty()
+            }
+            DefinitionItem::ExtensibleStruct(s) => constrained(&s.common.members),
+            DefinitionItem::ExtensibleStructs(collection) => collection
+                .structs
+                .iter()
+                .any(|s| constrained(&s.common.members)),
+            DefinitionItem::Protocol(proto) => {
+                proto.requests.iter().any(|r| constrained(&r.members))
+                    || proto.responses.iter().any(|r| constrained(&r.members))
+                    || proto.events.iter().any(|e| constrained(&e.members))
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns true when any request, response or event member of the item satisfies `pred`.
+    fn any_command_member(&self, pred: impl Fn(&Member) -> bool) -> bool {
+        match self {
+            DefinitionItem::Protocol(proto) => proto
+                .requests
+                .iter()
+                .flat_map(|r| &r.members)
+                .chain(proto.responses.iter().flat_map(|r| &r.members))
+                .chain(proto.events.iter().flat_map(|e| &e.members))
+                .any(pred),
+            _ => false,
+        }
+    }
+
+    /// Returns true when the item, one of its requests or one of its members is deprecated.
+    pub fn has_deprecations(&self) -> bool {
+        let deprecated = |members: &[Member]| members.iter().any(|m| m.deprecated.is_some());
+        match self {
+            DefinitionItem::Enum(e) => e.deprecated.is_some(),
+            DefinitionItem::Struct(s) => deprecated(&s.common.members),
+            DefinitionItem::Union(u) => deprecated(&u.common.members),
+            DefinitionItem::ExtensibleStruct(s) => deprecated(&s.common.members),
+            DefinitionItem::ExtensibleStructs(collection) => collection
+                .structs
+                .iter()
+                .any(|s| deprecated(&s.common.members)),
+            DefinitionItem::Protocol(proto) => {
+                proto.requests.iter().any(|r| r.deprecated.is_some())
+                    || self.any_command_member(|m| m.deprecated.is_some())
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns true when a struct or command of the item has a member typed by a declared enum or
+    /// `bool`, whose accessors report invalid values.
+    pub fn has_enum_members(&self) -> bool {
+        let typed = |m: &Member| m.enum_name.is_some() || m.boolean;
+        match self {
+            DefinitionItem::Struct(s) => s.common.members.iter().any(typed),
+            DefinitionItem::ExtensibleStruct(s) => s.common.members.iter().any(typed),
+            DefinitionItem::ExtensibleStructs(collection) => collection
+                .structs
+                .iter()
+                .flat_map(|s| &s.common.members)
+                .chain(&collection.protocol_struct.members)
+                .any(typed),
+            _ => self.any_command_member(typed),
+        }
+    }
+
+    /// Returns true when a struct or command member of the item is a handle.
+    pub fn has_handles(&self) -> bool {
+        match self {
+            DefinitionItem::Struct(s) => s.common.members.iter().any(|m| m.handle.is_some()),
+            _ => self.any_command_member(|m| m.handle.is_some()),
+        }
+    }
+
+    /// Returns true when the item is a protocol with a state machine.
+    pub fn has_state_machine(&self) -> bool {
+        matches!(self, DefinitionItem::Protocol(p) if p.state_machine.is_some())
+    }
+
+    /// Returns true when a command of the item carries a nested protocol payload.
+    pub fn has_payloads(&self) -> bool {
+        self.any_command_member(|m| m.payload_protocol.is_some())
+    }
+
+    /// Names the item's members refer to: member types, array element types and lengths, typed
+    /// command members and nested payloads. Primitive names are included; callers filter them.
+    pub fn referenced_names(&self) -> Vec<&str> {
+        let members: Vec<&Member> = match self {
+            DefinitionItem::Struct(s) => s.common.members.iter().collect(),
+            DefinitionItem::Union(u) => u.common.members.iter().collect(),
+            DefinitionItem::ExtensibleStruct(s) => s.common.members.iter().collect(),
+            DefinitionItem::ExtensibleStructs(collection) => collection
+                .structs
+                .iter()
+                .flat_map(|s| &s.common.members)
+                .collect(),
+            DefinitionItem::Function(f) => f.members.iter().collect(),
+            DefinitionItem::Protocol(proto) => proto
+                .requests
+                .iter()
+                .flat_map(|r| &r.members)
+                .chain(proto.responses.iter().flat_map(|r| &r.members))
+                .chain(proto.events.iter().flat_map(|e| &e.members))
+                .collect(),
+            _ => Vec::new(),
+        };
+
+        let mut names = Vec::new();
+        if let DefinitionItem::Typedef(t) = self {
+            let mut type_name = t.type_name.as_str();
+            while let Some((element, len)) = split_array_type(type_name) {
+                names.push(len);
+                type_name = element;
+            }
+            names.push(type_name);
+        }
+        if let DefinitionItem::Protocol(proto) = self {
+            names.push(proto.protocol_struct_name.as_str());
+        }
+        if let DefinitionItem::Function(f) = self {
+            names.push(f.ret.as_str());
+        }
+        for member in members {
+            let mut type_name = member.type_name.as_str();
+            while let Some((element, len)) = split_array_type(type_name) {
+                names.push(len);
+                type_name = element;
+            }
+            names.push(type_name);
+            names.extend(member.enum_name.as_deref());
+            names.extend(member.flag_name.as_deref());
+            names.extend(member.payload_protocol.as_deref());
+        }
+        names
+    }
+}
+
 #[derive(Debug, Default, Serialize, Clone)]
 pub struct GeneratedFile {
     pub out_path: String,
     pub file_name: String,
     pub file_type: String,
     pub includes: Vec<String>,
+    /// Files of the same type instantiating types this file uses, as `out_path/file_name`. Filled
+    /// in by `Api::link_generated_files`.
+    pub references: Vec<String>,
+    /// Makes the imports of included and referenced files `pub use`.
+    pub reexport: bool,
     pub instantiations: Vec<String>,
+    /// Decoder input: "stream" (default) reads from a `Reader`, "slice" from a byte slice.
+    pub decode_mode: String,
+    /// Makes a decoder record the requests it dispatches in an `OpcodeCoverage` bitmap.
+    pub coverage: bool,
+    /// Gives decoders variants of the trailing array readers allocating from a `DecodeArena`.
+    pub decode_arena: bool,
+    /// Adds the binary command log format and its `CommandLog` hook to a protocol file.
+    pub command_log: bool,
+    /// Encoded size in bytes up to which fixed-size commands get an allocation-free
+    /// `encode_inline()`.
+    pub small_command_limit: Option<usize>,
+    /// Latest API version the file describes: items, opcodes and values introduced after it are
+    /// left out.
+    pub max_version: Option<u32>,
+    /// Adds a typed client per protocol, with one method per request returning its paired
+    /// response.
+    pub client: bool,
+    /// Adds a thread-safe connection per protocol, matching responses to requests by sequence
+    /// number.
+    pub connection: bool,
+    /// Path of the generated module from outside its crate, such as `magma::proto`. Setting it
+    /// gives every request a doc-test example encoding and decoding it.
+    pub doc_module: Option<String>,
+    pub order: ItemOrder,
+    pub split: SplitMode,
+    /// Standard library a Rust protocol file builds against.
+    pub target: RustTarget,
+    /// C++ namespace of a C header, such as `magma::proto`. Setting it makes the header C++, with
+    /// `extern "C"` kept to the function declarations.
+    pub namespace: Option<String>,
+    /// Keeps a C header off hosted libc: it includes `typedef_headers` instead, such as kernel
+    /// headers providing the fixed-width integer types, and leaves out the snprintf-based flag
+    /// formatters.
+    pub freestanding: bool,
+    pub typedef_headers: Vec<String>,
+    /// Set on the per-definition files derived from a split file. They import the aggregating
+    /// module instead of repeating its shared helpers.
+    pub part: bool,
+}
+
+/// How a generated file spreads its instantiated definitions over output files.
+#[derive(Debug, Default, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SplitMode {
+    /// Everything in the one file.
+    #[default]
+    Single,
+    /// One module per instantiated definition, declared and re-exported by the file itself.
+    PerDefinition,
+}
+
+impl SplitMode {
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(s: &str) -> Option<SplitMode> {
+        match s {
+            "single" => Some(SplitMode::Single),
+            "per-definition" => Some(SplitMode::PerDefinition),
+            _ => None,
+        }
+    }
+}
+
+/// Standard library support a generated Rust protocol file assumes. Whatever the target, the wire
+/// layouts are the same; only helpers needing `std`, such as `Vec` encoders, differ.
+#[derive(Debug, Default, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RustTarget {
+    /// Every helper is emitted.
+    #[default]
+    Std,
+    /// Helpers needing `std` are left out, for `no_std` crates.
+    NoStd,
+    /// Helpers needing `std` are gated behind the crate's `std` feature.
+    Dual,
+}
+
+impl RustTarget {
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(s: &str) -> Option<RustTarget> {
+        match s {
+            "std" => Some(RustTarget::Std),
+            "no_std" => Some(RustTarget::NoStd),
+            "dual" => Some(RustTarget::Dual),
+            _ => None,
+        }
+    }
+}
+
+/// Order in which a generated file emits the items it instantiates.
+#[derive(Debug, Default, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ItemOrder {
+    /// As declared in the XML.
+    #[default]
+    Declaration,
+    /// Sorted by name.
+    Alphabetical,
+    /// Grouped by kind, in declaration order within each group.
+    Kind,
+    /// As declared, with the requests, responses and events of each protocol sorted by opcode
+    /// value.
+    Opcode,
+}
+
+impl ItemOrder {
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(s: &str) -> Option<ItemOrder> {
+        match s {
+            "declaration" => Some(ItemOrder::Declaration),
+            "alphabetical" => Some(ItemOrder::Alphabetical),
+            "kind" => Some(ItemOrder::Kind),
+            "opcode" => Some(ItemOrder::Opcode),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Default, Serialize, Clone)]
@@ -36,6 +369,8 @@ pub struct Copyright {
     pub spdx: String,
     pub holder: String,
     pub year: u32,
+    /// Lines replacing the default "DO NOT EDIT" banner, if any.
+    pub banner: Vec<String>,
 }
 
 #[derive(Debug, Default, Serialize, Clone)]
@@ -43,6 +378,18 @@ pub struct Constant {
     pub type_name: String,
     pub name: String,
     pub value: String,
+    /// Lines of the XML comment preceding the item, kept when the API preserves comments.
+    pub comment: Vec<String>,
+}
+
+/// Another name for a type, such as `magma_buffer_id` for `u64`. It has the size of its type and
+/// can be used wherever that type can.
+#[derive(Debug, Default, Serialize, Clone)]
+pub struct Typedef {
+    pub name: String,
+    pub type_name: String,
+    /// Lines of the XML comment preceding the item, kept when the API preserves comments.
+    pub comment: Vec<String>,
 }
 
 #[derive(Debug, Default, Serialize, Clone)]
@@ -50,6 +397,22 @@ pub struct StructCommon {
     pub name: String,
     pub members: Vec<Member>,
     pub array_info: Vec<ArrayInfo>,
+    pub align: Option<usize>,
+    pub size: usize,
+    pub non_exhaustive: bool,
+    /// Describes one memory heap; Rust outputs get a source trait to populate it per heap.
+    pub per_heap: bool,
+    /// Protocol outputs also get an owned counterpart of the struct, with `From` conversions.
+    pub owned: bool,
+    /// Rust attributes added verbatim to the generated type, such as `#[derive(Hash)]`.
+    pub attributes: Vec<String>,
+    pub visibility: Visibility,
+    /// Shipped: the layout is recorded in the frozen lock and must not change afterwards.
+    pub frozen: bool,
+    /// Lines of the XML comment preceding the item, kept when the API preserves comments.
+    pub comment: Vec<String>,
+    /// Lines of the item's `<description>`, emitted as doc comments.
+    pub description: Vec<String>,
 }
 
 #[derive(Debug, Default, Serialize, Clone)]
@@ -58,11 +421,21 @@ pub struct StructDef {
     pub common: StructCommon,
 }
 
+/// A C-style union: every member starts at offset 0 and the size is that of the largest member,
+/// rounded up to the declared alignment. Count and array pairing does not apply.
+#[derive(Debug, Default, Serialize, Clone)]
+pub struct UnionDef {
+    #[serde(flatten)]
+    pub common: StructCommon,
+}
+
 #[derive(Debug, Default, Serialize, Clone)]
 pub struct ArrayInfo {
     pub array_member_name: String,
     pub array_base_type: String,
     pub count_member_name: String,
+    /// Set when the elements are `per_heap` structs, indexed by heap.
+    pub per_heap: bool,
 }
 
 #[derive(Debug, Default, Clone, Serialize)]
@@ -70,6 +443,328 @@ pub struct Member {
     pub type_name: String,
     pub qualifier: String,
     pub name: String,
+    /// Inclusive lower bound, as written in the XML.
+    pub min: Option<String>,
+    /// Inclusive upper bound, as written in the XML.
+    pub max: Option<String>,
+    pub power_of_two: bool,
+    pub units: Option<Units>,
+    /// Enum a command member was declared with; `type_name` then holds its wire type.
+    pub enum_name: Option<String>,
+    /// Flag type a command member was declared with; `type_name` then holds its wire type.
+    pub flag_name: Option<String>,
+    /// Set on a `bool` command member, which is carried as a `u8` holding 0 or 1; `type_name` then
+    /// holds `u8`.
+    pub boolean: bool,
+    /// Protocol whose encoded commands follow a command as its payload. The member itself holds
+    /// the payload size in bytes.
+    pub payload_protocol: Option<String>,
+    /// Count member of an array member, or "none", overriding the API's count patterns.
+    pub count: Option<String>,
+    /// Shared-memory word written concurrently by both sides, accessed through atomics.
+    pub atomic: bool,
+    /// Variable-length array of `type_name` elements trailing a command, counted at runtime.
+    pub dynamic: bool,
+    /// Deprecation note; an empty note deprecates without one.
+    pub deprecated: Option<String>,
+    /// Value of the member in the generated `Default` impls: an integer or `true`/`false` literal
+    /// as written in the XML, or an entry of its enum, resolved to `Enum::ENTRY`.
+    pub default: Option<String>,
+    /// Width of a bitfield member, as declared with `<bits>`.
+    pub bits: Option<u32>,
+    /// Struct declared inline in the member instead of a `<type>`. The parser registers it under a
+    /// synthesized name and makes that the member's type.
+    #[serde(skip)]
+    pub inline_struct: Option<Box<StructDef>>,
+    /// Capacity in bytes of a `string` member, a number or a constant name, as written in the
+    /// XML; `type_name` then holds its `[u8; N]` storage. Shorter strings are NUL-padded, and a
+    /// string filling the whole capacity has no terminator.
+    pub max_len: Option<String>,
+    /// Bitfields packed into this member, which is then their storage unit.
+    pub bitfields: Vec<Bitfield>,
+    /// Only platform the struct member exists on; the struct's layout on other platforms it names
+    /// leaves the member out.
+    pub platform: Option<Platform>,
+    /// Holds data that must not leak into traces, such as a guest address or a token: generated
+    /// `Debug` and JSON output print it as `<redacted>`, and command logs zero its bytes.
+    pub sensitive: bool,
+    /// Object a `handle` member refers to. Wire structs carry its u64 id and Rust builders its
+    /// `rust` type; function parameters take its `ffi` type, which is then `type_name`.
+    pub handle: Option<Object>,
+    /// Lines of the item's `<description>`, emitted as doc comments.
+    pub description: Vec<String>,
+}
+
+/// Variable-length array following the fixed part of a command, whose size does not count it. Its
+/// length is held by a member of the fixed part.
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct TrailingArray {
+    pub name: String,
+    /// Type of the elements.
+    pub type_name: String,
+    pub count_member_name: String,
+    pub description: Vec<String>,
+}
+
+/// A bitfield member folded into its storage unit, `bits` wide at `shift` bits from the bottom.
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct Bitfield {
+    pub name: String,
+    pub bits: u32,
+    pub shift: u32,
+    /// Mask of the field's value before shifting, as a hex literal.
+    pub mask: String,
+    pub description: Vec<String>,
+}
+
+/// How the integer literals of one kind of item are written in every output.
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LiteralFormat {
+    Decimal,
+    /// Hexadecimal, zero-padded to `width` digits. Padded digits are grouped by four with `_`,
+    /// which C outputs leave out.
+    Hex {
+        width: usize,
+    },
+}
+
+impl LiteralFormat {
+    /// Parses `decimal`, `hex` or `hex:<width>`.
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(s: &str) -> Option<LiteralFormat> {
+        match s.split_once(':') {
+            None if s == "decimal" => Some(LiteralFormat::Decimal),
+            None if s == "hex" => Some(LiteralFormat::Hex { width: 0 }),
+            Some(("hex", width)) => Some(LiteralFormat::Hex {
+                width: width.parse().ok()?,
+            }),
+            _ => None,
+        }
+    }
+
+    /// Writes `value` in this format. Negative values stay decimal.
+    pub fn format(self, value: i128) -> String {
+        match self {
+            LiteralFormat::Hex { width } if value >= 0 => {
+                let digits = format!("{:0width$x}", value, width = width);
+                if width <= 4 {
+                    return format!("0x{}", digits);
+                }
+                let groups: Vec<&str> = (0..digits.len())
+                    .rev()
+                    .step_by(4)
+                    .map(|end| &digits[end.saturating_sub(3)..=end])
+                    .collect();
+                let grouped: Vec<&str> = groups.into_iter().rev().collect();
+                format!("0x{}", grouped.join("_"))
+            }
+            _ => value.to_string(),
+        }
+    }
+}
+
+/// Unit of an integer member, carried into the generated docs and, with `strict_units`, into a
+/// newtype wrapping the wire value.
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Units {
+    Bytes,
+    Pages,
+    Nanoseconds,
+}
+
+impl Units {
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(s: &str) -> Option<Units> {
+        match s {
+            "bytes" => Some(Units::Bytes),
+            "pages" => Some(Units::Pages),
+            "nanoseconds" => Some(Units::Nanoseconds),
+            _ => None,
+        }
+    }
+}
+
+/// Names one file type gives to the items it generates, from a `<naming>` element of the API.
+#[derive(Debug, Default, Serialize, Clone)]
+pub struct Naming {
+    /// Prefix removed from the names of structs, unions, enums, flags and typedefs, as written or
+    /// in PascalCase: `magma_` strips `Magma` from `MagmaHeap`.
+    pub strip_type_prefix: Option<String>,
+    pub constant_case: ConstantCase,
+}
+
+impl Naming {
+    pub fn type_name(&self, name: &str) -> String {
+        let stripped = self.strip_type_prefix.as_deref().and_then(|prefix| {
+            name.strip_prefix(prefix)
+                .or_else(|| name.strip_prefix(to_pascal_case(prefix).as_str()))
+        });
+        // A prefix that would leave no valid identifier is kept.
+        match stripped {
+            Some(rest) if rest.starts_with(|c: char| c.is_ascii_alphabetic()) => rest.to_string(),
+            _ => name.to_string(),
+        }
+    }
+
+    pub fn constant_name(&self, name: &str) -> String {
+        match self.constant_case {
+            ConstantCase::Preserve => name.to_string(),
+            ConstantCase::ScreamingSnake => to_snake_case(name).to_uppercase(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ConstantCase {
+    /// Names are kept as written.
+    #[default]
+    Preserve,
+    ScreamingSnake,
+}
+
+impl ConstantCase {
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(s: &str) -> Option<ConstantCase> {
+        match s {
+            "preserve" => Some(ConstantCase::Preserve),
+            "screaming_snake" => Some(ConstantCase::ScreamingSnake),
+            _ => None,
+        }
+    }
+}
+
+/// Visibility of a generated Rust type outside of its module. C headers ignore it. Variants are
+/// ordered from the least to the most visible.
+#[derive(Debug, Default, Serialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum Visibility {
+    Private,
+    Crate,
+    #[default]
+    Pub,
+}
+
+impl Visibility {
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(s: &str) -> Option<Visibility> {
+        match s {
+            "private" => Some(Visibility::Private),
+            "pub(crate)" => Some(Visibility::Crate),
+            "pub" => Some(Visibility::Pub),
+            _ => None,
+        }
+    }
+}
+
+/// Operating system a struct member is conditional on, named after its Rust `target_os`.
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum Platform {
+    Android,
+    Fuchsia,
+    Linux,
+    Macos,
+    Windows,
+}
+
+impl Platform {
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(s: &str) -> Option<Platform> {
+        match s {
+            "android" => Some(Platform::Android),
+            "fuchsia" => Some(Platform::Fuchsia),
+            "linux" => Some(Platform::Linux),
+            "macos" => Some(Platform::Macos),
+            "windows" => Some(Platform::Windows),
+            _ => None,
+        }
+    }
+
+    pub fn target_os(&self) -> &'static str {
+        match self {
+            Platform::Android => "android",
+            Platform::Fuchsia => "fuchsia",
+            Platform::Linux => "linux",
+            Platform::Macos => "macos",
+            Platform::Windows => "windows",
+        }
+    }
+}
+
+/// Parsed form of a member `<qualifier>`: an optional `const` or `mut` applying to the pointee,
+/// followed by one `ref` per level of indirection. A bare `const` makes the member read-only.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Qualifier {
+    pub is_const: bool,
+    pub pointer_depth: usize,
+}
+
+impl Qualifier {
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(s: &str) -> Option<Qualifier> {
+        let mut qualifier = Qualifier::default();
+        for (index, token) in s.split_whitespace().enumerate() {
+            match token {
+                "const" | "mut" if index == 0 => qualifier.is_const = token == "const",
+                "ref" => qualifier.pointer_depth += 1,
+                _ => return None,
+            }
+        }
+        Some(qualifier)
+    }
+
+    /// Spells `type_name` with this qualifier in Rust, e.g. `*const T` for `const ref`.
+    pub fn rust_type(&self, type_name: &str) -> String {
+        if self.pointer_depth == 0 {
+            return type_name.to_string();
+        }
+        let pointee = if self.is_const { "*const" } else { "*mut" };
+        format!(
+            "{}{} {}",
+            "*mut ".repeat(self.pointer_depth - 1),
+            pointee,
+            type_name
+        )
+    }
+
+    /// Spells `type_name` with this qualifier in C, e.g. `const T*` for `const ref`.
+    pub fn c_type(&self, type_name: &str) -> String {
+        let constness = if self.is_const { "const " } else { "" };
+        format!(
+            "{}{}{}",
+            constness,
+            type_name,
+            "*".repeat(self.pointer_depth)
+        )
+    }
+}
+
+/// How values not listed in an enum are handled on the decode path.
+#[derive(Debug, Default, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum UnknownValues {
+    /// Conversion from the wire type fails with the unknown value.
+    #[default]
+    Error,
+    /// The enum gets an `Unknown(value)` variant that captures unknown values.
+    Variant,
+    /// A `Raw<Enum>` wrapper of the wire type is generated for use in decoded structures.
+    Raw,
+}
+
+impl UnknownValues {
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(s: &str) -> Option<UnknownValues> {
+        match s {
+            "error" => Some(UnknownValues::Error),
+            "variant" => Some(UnknownValues::Variant),
+            "raw" => Some(UnknownValues::Raw),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Default, Serialize, Clone)]
@@ -77,12 +772,36 @@ pub struct Enum {
     pub name: String,
     pub type_name: String,
     pub entries: Vec<EnumEntry>,
+    pub unknown_values: UnknownValues,
+    pub non_exhaustive: bool,
+    /// Rust attributes added verbatim to the generated type, such as `#[derive(Hash)]`.
+    pub attributes: Vec<String>,
+    pub visibility: Visibility,
+    pub reserved: Vec<ReservedRange>,
+    /// Deprecation note; an empty note deprecates without one.
+    pub deprecated: Option<String>,
+    /// Shipped: the values are recorded in the frozen lock and must not change afterwards.
+    pub frozen: bool,
+    /// Lines of the XML comment preceding the item, kept when the API preserves comments.
+    pub comment: Vec<String>,
+    /// Lines of the item's `<description>`, emitted as doc comments.
+    pub description: Vec<String>,
+}
+
+/// An inclusive range of values that items must not use, e.g. for vendor extensions.
+#[derive(Debug, Default, Serialize, Clone)]
+pub struct ReservedRange {
+    pub from: String,
+    pub to: String,
+    pub reason: String,
 }
 
 #[derive(Debug, Default, Serialize, Clone)]
 pub struct EnumEntry {
     pub name: String,
     pub value: String,
+    /// API version introducing the value, 0 if it was always there.
+    pub since: u32,
 }
 
 impl From<SType> for EnumEntry {
@@ -90,6 +809,7 @@ impl From<SType> for EnumEntry {
         EnumEntry {
             name: stype.name,
             value: stype.value,
+            since: 0,
         }
     }
 }
@@ -99,6 +819,14 @@ pub struct Flag {
     pub name: String,
     pub type_name: String,
     pub entries: Vec<EnumEntry>,
+    /// Union of every defined bit, computed when the flag is added to the API.
+    pub mask: String,
+    /// Rust attributes added verbatim to the generated type, such as `#[derive(Hash)]`.
+    pub attributes: Vec<String>,
+    /// Shipped: the bits are recorded in the frozen lock and must not change afterwards.
+    pub frozen: bool,
+    /// Lines of the XML comment preceding the item, kept when the API preserves comments.
+    pub comment: Vec<String>,
 }
 
 #[derive(Debug, Default, Serialize, Clone)]
@@ -124,6 +852,18 @@ pub struct ExtensibleStructs {
     pub ffi_struct: StructCommon,
 }
 
+/// A type defined outside of the API, declared in its `<typemap>` so that members can use it.
+#[derive(Debug, Default, Serialize, Clone)]
+pub struct ExternalType {
+    /// Name members use, under which Rust outputs import the type.
+    pub name: String,
+    pub size: usize,
+    pub rust: String,
+    pub c: String,
+    /// System header C headers include for the type's declaration.
+    pub header: Option<String>,
+}
+
 #[derive(Debug, Default, Serialize, Clone)]
 pub struct Object {
     pub name: String,
@@ -135,31 +875,151 @@ pub struct Object {
 pub struct Function {
     pub name: String,
     pub ret: String,
+    pub panic_ret: String,
     pub members: Vec<Member>,
+    /// Lines of the XML comment preceding the item, kept when the API preserves comments.
+    pub comment: Vec<String>,
+    /// Lines of the item's `<description>`, emitted as doc comments.
+    pub description: Vec<String>,
 }
 
 #[derive(Debug, Default, Serialize, Clone)]
 pub struct Opcode {
     pub name: String,
     pub value: String,
+    pub min_version: u32,
 }
 
 #[derive(Debug, Default, Serialize, Clone)]
 pub struct Request {
     pub opcode: Opcode,
     pub members: Vec<Member>,
+    pub size: usize,
+    /// Opcode name of the response answering this request; `None` for one-way requests.
+    pub response: Option<String>,
+    pub oneway: bool,
+    /// Deprecation note; an empty note deprecates without one.
+    pub deprecated: Option<String>,
+    /// Lines of the item's `<description>`, emitted as doc comments.
+    pub description: Vec<String>,
+    pub trailing: Option<TrailingArray>,
+    /// Declared alignment, which the request is padded to instead of 8 bytes.
+    pub align: Option<usize>,
+    /// Offset and length of each run of sensitive bytes, nested structs included.
+    pub redacted: Vec<(usize, usize)>,
+}
+
+/// A message the host sends on its own initiative, such as a lost device or a signaled fence,
+/// rather than in answer to a request.
+#[derive(Debug, Default, Serialize, Clone)]
+pub struct Event {
+    pub opcode: Opcode,
+    pub members: Vec<Member>,
+    pub size: usize,
+    pub trailing: Option<TrailingArray>,
+    /// Lines of the item's `<description>`, emitted as doc comments.
+    pub description: Vec<String>,
 }
 
 #[derive(Debug, Default, Serialize, Clone)]
 pub struct Response {
     pub opcode: Opcode,
     pub members: Vec<Member>,
+    pub size: usize,
+    pub trailing: Option<TrailingArray>,
+    /// Set for responses not answering any request, e.g. generic acknowledgements.
+    pub oneway: bool,
+    /// Lines of the item's `<description>`, emitted as doc comments.
+    pub description: Vec<String>,
+    /// Offset and length of each run of sensitive bytes, nested structs included.
+    pub redacted: Vec<(usize, usize)>,
 }
 
 #[derive(Debug, Default, Serialize, Clone)]
 pub struct Protocol {
     pub name: String,
+    /// Value of the header's `proto` field identifying this protocol, if registered.
+    pub proto_id: Option<u32>,
+    /// Base protocol whose opcodes this one extends, sharing its header and proto id.
+    pub extends: Option<String>,
     pub protocol_struct_name: String,
+    /// Prefix of the generated request, response and event struct names: the protocol name in
+    /// pascal case for protocols declared `namespaced="true"`, empty otherwise.
+    pub type_prefix: String,
     pub requests: Vec<Request>,
     pub responses: Vec<Response>,
+    /// Unsolicited host-to-guest notifications, in an opcode space of their own.
+    pub events: Vec<Event>,
+    pub reserved: Vec<ReservedRange>,
+    /// Emits a shared-memory ring control block sized by the protocol's commands.
+    pub ring: bool,
+    /// Requires the request opcodes to be dense, failing generation otherwise.
+    pub dense: bool,
+    /// Credits a guest starts with under credit-based flow control. Setting it adds a `credits`
+    /// member to every response, holding the credits the host grants back.
+    pub credit_window: Option<u32>,
+    /// Adds a one-way `cancel` request naming the connection sequence number of a request the
+    /// host should abandon.
+    pub cancellable: bool,
+    /// Set when the request opcodes are dense enough to index a dispatch table.
+    pub dense_requests: Option<DenseOpcodes>,
+    /// Orderings of the requests the protocol allows, enforced by a generated state tracker.
+    pub state_machine: Option<StateMachine>,
+    /// Lines of the XML comment preceding the item, kept when the API preserves comments.
+    pub comment: Vec<String>,
+}
+
+impl Protocol {
+    /// Names of the structs generated for the protocol's requests, responses and events.
+    pub fn command_type_names(&self) -> impl Iterator<Item = String> + '_ {
+        let name = |opcode: &Opcode, suffix: &str| {
+            format!(
+                "{}{}{}",
+                self.type_prefix,
+                to_pascal_case(&opcode.name),
+                suffix
+            )
+        };
+        self.requests
+            .iter()
+            .map(move |r| name(&r.opcode, "Req"))
+            .chain(self.responses.iter().map(move |r| name(&r.opcode, "Resp")))
+            .chain(self.events.iter().map(move |e| name(&e.opcode, "Event")))
+    }
+}
+
+/// States of a protocol connection and the requests moving between them. Requests named by no
+/// transition are allowed in every state but final ones, and leave the state unchanged.
+#[derive(Debug, Default, Serialize, Clone)]
+pub struct StateMachine {
+    /// In declaration order; the first one is the initial state.
+    pub states: Vec<State>,
+    pub transitions: Vec<Transition>,
+    /// Requests allowed from given states only, filled in when the protocol is added.
+    pub restricted: Vec<String>,
+}
+
+#[derive(Debug, Default, Serialize, Clone)]
+pub struct State {
+    pub name: String,
+    /// No request is allowed once the connection reaches the state.
+    pub is_final: bool,
+}
+
+/// A request allowed in state `from`, moving the connection to state `to`.
+#[derive(Debug, Default, Serialize, Clone)]
+pub struct Transition {
+    /// `None` allows the request in every state but final ones.
+    pub from: Option<String>,
+    pub request: String,
+    /// `None` leaves the state unchanged.
+    pub to: Option<String>,
+}
+
+/// Request opcodes filling most of the range they span: `slots[i]` names the request with opcode
+/// `base + i`, or is `None` for a gap.
+#[derive(Debug, Default, Serialize, Clone)]
+pub struct DenseOpcodes {
+    pub base: u32,
+    pub slots: Vec<Option<String>>,
 }
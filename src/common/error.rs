@@ -5,26 +5,180 @@ use thiserror::Error;
 
 #[derive(Error, Debug)]
 pub enum ApiGenError {
-    #[error("IoError")]
+    #[error("I/O error: {0}")]
     Io(std::io::Error),
-    #[error("Xml parsing error")]
+    #[error("XML parsing error: {0}")]
     Xml(xml::reader::Error),
-    #[error("Integer parsing error")]
+    #[error("{file}:{line}:{column}: {source}")]
+    AtPosition {
+        file: String,
+        line: u64,
+        column: u64,
+        source: Box<ApiGenError>,
+    },
+    #[error("{file} does not follow the apigen schema:\n{}", .problems.join("\n"))]
+    SchemaViolations { file: String, problems: Vec<String> },
+    #[error("Integer parsing error: {0}")]
     ParseInt(std::num::ParseIntError),
     #[error("Missing attribute: {0}")]
     MissingAttribute(String),
-    #[error("Formatting error")]
+    #[error("Formatting error: {0}")]
     Fmt(std::fmt::Error),
-    #[error("Template error")]
+    #[error("Template error: {0:#}")]
     Template(minijinja::Error),
     #[error("Type not found: {0}")]
     TypeNotFound(String),
+    #[error("Type {0} is already defined")]
+    DuplicateType(String),
     #[error("Constant not found: {0}")]
     ConstantNotFound(String),
     #[error("Invalid array type format: {0}")]
     InvalidArrayTypeFormat(String),
     #[error("Invalid constant value for {name}: {value}")]
     InvalidConstantValue { name: String, value: String },
+    #[error("Invalid value for attribute {attribute}: {value}")]
+    InvalidAttributeValue { attribute: String, value: String },
+    #[error("Invalid configuration at line {line}: {message}")]
+    InvalidConfig { line: usize, message: String },
+    #[error("Failed to install git hook: {0}")]
+    HookInstall(String),
+    #[error("Post-processing of {file} failed: {message}")]
+    PostProcess { file: String, message: String },
+    #[error("Invalid integer value: {0}")]
+    InvalidIntegerValue(String),
+    #[error("Protocols {first} and {second} share proto id {id}")]
+    ProtocolIdCollision {
+        id: u32,
+        first: String,
+        second: String,
+    },
+    #[error(
+        "Protocols {first} and {second} both generate {name} in {file}; declare one namespaced=\"true\""
+    )]
+    CommandTypeCollision {
+        name: String,
+        file: String,
+        first: String,
+        second: String,
+    },
+    #[error("Opcodes {first} and {second} of protocol {protocol} share value {value}")]
+    OpcodeCollision {
+        protocol: String,
+        value: String,
+        first: String,
+        second: String,
+    },
+    #[error(
+        "Opcode {opcode} of protocol {protocol} has value {value}, which does not fit in a u32"
+    )]
+    OpcodeOutOfRange {
+        protocol: String,
+        opcode: String,
+        value: String,
+    },
+    #[error("Protocol {protocol} is dense, but its {count} request opcodes span {span} values")]
+    SparseOpcodes {
+        protocol: String,
+        count: usize,
+        span: u64,
+    },
+    #[error("{item} uses value {value} reserved for {reason} ({from}..={to})")]
+    ReservedValue {
+        item: String,
+        value: String,
+        from: String,
+        to: String,
+        reason: String,
+    },
+    #[error("Layout of frozen {0} differs from the one recorded in the frozen lock")]
+    FrozenLayoutChanged(String),
+    #[error("Invalid lockfile at line {line}: {message}")]
+    InvalidLockfile { line: usize, message: String },
+    #[error("Invalid manifest {file} at line {line}: {message}")]
+    InvalidManifest {
+        file: String,
+        line: usize,
+        message: String,
+    },
+    #[error("sTypes {first} and {second} share value {value}")]
+    StypeCollision {
+        value: String,
+        first: String,
+        second: String,
+    },
+    #[error("Invalid qualifier for member {member}: {qualifier}")]
+    InvalidQualifier { member: String, qualifier: String },
+    #[error("Opcode {opcode} of protocol {protocol}: {reason}")]
+    InvalidPairing {
+        protocol: String,
+        opcode: String,
+        reason: String,
+    },
+    #[error("<{element}> {name:?} is not an ASCII identifier")]
+    InvalidIdentifier { element: String, name: String },
+    #[error("Handle member {member} refers to unknown object {object}")]
+    UnknownObject { member: String, object: String },
+    #[error("Protocol {protocol} extends unknown protocol {base}")]
+    UnknownBaseProtocol { protocol: String, base: String },
+    #[error("Invalid payload in {opcode}: {reason}")]
+    InvalidPayload { opcode: String, reason: String },
+    #[error("Invalid state machine in protocol {protocol}: {reason}")]
+    InvalidStateMachine { protocol: String, reason: String },
+    #[error("Type {type_name} used by {file} is not instantiated by any {file_type} file")]
+    UninstantiatedType {
+        type_name: String,
+        file: String,
+        file_type: String,
+    },
+    #[error("Invalid include {include} in {file}: {reason}")]
+    InvalidInclude {
+        file: String,
+        include: String,
+        reason: String,
+    },
+    #[error("Invalid constraint on member {member}: {reason}")]
+    InvalidConstraint { member: String, reason: String },
+    #[error("Invalid bitfield {member}: {reason}")]
+    InvalidBitfield { member: String, reason: String },
+    #[error("Invalid union member {member}: {reason}")]
+    InvalidUnionMember { member: String, reason: String },
+    #[error("Invalid dynamic array {member}: {reason}")]
+    InvalidDynamicArray { member: String, reason: String },
+    #[error("Invalid inline struct in member {member}: {reason}")]
+    InvalidInlineStruct { member: String, reason: String },
+    #[error("Invalid alignment for {name}: {align}")]
+    InvalidAlignment { name: String, align: usize },
+    #[error("{item} cannot be exported to {language}: {reason}")]
+    UnsupportedExport {
+        item: String,
+        language: String,
+        reason: String,
+    },
+    #[error("{owner} is more visible than {type_name}, the type of its member {member}")]
+    VisibilityLeak {
+        owner: String,
+        member: String,
+        type_name: String,
+    },
+    #[error("Naming of {file_type} files gives {first} and {second} the same name {name}")]
+    NameCollision {
+        file_type: String,
+        name: String,
+        first: String,
+        second: String,
+    },
+    #[error("Invalid platform-specific member {member}: {reason}")]
+    InvalidPlatformMember { member: String, reason: String },
+    #[error(
+        "Layouts of {name} differ in size: {first_size} bytes on {first}, {size} on {platform}"
+    )]
+    PlatformLayoutMismatch {
+        name: String,
+        first: String,
+        first_size: usize,
+        platform: String,
+        size: usize,
+    },
 }
 
 impl From<minijinja::Error> for ApiGenError {
@@ -0,0 +1,234 @@
+// Copyright 2025 Google
+// SPDX-License-Identifier: MIT
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use crate::common::error::ApiGenError;
+use crate::common::utils::parse_int_literal;
+
+/// Base of the range auto-assigned sType values are drawn from.
+pub const STYPE_AUTO_BASE: u32 = 0x1000_0000;
+/// Mask applied to the name hash before adding `STYPE_AUTO_BASE`.
+pub const STYPE_AUTO_MASK: u32 = 0x0fff_ffff;
+
+const LOCK_HEADER: &str = "# Auto-assigned sType values. Generated by apigen-xml, do not edit.\n\
+     # Entries are never removed so that assigned values are not reused.\n";
+
+const OPCODE_LOCK_HEADER: &str =
+    "# Auto-assigned opcode values. Generated by apigen-xml, do not edit.\n\
+     # Entries are never removed so that assigned values are not reused.\n";
+
+const FROZEN_LOCK_HEADER: &str =
+    "# Layout fingerprints of frozen definitions. Generated by apigen-xml, do not edit.\n\
+     # Entries are never removed so that shipped definitions stay frozen.\n";
+
+/// Returns the lockfile path recording auto-assigned sTypes for an XML file.
+pub fn stype_lock_path(xml: &Path) -> PathBuf {
+    xml.with_extension("stypes.lock")
+}
+
+/// Returns the lockfile path recording auto-assigned opcodes for an XML file.
+pub fn opcode_lock_path(xml: &Path) -> PathBuf {
+    xml.with_extension("opcodes.lock")
+}
+
+/// Returns the lockfile path recording the layout of frozen definitions for an XML file.
+pub fn frozen_lock_path(xml: &Path) -> PathBuf {
+    xml.with_extension("frozen.lock")
+}
+
+/// Computes the 32-bit FNV-1a hash of a name.
+pub fn fnv1a_32(name: &str) -> u32 {
+    name.bytes().fold(0x811c_9dc5, |hash, b| {
+        (hash ^ b as u32).wrapping_mul(0x0100_0193)
+    })
+}
+
+/// Returns the preferred auto-assigned value for an sType name.
+pub fn stype_auto_value(name: &str) -> u32 {
+    STYPE_AUTO_BASE | (fnv1a_32(name) & STYPE_AUTO_MASK)
+}
+
+/// Returns the next candidate after `value`, wrapping within the auto-assigned range.
+pub fn stype_next_value(value: u32) -> u32 {
+    STYPE_AUTO_BASE | (value.wrapping_add(1) & STYPE_AUTO_MASK)
+}
+
+/// Reads `Name = value` lines from a lockfile. A missing file yields an empty lock.
+pub fn read_lock(path: &Path) -> Result<BTreeMap<String, u32>, ApiGenError> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(BTreeMap::new()),
+        Err(err) => return Err(err.into()),
+    };
+    parse_lock_str(&content)
+}
+
+/// Parses `Name = value` lines, skipping blank lines and `#` comments.
+pub fn parse_lock_str(content: &str) -> Result<BTreeMap<String, u32>, ApiGenError> {
+    let mut lock = BTreeMap::new();
+    for (index, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let invalid = |message: &str| ApiGenError::InvalidLockfile {
+            line: index + 1,
+            message: message.to_string(),
+        };
+        let (name, value) = line
+            .split_once('=')
+            .ok_or_else(|| invalid("expected 'Name = value'"))?;
+        let value = parse_int_literal(value)
+            .and_then(|v| u32::try_from(v).ok())
+            .ok_or_else(|| invalid("value is not a u32 integer"))?;
+        lock.insert(name.trim().to_string(), value);
+    }
+    Ok(lock)
+}
+
+fn render_lock(header: &str, lock: &BTreeMap<String, u32>) -> String {
+    let mut content = header.to_string();
+    for (name, value) in lock {
+        content.push_str(&format!("{} = {:#010x}\n", name, value));
+    }
+    content
+}
+
+fn write_lock(path: &Path, header: &str, lock: &BTreeMap<String, u32>) -> Result<(), ApiGenError> {
+    if lock.is_empty() {
+        return Ok(());
+    }
+    let content = render_lock(header, lock);
+    if fs::read_to_string(path).ok().as_deref() != Some(content.as_str()) {
+        fs::write(path, content)?;
+    }
+    Ok(())
+}
+
+/// Renders the lockfile contents for a set of assigned sTypes.
+pub fn render_stype_lock(lock: &BTreeMap<String, u32>) -> String {
+    render_lock(LOCK_HEADER, lock)
+}
+
+/// Writes the lockfile, leaving it untouched when nothing was auto-assigned.
+pub fn write_stype_lock(path: &Path, lock: &BTreeMap<String, u32>) -> Result<(), ApiGenError> {
+    write_lock(path, LOCK_HEADER, lock)
+}
+
+/// Returns true when the lockfile on disk matches the assigned sTypes.
+pub fn stype_lock_up_to_date(path: &Path, lock: &BTreeMap<String, u32>) -> bool {
+    lock.is_empty() || fs::read_to_string(path).ok() == Some(render_stype_lock(lock))
+}
+
+/// Renders the lockfile contents for a set of assigned opcodes.
+pub fn render_opcode_lock(lock: &BTreeMap<String, u32>) -> String {
+    render_lock(OPCODE_LOCK_HEADER, lock)
+}
+
+/// Writes the opcode lock, leaving it untouched when no opcode was auto-assigned.
+pub fn write_opcode_lock(path: &Path, lock: &BTreeMap<String, u32>) -> Result<(), ApiGenError> {
+    write_lock(path, OPCODE_LOCK_HEADER, lock)
+}
+
+/// Returns true when the opcode lock on disk matches the assigned opcodes.
+pub fn opcode_lock_up_to_date(path: &Path, lock: &BTreeMap<String, u32>) -> bool {
+    lock.is_empty() || fs::read_to_string(path).ok() == Some(render_opcode_lock(lock))
+}
+
+/// Renders the lockfile contents for the layout fingerprints of frozen definitions.
+pub fn render_frozen_lock(lock: &BTreeMap<String, u32>) -> String {
+    render_lock(FROZEN_LOCK_HEADER, lock)
+}
+
+/// Writes the frozen lock, leaving it untouched when nothing is frozen.
+pub fn write_frozen_lock(path: &Path, lock: &BTreeMap<String, u32>) -> Result<(), ApiGenError> {
+    write_lock(path, FROZEN_LOCK_HEADER, lock)
+}
+
+/// Returns true when the frozen lock on disk records every frozen definition.
+pub fn frozen_lock_up_to_date(path: &Path, lock: &BTreeMap<String, u32>) -> bool {
+    lock.is_empty() || fs::read_to_string(path).ok() == Some(render_frozen_lock(lock))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_entries_and_comments() {
+        let lock = parse_lock_str(
+            "# Auto-assigned sType values.\n\
+             \n\
+             MAGMA_STRUCTURE_TYPE_FOO = 0x10000001\n\
+             \x20 MAGMA_STRUCTURE_TYPE_BAR=42  \n",
+        )
+        .unwrap();
+        assert_eq!(lock.len(), 2);
+        assert_eq!(lock["MAGMA_STRUCTURE_TYPE_FOO"], 0x1000_0001);
+        assert_eq!(lock["MAGMA_STRUCTURE_TYPE_BAR"], 42);
+    }
+
+    #[test]
+    fn rejects_malformed_lines() {
+        for (content, line) in [
+            ("FOO\n", 1),
+            ("# header\nFOO = bar\n", 2),
+            ("FOO = -1\n", 1),
+            ("FOO = 0x100000000\n", 1),
+        ] {
+            match parse_lock_str(content) {
+                Err(ApiGenError::InvalidLockfile { line: got, .. }) => assert_eq!(got, line),
+                other => panic!("{content:?} parsed as {other:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn round_trips_stype_lock() {
+        let lock = BTreeMap::from([
+            ("MAGMA_STRUCTURE_TYPE_A".to_string(), stype_auto_value("A")),
+            ("MAGMA_STRUCTURE_TYPE_B".to_string(), 7),
+        ]);
+        let content = render_stype_lock(&lock);
+        assert!(content.starts_with(LOCK_HEADER));
+        assert!(content.contains("MAGMA_STRUCTURE_TYPE_B = 0x00000007\n"));
+        assert_eq!(parse_lock_str(&content).unwrap(), lock);
+    }
+
+    #[test]
+    fn writes_opcode_lock_only_when_needed() {
+        let dir = std::env::temp_dir().join(format!("apigen-lockfile-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = opcode_lock_path(&dir.join("magma.xml"));
+        assert_eq!(path, dir.join("magma.opcodes.lock"));
+        let _ = fs::remove_file(&path);
+
+        let empty = BTreeMap::new();
+        assert!(opcode_lock_up_to_date(&path, &empty));
+        write_opcode_lock(&path, &empty).unwrap();
+        assert!(!path.exists());
+        assert!(read_lock(&path).unwrap().is_empty());
+
+        let lock = BTreeMap::from([("Magma::Execute".to_string(), 0x1000_0002)]);
+        assert!(!opcode_lock_up_to_date(&path, &lock));
+        write_opcode_lock(&path, &lock).unwrap();
+        assert!(opcode_lock_up_to_date(&path, &lock));
+        assert!(!stype_lock_up_to_date(&path, &lock));
+        assert_eq!(read_lock(&path).unwrap(), lock);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn keeps_auto_values_stable_and_in_range() {
+        assert_eq!(fnv1a_32(""), 0x811c_9dc5);
+        assert_eq!(fnv1a_32("a"), 0xe40c_292c);
+        assert_eq!(stype_auto_value("a"), 0x140c_292c);
+        assert_eq!(stype_next_value(0x1fff_ffff), STYPE_AUTO_BASE);
+        assert_eq!(stype_next_value(0x1000_0001), 0x1000_0002);
+    }
+}
@@ -2,8 +2,11 @@
 // SPDX-License-Identifier: MIT
 
 pub mod api;
+pub mod changelog;
+pub mod config;
 pub mod defines;
 pub mod error;
+pub mod lockfile;
 pub mod utils;
 
 pub use api::Api;
@@ -17,6 +17,349 @@ pub fn to_pascal_case(s: &str) -> String {
     pascal
 }
 
+pub fn to_snake_case(s: &str) -> String {
+    let chars: Vec<char> = s.chars().collect();
+    let mut snake = String::new();
+    for (i, &c) in chars.iter().enumerate() {
+        if c.is_ascii_uppercase() && i > 0 {
+            let prev = chars[i - 1];
+            let next_lower = chars.get(i + 1).is_some_and(|n| n.is_ascii_lowercase());
+            if prev.is_ascii_lowercase()
+                || prev.is_ascii_digit()
+                || (prev.is_ascii_uppercase() && next_lower)
+            {
+                snake.push('_');
+            }
+        }
+        snake.push(c.to_ascii_lowercase());
+    }
+    snake
+}
+
+/// Rust keywords, strict and reserved, as of the 2021 edition.
+const RUST_KEYWORDS: &[&str] = &[
+    "abstract", "as", "async", "await", "become", "box", "break", "const", "continue", "crate",
+    "do", "dyn", "else", "enum", "extern", "false", "final", "fn", "for", "if", "impl", "in",
+    "let", "loop", "macro", "match", "mod", "move", "mut", "override", "priv", "pub", "ref",
+    "return", "self", "Self", "static", "struct", "super", "trait", "true", "try", "type",
+    "typeof", "unsafe", "unsized", "use", "virtual", "where", "while", "yield",
+];
+
+/// Keywords of C and C++, which C headers may be compiled as.
+const C_KEYWORDS: &[&str] = &[
+    "alignas",
+    "alignof",
+    "and",
+    "asm",
+    "auto",
+    "bool",
+    "break",
+    "case",
+    "catch",
+    "char",
+    "class",
+    "const",
+    "constexpr",
+    "continue",
+    "default",
+    "delete",
+    "do",
+    "double",
+    "else",
+    "enum",
+    "explicit",
+    "export",
+    "extern",
+    "false",
+    "float",
+    "for",
+    "friend",
+    "goto",
+    "if",
+    "inline",
+    "int",
+    "long",
+    "mutable",
+    "namespace",
+    "new",
+    "noexcept",
+    "not",
+    "nullptr",
+    "operator",
+    "or",
+    "private",
+    "protected",
+    "public",
+    "register",
+    "restrict",
+    "return",
+    "short",
+    "signed",
+    "sizeof",
+    "static",
+    "struct",
+    "switch",
+    "template",
+    "this",
+    "throw",
+    "true",
+    "try",
+    "typedef",
+    "typeid",
+    "typename",
+    "union",
+    "unsigned",
+    "using",
+    "virtual",
+    "void",
+    "volatile",
+    "while",
+    "xor",
+];
+
+/// Returns true when `name` is an identifier in every output language: ASCII letters, digits and
+/// underscores, not starting with a digit. A lone `_` is reserved in Rust.
+pub fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && name != "_"
+}
+
+/// Escapes a Rust keyword used as an identifier: `type` becomes the raw identifier `r#type`.
+/// Keywords that cannot be raw identifiers, such as `self`, get a trailing underscore instead.
+pub fn escape_rust_keyword(name: &str) -> String {
+    match name {
+        "crate" | "self" | "Self" | "super" => format!("{}_", name),
+        _ if RUST_KEYWORDS.contains(&name) => format!("r#{}", name),
+        _ => name.to_string(),
+    }
+}
+
+/// Escapes a C or C++ keyword used as an identifier with a trailing underscore: `class` becomes
+/// `class_`.
+pub fn escape_c_keyword(name: &str) -> String {
+    if C_KEYWORDS.contains(&name) {
+        format!("{}_", name)
+    } else {
+        name.to_string()
+    }
+}
+
 pub fn split(s: &str, p: &str) -> Vec<String> {
     s.split(p).map(|s| s.to_string()).collect()
 }
+
+/// Splits a fixed array type `[T; N]` into its element type and length, both trimmed. Nested
+/// arrays split at their outermost level: `[[u32; 4]; 2]` gives `[u32; 4]` and `2`.
+pub fn split_array_type(type_name: &str) -> Option<(&str, &str)> {
+    let (element, len) = type_name
+        .trim()
+        .strip_prefix('[')?
+        .strip_suffix(']')?
+        .rsplit_once(';')?;
+    Some((element.trim(), len.trim()))
+}
+
+/// Parses a decimal or `0x`-prefixed hexadecimal integer literal, optionally negative. Digits may
+/// be separated by `_`, as in Rust.
+pub fn parse_int_literal(s: &str) -> Option<i128> {
+    let s = s.trim().replace('_', "");
+    let s = s.as_str();
+    let (negative, digits) = match s.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, s),
+    };
+    let value = match digits
+        .strip_prefix("0x")
+        .or_else(|| digits.strip_prefix("0X"))
+    {
+        Some(hex) => i128::from_str_radix(hex, 16).ok()?,
+        None => digits.parse::<i128>().ok()?,
+    };
+    Some(if negative { -value } else { value })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ExprToken {
+    Value(i128),
+    Op(&'static str),
+    Open,
+    Close,
+}
+
+/// Binary operators of integer expressions with their precedence, as in C.
+const BINARY_OPS: [(&str, u8); 7] = [
+    ("|", 1),
+    ("^", 2),
+    ("&", 3),
+    ("<<", 4),
+    (">>", 4),
+    ("+", 5),
+    ("-", 5),
+];
+
+fn tokenize_expr(expr: &str, lookup: &dyn Fn(&str) -> Option<i128>) -> Option<Vec<ExprToken>> {
+    let mut tokens = Vec::new();
+    let mut rest = expr.trim_start();
+    while let Some(c) = rest.chars().next() {
+        let len = if c.is_ascii_alphanumeric() || c == '_' {
+            let len = rest
+                .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                .unwrap_or(rest.len());
+            let word = &rest[..len];
+            let value = if c.is_ascii_digit() {
+                parse_int_literal(word)?
+            } else {
+                lookup(word)?
+            };
+            tokens.push(ExprToken::Value(value));
+            len
+        } else if rest.starts_with("<<") || rest.starts_with(">>") {
+            tokens.push(ExprToken::Op(if c == '<' { "<<" } else { ">>" }));
+            2
+        } else {
+            tokens.push(match c {
+                '(' => ExprToken::Open,
+                ')' => ExprToken::Close,
+                '~' => ExprToken::Op("~"),
+                _ => ExprToken::Op(
+                    BINARY_OPS
+                        .iter()
+                        .find(|(op, _)| op.len() == 1 && op.starts_with(c))?
+                        .0,
+                ),
+            });
+            1
+        };
+        rest = rest[len..].trim_start();
+    }
+    Some(tokens)
+}
+
+fn eval_operand(tokens: &[ExprToken], pos: &mut usize) -> Option<i128> {
+    let token = *tokens.get(*pos)?;
+    *pos += 1;
+    match token {
+        ExprToken::Value(value) => Some(value),
+        ExprToken::Op("-") => eval_operand(tokens, pos)?.checked_neg(),
+        ExprToken::Op("~") => Some(!eval_operand(tokens, pos)?),
+        ExprToken::Open => {
+            let value = eval_binary(tokens, pos, 0)?;
+            if tokens.get(*pos) != Some(&ExprToken::Close) {
+                return None;
+            }
+            *pos += 1;
+            Some(value)
+        }
+        _ => None,
+    }
+}
+
+fn eval_binary(tokens: &[ExprToken], pos: &mut usize, min_precedence: u8) -> Option<i128> {
+    let mut lhs = eval_operand(tokens, pos)?;
+    while let Some(ExprToken::Op(op)) = tokens.get(*pos) {
+        let (_, precedence) = *BINARY_OPS.iter().find(|(o, _)| o == op)?;
+        if precedence < min_precedence {
+            break;
+        }
+        *pos += 1;
+        let rhs = eval_binary(tokens, pos, precedence + 1)?;
+        lhs = match *op {
+            "|" => lhs | rhs,
+            "^" => lhs ^ rhs,
+            "&" => lhs & rhs,
+            "<<" => {
+                // `checked_shl` only checks the shift amount, bits shifted out overflow too.
+                let shift = u32::try_from(rhs).ok()?;
+                let value = lhs.checked_shl(shift)?;
+                (value >> shift == lhs).then_some(value)?
+            }
+            ">>" => lhs.checked_shr(u32::try_from(rhs).ok()?)?,
+            "+" => lhs.checked_add(rhs)?,
+            _ => lhs.checked_sub(rhs)?,
+        };
+    }
+    Some(lhs)
+}
+
+/// Evaluates an integer expression made of literals, names resolved by `lookup`, parentheses,
+/// unary `-` and `~` and the binary `|`, `^`, `&`, `<<`, `>>`, `+` and `-` operators, with C
+/// precedence. Like in C on signed integers, `~` flips every bit, so `~MASK` is negative until
+/// masked, as in `~MASK & 0xff`. Returns `None` for malformed expressions, unknown names and
+/// overflows.
+pub fn eval_int_expr(expr: &str, lookup: &dyn Fn(&str) -> Option<i128>) -> Option<i128> {
+    let tokens = tokenize_expr(expr, lookup)?;
+    let mut pos = 0;
+    let value = eval_binary(&tokens, &mut pos, 0)?;
+    (pos == tokens.len()).then_some(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(expr: &str) -> Option<i128> {
+        eval_int_expr(expr, &|name| match name {
+            "READ" => Some(0x1),
+            "WRITE" => Some(0x2),
+            "PAGE_SHIFT" => Some(12),
+            _ => None,
+        })
+    }
+
+    #[test]
+    fn follows_c_precedence() {
+        assert_eq!(eval("1 + 2 << 3"), Some(24));
+        assert_eq!(eval("1 | 2 & 3"), Some(3));
+        assert_eq!(eval("6 ^ 3 | 8"), Some(13));
+        assert_eq!(eval("0xf0 & 0x3c ^ 0x0f"), Some(0x3f));
+        assert_eq!(eval("(1 | 2) & 3"), Some(3));
+        assert_eq!(eval("10 - 3 - 2"), Some(5));
+        assert_eq!(eval("-(1 + 2)"), Some(-3));
+    }
+
+    #[test]
+    fn evaluates_shifts() {
+        assert_eq!(eval("1 << PAGE_SHIFT"), Some(4096));
+        assert_eq!(eval("0x8000 >> 15"), Some(1));
+        assert_eq!(eval("1 << 2 << 3"), Some(32));
+    }
+
+    #[test]
+    fn complements_masks() {
+        assert_eq!(eval("~0"), Some(-1));
+        assert_eq!(eval("~READ & 0xff"), Some(0xfe));
+        assert_eq!(eval("~(READ | WRITE) & 0x0f"), Some(0x0c));
+        assert_eq!(eval("0xff & ~WRITE"), Some(0xfd));
+        assert_eq!(eval("~~5"), Some(5));
+    }
+
+    #[test]
+    fn resolves_names() {
+        assert_eq!(eval("READ | WRITE"), Some(3));
+        assert_eq!(eval("EXEC | READ"), None);
+        assert_eq!(eval("READ | exec"), None);
+    }
+
+    #[test]
+    fn rejects_overflows() {
+        assert_eq!(eval("1 << 126"), Some(1 << 126));
+        assert_eq!(eval("1 << 127"), None);
+        assert_eq!(eval("1 << 128"), None);
+        assert_eq!(eval("1 << -1"), None);
+        assert_eq!(eval("170141183460469231731687303715884105727 + 1"), None);
+        assert_eq!(eval("-170141183460469231731687303715884105727 - 2"), None);
+    }
+
+    #[test]
+    fn rejects_malformed_expressions() {
+        assert_eq!(eval(""), None);
+        assert_eq!(eval("1 +"), None);
+        assert_eq!(eval("(1 | 2"), None);
+        assert_eq!(eval("1 | 2)"), None);
+        assert_eq!(eval("1 2"), None);
+        assert_eq!(eval("1 * 2"), None);
+        assert_eq!(eval("~"), None);
+    }
+}
@@ -0,0 +1,52 @@
+// Copyright 2025 Google
+// SPDX-License-Identifier: MIT
+
+use std::io::Write;
+use std::path::Path;
+
+use minijinja::{context, Environment};
+
+use crate::common::utils::{to_pascal_case, to_snake_case};
+use crate::common::*;
+use crate::generator::idl::{idl_file, Idl};
+use crate::generator::types::{template_loader, Writer};
+
+/// Emits an AIDL interface named after the generated file, nesting the instantiated constants,
+/// enums and structs and declaring a method for every request of the instantiated protocols.
+pub struct AidlWriter;
+
+impl Writer for AidlWriter {
+    fn write(
+        &self,
+        api: &Api,
+        gen_file: &GeneratedFile,
+        output: &mut dyn Write,
+    ) -> Result<(), ApiGenError> {
+        let mut env = Environment::new();
+        env.set_loader(template_loader(api));
+        env.add_filter("pascal_case", to_pascal_case);
+        env.add_filter("snake_case", to_snake_case);
+
+        // AIDL requires a file to be named after the interface it declares.
+        let interface = Path::new(&gen_file.file_name)
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let tmpl = env.get_template("aidl/file.jinja")?;
+        write!(
+            output,
+            "{}",
+            tmpl.render(context! {
+                year => api.copyright().year,
+                holder => api.copyright().holder,
+                spdx => api.copyright().spdx,
+                banner => api.copyright().banner,
+                api_name => api.name(),
+                interface => interface,
+                file => idl_file(api, gen_file, Idl::Aidl)?,
+            })?
+        )?;
+
+        Ok(())
+    }
+}
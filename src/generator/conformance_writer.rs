@@ -0,0 +1,48 @@
+// Copyright 2025 Google
+// SPDX-License-Identifier: MIT
+
+use std::io::Write;
+
+use minijinja::{context, Environment};
+
+use crate::common::utils::{escape_rust_keyword, to_pascal_case, to_snake_case};
+use crate::common::*;
+use crate::generator::types::{include_uses, instantiated_items, template_loader, Writer};
+
+/// Emits a conformance suite sending each request of the instantiated protocols to a host
+/// implementation and checking the structure of its response.
+pub struct ConformanceWriter;
+
+impl Writer for ConformanceWriter {
+    fn write(
+        &self,
+        api: &Api,
+        gen_file: &GeneratedFile,
+        output: &mut dyn Write,
+    ) -> Result<(), ApiGenError> {
+        let mut env = Environment::new();
+        env.set_loader(template_loader(api));
+        env.add_filter("ident", escape_rust_keyword);
+        env.add_filter("pascal_case", to_pascal_case);
+        env.add_filter("snake_case", to_snake_case);
+
+        let tmpl = env.get_template("conformance/file.jinja")?;
+        write!(
+            output,
+            "{}",
+            tmpl.render(context! {
+                year => api.copyright().year,
+                holder => api.copyright().holder,
+                spdx => api.copyright().spdx,
+                banner => api.copyright().banner,
+                api_name => api.name(),
+                has_deprecations => api.has_deprecations(),
+                defs => instantiated_items(api, gen_file)?,
+                uses => include_uses(api, gen_file)?,
+                reexport => gen_file.reexport,
+            })?
+        )?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,138 @@
+// Copyright 2025 Google
+// SPDX-License-Identifier: MIT
+
+use std::collections::HashMap;
+use std::io::Write;
+
+use minijinja::{context, Environment};
+use serde::Serialize;
+
+use crate::common::utils::parse_int_literal;
+use crate::common::*;
+use crate::generator::types::{template_loader, versioned_item, Writer};
+
+pub struct ConstantsWriter;
+
+#[derive(Serialize)]
+struct NamedValue {
+    name: String,
+    value: i128,
+}
+
+#[derive(Serialize)]
+struct ValueGroup {
+    name: String,
+    entries: Vec<NamedValue>,
+}
+
+fn resolve(name: &str, value: &str) -> Result<i128, ApiGenError> {
+    parse_int_literal(value).ok_or_else(|| ApiGenError::InvalidConstantValue {
+        name: name.to_string(),
+        value: value.to_string(),
+    })
+}
+
+fn resolve_entries(entries: &[EnumEntry]) -> Result<Vec<NamedValue>, ApiGenError> {
+    entries
+        .iter()
+        .map(|entry| {
+            Ok(NamedValue {
+                name: entry.name.clone(),
+                value: resolve(&entry.name, &entry.value)?,
+            })
+        })
+        .collect()
+}
+
+impl Writer for ConstantsWriter {
+    fn write(
+        &self,
+        api: &Api,
+        gen_file: &GeneratedFile,
+        output: &mut dyn Write,
+    ) -> Result<(), ApiGenError> {
+        let mut env = Environment::new();
+        env.set_loader(template_loader(api));
+
+        let mut constants = Vec::new();
+        let mut enums = Vec::new();
+        let mut flags = Vec::new();
+        let mut opcodes = Vec::new();
+
+        let items: Vec<DefinitionItem> = gen_file
+            .instantiations
+            .iter()
+            .filter_map(|def_name| api.definitions().get(def_name))
+            .flat_map(|def| def.items.iter())
+            .filter_map(|item_name| versioned_item(api, gen_file, item_name))
+            .collect();
+        // Constants may be defined in terms of previously declared constants.
+        let mut resolved: HashMap<&str, i128> = HashMap::new();
+        for item in &items {
+            match item {
+                DefinitionItem::Constant(constant) => {
+                    let value = match resolved.get(constant.value.trim()) {
+                        Some(value) => *value,
+                        None => resolve(&constant.name, &constant.value)?,
+                    };
+                    resolved.insert(&constant.name, value);
+                    constants.push(NamedValue {
+                        name: constant.name.clone(),
+                        value,
+                    });
+                }
+                DefinitionItem::Enum(e) => enums.push(ValueGroup {
+                    name: e.name.clone(),
+                    entries: resolve_entries(&e.entries)?,
+                }),
+                DefinitionItem::ExtensibleStructs(ext) => {
+                    let stypes: Vec<EnumEntry> =
+                        ext.structs.iter().map(|s| s.stype.clone().into()).collect();
+                    enums.push(ValueGroup {
+                        name: ext.stypes_name.clone(),
+                        entries: resolve_entries(&stypes)?,
+                    });
+                }
+                DefinitionItem::Flag(flag) => flags.push(ValueGroup {
+                    name: flag.name.clone(),
+                    entries: resolve_entries(&flag.entries)?,
+                }),
+                DefinitionItem::Protocol(proto) => {
+                    let entries = proto
+                        .requests
+                        .iter()
+                        .map(|req| &req.opcode)
+                        .chain(proto.responses.iter().map(|resp| &resp.opcode))
+                        .map(|opcode| {
+                            Ok(NamedValue {
+                                name: opcode.name.clone(),
+                                value: resolve(&opcode.name, &opcode.value)?,
+                            })
+                        })
+                        .collect::<Result<_, ApiGenError>>()?;
+                    opcodes.push(ValueGroup {
+                        name: proto.name.clone(),
+                        entries,
+                    });
+                }
+                _ => {}
+            }
+        }
+
+        let tmpl = env.get_template("constants/file.jinja")?;
+        write!(
+            output,
+            "{}",
+            tmpl.render(context! {
+                api_name => api.name(),
+                version => api.version(),
+                constants => constants,
+                enums => enums,
+                flags => flags,
+                opcodes => opcodes,
+            })?
+        )?;
+
+        Ok(())
+    }
+}
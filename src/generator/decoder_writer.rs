@@ -1,14 +1,16 @@
 // Copyright 2025 Google
 // SPDX-License-Identifier: MIT
 
-use std::fs::File;
+use std::collections::HashMap;
 use std::io::Write;
 
 use minijinja::{context, Environment};
 
+use crate::common::utils::{escape_rust_keyword, to_pascal_case, to_snake_case};
 use crate::common::*;
-use crate::common::utils::to_pascal_case;
-use crate::generator::types::Writer;
+use crate::generator::types::{
+    gated_definitions, include_uses, template_loader, versioned_item, Writer,
+};
 
 pub struct DecoderWriter;
 
@@ -17,11 +19,20 @@ impl Writer for DecoderWriter {
         &self,
         api: &Api,
         gen_file: &GeneratedFile,
-        output: &mut File,
+        output: &mut dyn Write,
     ) -> Result<(), ApiGenError> {
         let mut env = Environment::new();
-        env.set_loader(minijinja::path_loader("src/generator/templates"));
+        env.set_loader(template_loader(api));
+        env.add_filter("ident", escape_rust_keyword);
+        env.add_global("strict_units", api.strict_units());
         env.add_filter("pascal_case", to_pascal_case);
+        env.add_filter("snake_case", to_snake_case);
+
+        // Instantiated items as of the file's version, looked up by name in the template.
+        let items: HashMap<&String, DefinitionItem> = api
+            .instantiated_item_names(gen_file)
+            .filter_map(|item_name| Some((item_name, versioned_item(api, gen_file, item_name)?)))
+            .collect();
 
         let tmpl = env.get_template("decoder/file.jinja")?;
         write!(
@@ -31,8 +42,14 @@ impl Writer for DecoderWriter {
                 year => api.copyright().year,
                 holder => api.copyright().holder,
                 spdx => api.copyright().spdx,
+                banner => api.copyright().banner,
+                has_deprecations => api.has_deprecations(),
                 generated_file => gen_file,
+                uses => include_uses(api, gen_file)?,
+                reexport => gen_file.reexport,
                 api => api,
+                items => items,
+                gated => gated_definitions(api, gen_file)?,
             })?
         )?;
 
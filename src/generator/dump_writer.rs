@@ -0,0 +1,47 @@
+// Copyright 2025 Google
+// SPDX-License-Identifier: MIT
+
+use std::io::Write;
+
+use minijinja::{context, Environment};
+
+use crate::common::utils::{to_pascal_case, to_snake_case};
+use crate::common::*;
+use crate::generator::types::{include_uses, instantiated_items, template_loader, Writer};
+
+/// Emits a module pretty-printing captures of raw commands of the instantiated protocols,
+/// with their names, sizes and field values.
+pub struct DumpWriter;
+
+impl Writer for DumpWriter {
+    fn write(
+        &self,
+        api: &Api,
+        gen_file: &GeneratedFile,
+        output: &mut dyn Write,
+    ) -> Result<(), ApiGenError> {
+        let mut env = Environment::new();
+        env.set_loader(template_loader(api));
+        env.add_filter("pascal_case", to_pascal_case);
+        env.add_filter("snake_case", to_snake_case);
+
+        let tmpl = env.get_template("dump/file.jinja")?;
+        write!(
+            output,
+            "{}",
+            tmpl.render(context! {
+                year => api.copyright().year,
+                holder => api.copyright().holder,
+                spdx => api.copyright().spdx,
+                banner => api.copyright().banner,
+                api_name => api.name(),
+                has_deprecations => api.has_deprecations(),
+                defs => instantiated_items(api, gen_file)?,
+                uses => include_uses(api, gen_file)?,
+                reexport => gen_file.reexport,
+            })?
+        )?;
+
+        Ok(())
+    }
+}
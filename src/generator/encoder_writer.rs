@@ -1,13 +1,12 @@
 // Copyright 2025 Google
 // SPDX-License-Identifier: MIT
 
-use std::fs::File;
 use std::io::Write;
 
 use minijinja::{context, Environment};
 
 use crate::common::*;
-use crate::generator::types::Writer;
+use crate::generator::types::{template_loader, versioned_item, Writer};
 
 pub struct EncoderWriter;
 
@@ -16,20 +15,20 @@ impl Writer for EncoderWriter {
         &self,
         api: &Api,
         gen_file: &GeneratedFile,
-        output: &mut File,
+        output: &mut dyn Write,
     ) -> Result<(), ApiGenError> {
         let mut env = Environment::new();
-        env.set_loader(minijinja::path_loader("src/generator/templates"));
+        env.set_loader(template_loader(api));
 
         let tmpl = env.get_template("encoder/file.jinja")?;
-        let defs: Vec<&DefinitionItem> = gen_file
+        let defs: Vec<DefinitionItem> = gen_file
             .instantiations
             .iter()
             .filter_map(|def_name| {
                 api.definitions().get(def_name).map(|def| {
                     def.items
                         .iter()
-                        .filter_map(|item_name| api.definition_items().get(item_name))
+                        .filter_map(|item_name| versioned_item(api, gen_file, item_name))
                 })
             })
             .flatten()
@@ -41,6 +40,7 @@ impl Writer for EncoderWriter {
                 year => api.copyright().year,
                 holder => api.copyright().holder,
                 spdx => api.copyright().spdx,
+                banner => api.copyright().banner,
                 defs => defs,
                 gen_file => gen_file,
             })?
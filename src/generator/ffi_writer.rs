@@ -1,13 +1,13 @@
 // Copyright 2025 Google
 // SPDX-License-Identifier: MIT
 
-use std::fs::File;
 use std::io::Write;
 
 use minijinja::{context, Environment};
 
+use crate::common::utils::escape_rust_keyword;
 use crate::common::*;
-use crate::generator::types::Writer;
+use crate::generator::types::{include_uses, instantiated_items, template_loader, Writer};
 
 pub struct FfiWriter;
 
@@ -16,24 +16,23 @@ impl Writer for FfiWriter {
         &self,
         api: &Api,
         gen_file: &GeneratedFile,
-        output: &mut File,
+        output: &mut dyn Write,
     ) -> Result<(), ApiGenError> {
         let mut env = Environment::new();
-        env.set_loader(minijinja::path_loader("src/generator/templates"));
+        env.set_loader(template_loader(api));
+        env.add_filter("ident", escape_rust_keyword);
+        env.add_filter("rust_qualified", |type_name: String, qualifier: String| {
+            match Qualifier::from_str(&qualifier) {
+                Some(parsed) => Ok(parsed.rust_type(&type_name)),
+                None => Err(minijinja::Error::new(
+                    minijinja::ErrorKind::InvalidOperation,
+                    format!("invalid qualifier `{}` of {}", qualifier, type_name),
+                )),
+            }
+        });
 
         let tmpl = env.get_template("ffi/file.jinja")?;
-        let defs: Vec<&DefinitionItem> = gen_file
-            .instantiations
-            .iter()
-            .filter_map(|def_name| {
-                api.definitions().get(def_name).map(|def| {
-                    def.items
-                        .iter()
-                        .filter_map(|item_name| api.definition_items().get(item_name))
-                })
-            })
-            .flatten()
-            .collect();
+        let defs = instantiated_items(api, gen_file)?;
         write!(
             output,
             "{}",
@@ -41,8 +40,12 @@ impl Writer for FfiWriter {
                 year => api.copyright().year,
                 holder => api.copyright().holder,
                 spdx => api.copyright().spdx,
+                banner => api.copyright().banner,
+                has_deprecations => api.has_deprecations(),
                 defs => defs,
                 gen_file => gen_file,
+                uses => include_uses(api, gen_file)?,
+                reexport => gen_file.reexport,
             })?
         )?;
 
@@ -0,0 +1,45 @@
+// Copyright 2025 Google
+// SPDX-License-Identifier: MIT
+
+use std::io::Write;
+
+use minijinja::{context, Environment};
+
+use crate::common::utils::{to_pascal_case, to_snake_case};
+use crate::common::*;
+use crate::generator::idl::{idl_file, Idl};
+use crate::generator::types::{template_loader, Writer};
+
+/// Emits a FIDL library declaring the instantiated constants, enums, flags and structs, and a
+/// protocol for every instantiated protocol.
+pub struct FidlWriter;
+
+impl Writer for FidlWriter {
+    fn write(
+        &self,
+        api: &Api,
+        gen_file: &GeneratedFile,
+        output: &mut dyn Write,
+    ) -> Result<(), ApiGenError> {
+        let mut env = Environment::new();
+        env.set_loader(template_loader(api));
+        env.add_filter("pascal_case", to_pascal_case);
+        env.add_filter("snake_case", to_snake_case);
+
+        let tmpl = env.get_template("fidl/file.jinja")?;
+        write!(
+            output,
+            "{}",
+            tmpl.render(context! {
+                year => api.copyright().year,
+                holder => api.copyright().holder,
+                spdx => api.copyright().spdx,
+                banner => api.copyright().banner,
+                api_name => api.name(),
+                file => idl_file(api, gen_file, Idl::Fidl)?,
+            })?
+        )?;
+
+        Ok(())
+    }
+}
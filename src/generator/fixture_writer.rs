@@ -0,0 +1,388 @@
+// Copyright 2025 Google
+// SPDX-License-Identifier: MIT
+
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use crate::common::api::PRIMITIVE_TYPES;
+use crate::common::utils::{parse_int_literal, split_array_type, to_pascal_case};
+use crate::common::*;
+use crate::generator::types::{versioned_item, Writer};
+
+/// Emits one binary fixture per request and response, plus a JSON manifest documenting the value
+/// of every field, so codecs in other languages can be checked against the canonical bytes.
+pub struct FixtureWriter;
+
+#[derive(Serialize)]
+struct FixtureField {
+    path: String,
+    offset: usize,
+    size: usize,
+    value: String,
+}
+
+#[derive(Serialize)]
+struct Fixture {
+    file: String,
+    protocol: String,
+    opcode: String,
+    kind: &'static str,
+    size: usize,
+    fields: Vec<FixtureField>,
+    #[serde(skip)]
+    bytes: Vec<u8>,
+}
+
+#[derive(Serialize)]
+struct Manifest<'a> {
+    api: &'a str,
+    version: u32,
+    fixtures: &'a [Fixture],
+}
+
+/// Lays out a command field by field. Fields without a fixed value are filled with a pattern
+/// derived from their offset, so every byte of the fixture is distinguishable.
+struct FixtureBuilder<'a> {
+    api: &'a Api,
+    bytes: Vec<u8>,
+    fields: Vec<FixtureField>,
+}
+
+impl<'a> FixtureBuilder<'a> {
+    fn new(api: &'a Api) -> Self {
+        FixtureBuilder {
+            api,
+            bytes: Vec::new(),
+            fields: Vec::new(),
+        }
+    }
+
+    fn array_len(&self, count: &str) -> Result<usize, ApiGenError> {
+        if let Some(count) = parse_int_literal(count).and_then(|c| usize::try_from(c).ok()) {
+            return Ok(count);
+        }
+        match self.api.definition_items().get(count) {
+            Some(DefinitionItem::Constant(constant)) => parse_int_literal(&constant.value)
+                .and_then(|c| usize::try_from(c).ok())
+                .ok_or_else(|| ApiGenError::InvalidConstantValue {
+                    name: constant.name.clone(),
+                    value: constant.value.clone(),
+                }),
+            _ => Err(ApiGenError::ConstantNotFound(count.to_string())),
+        }
+    }
+
+    fn push_members(
+        &mut self,
+        path: &str,
+        members: &[Member],
+        overrides: &HashMap<&str, i128>,
+    ) -> Result<(), ApiGenError> {
+        for member in members {
+            let member_path = format!("{}{}", path, member.name);
+            let value = overrides.get(member.name.as_str()).copied();
+            self.push(&member_path, &member.type_name, value)?;
+        }
+        Ok(())
+    }
+
+    fn push(
+        &mut self,
+        path: &str,
+        type_name: &str,
+        value: Option<i128>,
+    ) -> Result<(), ApiGenError> {
+        let type_name = type_name.trim();
+        if type_name.starts_with('[') {
+            let (base, count) = split_array_type(type_name)
+                .ok_or_else(|| ApiGenError::InvalidArrayTypeFormat(type_name.to_string()))?;
+            for index in 0..self.array_len(count)? {
+                self.push(&format!("{}[{}]", path, index), base, value)?;
+            }
+            return Ok(());
+        }
+
+        if PRIMITIVE_TYPES.iter().any(|p| p.rust == type_name) {
+            // Booleans are set rather than patterned, so the fixture stays decodable.
+            let value = value.or((type_name == "bool").then_some(1));
+            let offset = self.bytes.len();
+            let size = self.api.type_size(type_name)?;
+            let bytes: Vec<u8> = match value {
+                Some(value) => value.to_le_bytes()[..size].to_vec(),
+                None => (0..size).map(|i| (offset + i + 1) as u8).collect(),
+            };
+            let mut raw = [0u8; 16];
+            raw[..size].copy_from_slice(&bytes);
+            self.fields.push(FixtureField {
+                path: path.to_string(),
+                offset,
+                size,
+                value: format!(
+                    "{:#0width$x}",
+                    u128::from_le_bytes(raw),
+                    width = 2 + 2 * size
+                ),
+            });
+            self.bytes.extend(bytes);
+            return Ok(());
+        }
+
+        let no_overrides = HashMap::new();
+        match self.api.definition_items().get(type_name) {
+            Some(DefinitionItem::Enum(e)) => {
+                // Use the first declared value so the fixture stays decodable.
+                let first = e
+                    .entries
+                    .first()
+                    .and_then(|entry| parse_int_literal(&entry.value));
+                self.push(path, &e.type_name, value.or(first).or(Some(0)))
+            }
+            Some(DefinitionItem::ExtensibleStructs(collection)) => {
+                let first = collection
+                    .structs
+                    .first()
+                    .and_then(|s| parse_int_literal(&s.stype.value));
+                self.push(path, "u32", value.or(first).or(Some(0)))
+            }
+            Some(DefinitionItem::Struct(s)) => {
+                // Layouts of all platforms have the same size; lay out that of the first one.
+                let platform = s.common.members.iter().filter_map(|m| m.platform).min();
+                let members: Vec<Member> = s
+                    .common
+                    .members
+                    .iter()
+                    .filter(|m| m.platform.is_none() || m.platform == platform)
+                    .cloned()
+                    .collect();
+                self.push_members(&format!("{}.", path), &members, &no_overrides)
+            }
+            Some(DefinitionItem::Typedef(t)) => self.push(path, &t.type_name, value),
+            Some(DefinitionItem::Union(u)) => {
+                // A union holds its first member, zero-extended to the size of the union.
+                let start = self.bytes.len();
+                if let Some(member) = u.common.members.first() {
+                    self.push(
+                        &format!("{}.{}", path, member.name),
+                        &member.type_name,
+                        None,
+                    )?;
+                }
+                match u.common.size - (self.bytes.len() - start) {
+                    0 => Ok(()),
+                    rest => self.push(
+                        &format!("{}.padding", path),
+                        &format!("[u8; {}]", rest),
+                        Some(0),
+                    ),
+                }
+            }
+            Some(DefinitionItem::ExtensibleStruct(s)) => {
+                let stype = parse_int_literal(&s.stype.value).unwrap_or_default();
+                self.push(&format!("{}.hdr.stype", path), "u32", Some(stype))?;
+                self.push(
+                    &format!("{}.hdr.size", path),
+                    "u32",
+                    Some(s.common.size as i128),
+                )?;
+                self.push_members(&format!("{}.", path), &s.common.members, &no_overrides)?;
+                match &s.padding {
+                    Some(padding) => {
+                        self.push(&format!("{}.padding", path), &padding.type_name, Some(0))
+                    }
+                    None => Ok(()),
+                }
+            }
+            _ => Err(ApiGenError::TypeNotFound(type_name.to_string())),
+        }
+    }
+}
+
+impl FixtureWriter {
+    fn fixtures(&self, api: &Api, gen_file: &GeneratedFile) -> Result<Vec<Fixture>, ApiGenError> {
+        let mut fixtures = Vec::new();
+        let protocols: Vec<Protocol> = gen_file
+            .instantiations
+            .iter()
+            .filter_map(|def_name| api.definitions().get(def_name))
+            .flat_map(|def| def.items.iter())
+            .filter_map(|item_name| match versioned_item(api, gen_file, item_name) {
+                Some(DefinitionItem::Protocol(proto)) => Some(proto),
+                _ => None,
+            })
+            .collect();
+        for proto in &protocols {
+            let commands = proto
+                .requests
+                .iter()
+                .map(|req| (&req.opcode, &req.members, req.size, "request", "Req"))
+                .chain(
+                    proto
+                        .responses
+                        .iter()
+                        .map(|res| (&res.opcode, &res.members, res.size, "response", "Resp")),
+                );
+            for (opcode, members, size, kind, suffix) in commands {
+                let overrides = HashMap::from([
+                    ("proto", proto.proto_id.unwrap_or_default() as i128),
+                    ("size", size as i128),
+                ]);
+                let mut builder = FixtureBuilder::new(api);
+                // Commands are framed by their opcode, as decoders read them.
+                let opcode_value = parse_int_literal(&opcode.value)
+                    .ok_or_else(|| ApiGenError::InvalidIntegerValue(opcode.value.clone()))?;
+                builder.push("opcode", "u32", Some(opcode_value))?;
+                for member in members {
+                    match member.name.as_str() {
+                        "hdr" => builder.push_members(
+                            "hdr.",
+                            header_members(api, member)?,
+                            &overrides,
+                        )?,
+                        "padding" => builder.push("padding", &member.type_name, Some(0))?,
+                        _ => builder.push(
+                            &member.name,
+                            member.enum_name.as_ref().unwrap_or(&member.type_name),
+                            None,
+                        )?,
+                    }
+                }
+
+                let file = Path::new(&proto.name)
+                    .join(format!("{}{}.bin", to_pascal_case(&opcode.name), suffix))
+                    .to_string_lossy()
+                    .into_owned();
+                fixtures.push(Fixture {
+                    file,
+                    protocol: proto.name.clone(),
+                    opcode: opcode.name.clone(),
+                    kind,
+                    size: builder.bytes.len(),
+                    fields: builder.fields,
+                    bytes: builder.bytes,
+                });
+            }
+        }
+        Ok(fixtures)
+    }
+}
+
+fn header_members<'a>(api: &'a Api, member: &Member) -> Result<&'a [Member], ApiGenError> {
+    match api.definition_items().get(&member.type_name) {
+        Some(DefinitionItem::Struct(s)) => Ok(&s.common.members),
+        _ => Err(ApiGenError::TypeNotFound(member.type_name.clone())),
+    }
+}
+
+impl Writer for FixtureWriter {
+    fn write(
+        &self,
+        api: &Api,
+        gen_file: &GeneratedFile,
+        output: &mut dyn Write,
+    ) -> Result<(), ApiGenError> {
+        self.write_with_extra_files(api, gen_file, output)?;
+        Ok(())
+    }
+
+    fn write_with_extra_files(
+        &self,
+        api: &Api,
+        gen_file: &GeneratedFile,
+        output: &mut dyn Write,
+    ) -> Result<Vec<(PathBuf, Vec<u8>)>, ApiGenError> {
+        let fixtures = self.fixtures(api, gen_file)?;
+        let manifest = Manifest {
+            api: api.name(),
+            version: api.version(),
+            fixtures: &fixtures,
+        };
+        serde_json::to_writer_pretty(&mut *output, &manifest).map_err(std::io::Error::from)?;
+        writeln!(output)?;
+
+        Ok(fixtures
+            .into_iter()
+            .map(|fixture| (PathBuf::from(fixture.file), fixture.bytes))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::parser::parse_api;
+
+    const API: &str = r#"<?xml version="1.0"?>
+<api name="fixtures">
+  <copyright>
+    <spdx> MIT </spdx>
+    <holder> Google </holder>
+    <year> 2025 </year>
+  </copyright>
+  <version> 1 </version>
+  <define>
+    <name>defs</name>
+    <protocol id="1">
+      <protocol_name>Chars</protocol_name>
+      <request oneway="true">
+        <opcode name="SetName" value="0x00000001"/>
+        <member>
+          <type>[c_char; 4]</type>
+          <name>name</name>
+        </member>
+        <member>
+          <type>c_char</type>
+          <name>initial</name>
+        </member>
+      </request>
+    </protocol>
+  </define>
+  <generated_file>
+    <out_path>fixtures</out_path>
+    <file_name>manifest.json</file_name>
+    <file_type>fixtures</file_type>
+    <instantiate>defs</instantiate>
+  </generated_file>
+</api>
+"#;
+
+    fn parse(xml: &str) -> Api {
+        let path = std::env::temp_dir().join(format!("apigen-fixtures-{}.xml", std::process::id()));
+        std::fs::write(&path, xml).unwrap();
+        let api = parse_api(&path);
+        std::fs::remove_file(&path).unwrap();
+        api.unwrap()
+    }
+
+    #[test]
+    fn lays_out_c_char_members() {
+        let api = parse(API);
+        let fixtures = FixtureWriter
+            .fixtures(&api, &api.generated_files()[0])
+            .unwrap();
+        let fixture = &fixtures[0];
+        let fields: Vec<(&str, usize, usize)> = fixture
+            .fields
+            .iter()
+            .map(|f| (f.path.as_str(), f.offset, f.size))
+            .collect();
+        assert_eq!(
+            fields[..8],
+            [
+                ("opcode", 0, 4),
+                ("hdr.proto", 4, 4),
+                ("hdr.size", 8, 4),
+                ("name[0]", 12, 1),
+                ("name[1]", 13, 1),
+                ("name[2]", 14, 1),
+                ("name[3]", 15, 1),
+                ("initial", 16, 1),
+            ]
+        );
+        assert_eq!(fixture.bytes.len(), 20);
+        assert_eq!(fixture.bytes[..4], 1u32.to_le_bytes());
+    }
+}
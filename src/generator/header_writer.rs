@@ -1,39 +1,117 @@
 // Copyright 2025 Google
 // SPDX-License-Identifier: MIT
 
-use std::fs::File;
+use std::collections::HashMap;
 use std::io::Write;
 
 use minijinja::{context, Environment};
 
+use crate::common::utils::{escape_c_keyword, split_array_type, to_snake_case};
 use crate::common::*;
-use crate::generator::types::Writer;
+use crate::generator::types::{header_includes, instantiated_items, template_loader, Writer};
 
 pub struct HeaderWriter;
 
+/// Declares `name` as a C struct member or parameter. Types go through the Rust-to-C type map and
+/// a fixed array `[T; N]` becomes `T name[N]`, `[[T; N]; M]` becomes `T name[M][N]`.
+fn c_declaration(
+    typemap: &HashMap<String, String>,
+    name: &str,
+    type_name: &str,
+    qualifier: &str,
+) -> Result<String, minijinja::Error> {
+    let qualifier = match Qualifier::from_str(qualifier) {
+        Some(parsed) => parsed,
+        None => {
+            return Err(minijinja::Error::new(
+                minijinja::ErrorKind::InvalidOperation,
+                format!("invalid qualifier `{}` of {}", qualifier, name),
+            ))
+        }
+    };
+    let mut element = type_name;
+    let mut suffix = String::new();
+    while let Some((inner, len)) = split_array_type(element) {
+        suffix.push_str(&format!("[{}]", len));
+        element = inner;
+    }
+    let c_type = typemap.get(element).map(String::as_str).unwrap_or(element);
+    Ok(format!(
+        "{} {}{}",
+        qualifier.c_type(c_type),
+        escape_c_keyword(name),
+        suffix
+    ))
+}
+
+/// Derives the include guard of a generated header from its file name, so that headers including
+/// each other keep distinct guards: `magma_generated.h` is guarded by `MAGMA_GENERATED_H`.
+fn include_guard(file_name: &str) -> String {
+    file_name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
 impl Writer for HeaderWriter {
     fn write(
         &self,
         api: &Api,
         gen_file: &GeneratedFile,
-        output: &mut File,
+        output: &mut dyn Write,
     ) -> Result<(), ApiGenError> {
         let mut env = Environment::new();
-        env.set_loader(minijinja::path_loader("src/generator/templates"));
+        env.set_loader(template_loader(api));
+        let typemap = api.rust_to_c_typemap().clone();
+        env.add_filter("c_type", move |type_name: String| {
+            typemap.get(&type_name).cloned().unwrap_or(type_name)
+        });
+        let typemap = api.rust_to_c_typemap().clone();
+        env.add_filter(
+            "c_declaration",
+            move |name: String, type_name: String, qualifier: String| {
+                c_declaration(&typemap, &name, &type_name, &qualifier)
+            },
+        );
+        env.add_filter("snake_case", to_snake_case);
+        // Freestanding C has no <assert.h> or <stdalign.h> to provide the keyword macros.
+        let c11_keywords = gen_file.freestanding && gen_file.namespace.is_none();
+        env.add_global("freestanding", gen_file.freestanding);
+        env.add_global(
+            "static_assert",
+            if c11_keywords {
+                "_Static_assert"
+            } else {
+                "static_assert"
+            },
+        );
+        env.add_global("alignas", if c11_keywords { "_Alignas" } else { "alignas" });
+        // C++ and kernel code have no portable `_Atomic`, they access the word with their own
+        // atomics.
+        let hosted_c = !gen_file.freestanding && gen_file.namespace.is_none();
+        env.add_global(
+            "atomic_qualifier",
+            if hosted_c { "_Atomic" } else { "volatile" },
+        );
 
         let tmpl = env.get_template("header/file.jinja")?;
-        let defs: Vec<&DefinitionItem> = gen_file
-            .instantiations
+        let defs = instantiated_items(api, gen_file)?;
+        let mut external_headers: Vec<&str> = Vec::new();
+        for header in api
+            .external_types()
             .iter()
-            .filter_map(|def_name| {
-                api.definitions().get(def_name).map(|def| {
-                    def.items
-                        .iter()
-                        .filter_map(|item_name| api.definition_items().get(item_name))
-                })
-            })
-            .flatten()
-            .collect();
+            .filter_map(|t| t.header.as_deref())
+        {
+            if !external_headers.contains(&header) {
+                external_headers.push(header);
+            }
+        }
         write!(
             output,
             "{}",
@@ -41,8 +119,13 @@ impl Writer for HeaderWriter {
                 year => api.copyright().year,
                 holder => api.copyright().holder,
                 spdx => api.copyright().spdx,
+                banner => api.copyright().banner,
                 defs => defs,
                 gen_file => gen_file,
+                external_headers => external_headers,
+                includes => header_includes(api, gen_file)?,
+                namespace => gen_file.namespace,
+                include_guard => include_guard(&gen_file.file_name),
             })?
         )?;
 
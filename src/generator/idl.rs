@@ -0,0 +1,441 @@
+// Copyright 2025 Google
+// SPDX-License-Identifier: MIT
+
+use std::collections::{HashMap, HashSet};
+
+use serde::Serialize;
+
+use crate::common::utils::{eval_int_expr, split_array_type, to_pascal_case};
+use crate::common::*;
+use crate::generator::types::versioned_item;
+
+/// Interface definition language of the IPC frameworks protocols are exported to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Idl {
+    Fidl,
+    Aidl,
+}
+
+impl Idl {
+    fn name(self) -> &'static str {
+        match self {
+            Idl::Fidl => "FIDL",
+            Idl::Aidl => "AIDL",
+        }
+    }
+
+    /// Spells a primitive type. AIDL has no unsigned integers, so those take the signed type of
+    /// their width, and no 16-bit `short`, so 16-bit integers are `int`s.
+    fn primitive(self, type_name: &str) -> Option<&'static str> {
+        let (fidl, aidl) = match type_name {
+            "u8" => ("uint8", "byte"),
+            "i8" => ("int8", "byte"),
+            "u16" => ("uint16", "int"),
+            "i16" => ("int16", "int"),
+            "u32" => ("uint32", "int"),
+            "i32" => ("int32", "int"),
+            "u64" | "usize" => ("uint64", "long"),
+            "i64" | "isize" => ("int64", "long"),
+            "f32" => ("float32", "float"),
+            "f64" => ("float64", "double"),
+            "bool" => ("bool", "boolean"),
+            "core::ffi::c_char" => ("int8", "byte"),
+            _ => return None,
+        };
+        Some(match self {
+            Idl::Fidl => fidl,
+            Idl::Aidl => aidl,
+        })
+    }
+
+    /// Spells `value` for a field of the spelled type `type_name`. AIDL integers are signed, so
+    /// values past the signed range of their width wrap to two's complement, while values that
+    /// do not fit the width at all are rejected.
+    fn integer(self, type_name: &str, value: i128) -> Option<i128> {
+        let bits = match (self, type_name) {
+            (Idl::Aidl, "byte") => 8,
+            (Idl::Aidl, "int") => 32,
+            (Idl::Aidl, "long") => 64,
+            _ => return Some(value),
+        };
+        let half = 1i128 << (bits - 1);
+        match value {
+            _ if (-half..half).contains(&value) => Some(value),
+            _ if (half..half << 1).contains(&value) => Some(value - (half << 1)),
+            _ => None,
+        }
+    }
+
+    fn array(self, element: &str, len: i128) -> String {
+        match self {
+            Idl::Fidl => format!("array<{}, {}>", element, len),
+            Idl::Aidl => format!("{}[{}]", element, len),
+        }
+    }
+
+    fn vector(self, element: &str) -> String {
+        match self {
+            Idl::Fidl => format!("vector<{}>", element),
+            Idl::Aidl => format!("{}[]", element),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct IdlField {
+    pub name: String,
+    pub type_name: String,
+    /// Set for primitives and enums, which AIDL passes without a direction.
+    pub by_value: bool,
+    pub description: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct IdlStruct {
+    pub name: String,
+    pub fields: Vec<IdlField>,
+    pub description: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct IdlValue {
+    pub name: String,
+    pub value: i128,
+}
+
+/// An enum, or a flag type when `bits` is set.
+#[derive(Serialize)]
+pub struct IdlEnum {
+    pub name: String,
+    pub type_name: String,
+    pub bits: bool,
+    pub entries: Vec<IdlValue>,
+    pub description: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct IdlConstant {
+    pub name: String,
+    pub type_name: String,
+    pub value: i128,
+}
+
+/// A request, with the fields of the response answering it, or an event.
+#[derive(Serialize)]
+pub struct IdlMethod {
+    pub name: String,
+    pub request: Vec<IdlField>,
+    pub response: Option<IdlStruct>,
+    pub description: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct IdlProtocol {
+    pub name: String,
+    pub methods: Vec<IdlMethod>,
+    pub events: Vec<IdlMethod>,
+}
+
+/// Items of a generated file as an interface definition language spells them.
+#[derive(Serialize)]
+pub struct IdlFile {
+    pub constants: Vec<IdlConstant>,
+    pub enums: Vec<IdlEnum>,
+    pub structs: Vec<IdlStruct>,
+    pub protocols: Vec<IdlProtocol>,
+}
+
+struct IdlBuilder<'a> {
+    api: &'a Api,
+    idl: Idl,
+    /// Values of the constants declared so far, which array lengths and later constants use.
+    constants: HashMap<String, i128>,
+}
+
+impl IdlBuilder<'_> {
+    fn unsupported(&self, item: &str, reason: &str) -> ApiGenError {
+        ApiGenError::UnsupportedExport {
+            item: item.to_string(),
+            language: self.idl.name().to_string(),
+            reason: reason.to_string(),
+        }
+    }
+
+    fn value(&self, name: &str, value: &str) -> Result<i128, ApiGenError> {
+        eval_int_expr(value, &|constant| self.constants.get(constant).copied()).ok_or_else(|| {
+            ApiGenError::InvalidConstantValue {
+                name: name.to_string(),
+                value: value.to_string(),
+            }
+        })
+    }
+
+    /// Spells `type_name`, returning whether it is passed by value as well. Typedefs are resolved
+    /// since AIDL has no aliases, and unions, which neither language lays out in place, become
+    /// byte arrays of their size.
+    fn field_type(&self, owner: &str, type_name: &str) -> Result<(String, bool), ApiGenError> {
+        if let Some((element, len)) = split_array_type(type_name) {
+            let (element, _) = self.field_type(owner, element)?;
+            return Ok((self.idl.array(&element, self.value(owner, len)?), false));
+        }
+        if let Some(primitive) = self.idl.primitive(type_name) {
+            return Ok((primitive.to_string(), true));
+        }
+        match self.api.definition_items().get(type_name) {
+            Some(DefinitionItem::Typedef(t)) => self.field_type(owner, &t.type_name),
+            Some(DefinitionItem::Enum(_) | DefinitionItem::Flag(_)) => {
+                Ok((type_name.to_string(), true))
+            }
+            Some(DefinitionItem::Struct(_)) => Ok((type_name.to_string(), false)),
+            Some(DefinitionItem::ExtensibleStructs(_)) => self.field_type(owner, "u32"),
+            Some(DefinitionItem::Union(u)) => {
+                let byte = self.idl.primitive("u8").unwrap_or_default();
+                Ok((self.idl.array(byte, u.common.size as i128), false))
+            }
+            _ => Err(self.unsupported(owner, &format!("type {} has no equivalent", type_name))),
+        }
+    }
+
+    fn fields<'m>(
+        &self,
+        owner: &str,
+        members: impl Iterator<Item = &'m Member>,
+    ) -> Result<Vec<IdlField>, ApiGenError> {
+        members
+            .map(|member| {
+                if member.platform.is_some() {
+                    return Err(self.unsupported(
+                        owner,
+                        &format!("platform-specific member {} has no equivalent", member.name),
+                    ));
+                }
+                // Command members declared with an enum, flag or `bool` type carry their wire
+                // type.
+                let type_name = match &member.enum_name {
+                    _ if member.boolean => "bool",
+                    Some(enum_name) => enum_name.as_str(),
+                    None => member.flag_name.as_deref().unwrap_or(&member.type_name),
+                };
+                let (type_name, by_value) = self.field_type(owner, type_name)?;
+                Ok(IdlField {
+                    name: member.name.clone(),
+                    type_name,
+                    by_value,
+                    description: member.description.clone(),
+                })
+            })
+            .collect()
+    }
+
+    /// Fields of a command: its members without the protocol header and padding, which the
+    /// frameworks bring their own of, followed by its trailing array.
+    fn command_fields(
+        &self,
+        owner: &str,
+        members: &[Member],
+        trailing: &Option<TrailingArray>,
+    ) -> Result<Vec<IdlField>, ApiGenError> {
+        let mut fields = self.fields(
+            owner,
+            members
+                .iter()
+                .filter(|m| m.name != "hdr" && m.name != "padding"),
+        )?;
+        if let Some(trailing) = trailing {
+            let (element, _) = self.field_type(owner, &trailing.type_name)?;
+            fields.push(IdlField {
+                name: trailing.name.clone(),
+                type_name: self.idl.vector(&element),
+                by_value: false,
+                description: trailing.description.clone(),
+            });
+        }
+        Ok(fields)
+    }
+
+    /// Fits a value to the spelled integer type of `owner`.
+    fn integer(&self, owner: &str, type_name: &str, value: i128) -> Result<i128, ApiGenError> {
+        self.idl.integer(type_name, value).ok_or_else(|| {
+            self.unsupported(
+                owner,
+                &format!("value {} does not fit a {}", value, type_name),
+            )
+        })
+    }
+
+    fn entries(
+        &self,
+        owner: &str,
+        type_name: &str,
+        entries: &[EnumEntry],
+    ) -> Result<Vec<IdlValue>, ApiGenError> {
+        entries
+            .iter()
+            .map(|entry| {
+                let value = self.value(owner, &entry.value)?;
+                Ok(IdlValue {
+                    name: entry.name.clone(),
+                    value: self.integer(owner, type_name, value)?,
+                })
+            })
+            .collect()
+    }
+
+    fn enum_type(&self, owner: &str, type_name: &str) -> Result<String, ApiGenError> {
+        self.idl
+            .primitive(type_name)
+            .filter(|_| type_name.starts_with(['u', 'i']))
+            .map(str::to_string)
+            .ok_or_else(|| self.unsupported(owner, "enums must have an integer type"))
+    }
+
+    fn protocol(
+        &self,
+        proto: &Protocol,
+        responses: &HashMap<&str, &Response>,
+    ) -> Result<IdlProtocol, ApiGenError> {
+        let mut methods = Vec::new();
+        for req in &proto.requests {
+            let owner = format!("{}::{}", proto.name, req.opcode.name);
+            let response = match &req.response {
+                Some(name) => {
+                    let resp = responses
+                        .get(name.as_str())
+                        .ok_or_else(|| self.unsupported(&owner, "its response is not exported"))?;
+                    let name = to_pascal_case(name);
+                    Some(IdlStruct {
+                        name: if name.ends_with("Resp") {
+                            name
+                        } else {
+                            format!("{}Resp", name)
+                        },
+                        fields: self.command_fields(&owner, &resp.members, &resp.trailing)?,
+                        description: resp.description.clone(),
+                    })
+                }
+                None => None,
+            };
+            methods.push(IdlMethod {
+                name: req.opcode.name.clone(),
+                request: self.command_fields(&owner, &req.members, &req.trailing)?,
+                response,
+                description: req.description.clone(),
+            });
+        }
+        let events = proto
+            .events
+            .iter()
+            .map(|event| {
+                let owner = format!("{}::{}", proto.name, event.opcode.name);
+                Ok(IdlMethod {
+                    name: event.opcode.name.clone(),
+                    request: self.command_fields(&owner, &event.members, &event.trailing)?,
+                    response: None,
+                    description: event.description.clone(),
+                })
+            })
+            .collect::<Result<_, ApiGenError>>()?;
+        Ok(IdlProtocol {
+            name: proto.name.clone(),
+            methods,
+            events,
+        })
+    }
+}
+
+/// Translates the items instantiated by `gen_file` into `idl`: constants, enums, flags, structs
+/// and protocols, whose requests become methods returning the fields of their response. Protocol
+/// headers are left out, as are objects, functions and extensible structs, which have no
+/// equivalent.
+pub fn idl_file(api: &Api, gen_file: &GeneratedFile, idl: Idl) -> Result<IdlFile, ApiGenError> {
+    let items: Vec<DefinitionItem> = gen_file
+        .instantiations
+        .iter()
+        .filter_map(|def_name| api.definitions().get(def_name))
+        .flat_map(|def| def.items.iter())
+        .filter_map(|item_name| versioned_item(api, gen_file, item_name))
+        .collect();
+    let headers: HashSet<&str> = api
+        .definition_items()
+        .values()
+        .filter_map(|item| match item {
+            DefinitionItem::Protocol(proto) => Some(proto.protocol_struct_name.as_str()),
+            _ => None,
+        })
+        .collect();
+    // Requests of an extension may be answered by a response of its base protocol, which need
+    // not be instantiated along with it.
+    let responses: HashMap<&str, &Response> = api
+        .definition_items()
+        .values()
+        .filter_map(|item| match item {
+            DefinitionItem::Protocol(proto) => Some(&proto.responses),
+            _ => None,
+        })
+        .flatten()
+        .map(|resp| (resp.opcode.name.as_str(), resp))
+        .collect();
+
+    let mut builder = IdlBuilder {
+        api,
+        idl,
+        constants: HashMap::new(),
+    };
+    let mut file = IdlFile {
+        constants: Vec::new(),
+        enums: Vec::new(),
+        structs: Vec::new(),
+        protocols: Vec::new(),
+    };
+    for item in &items {
+        match item {
+            DefinitionItem::Constant(constant) => {
+                let value = builder.value(&constant.name, &constant.value)?;
+                builder.constants.insert(constant.name.clone(), value);
+                let type_name = if constant.type_name.is_empty() {
+                    "u32"
+                } else {
+                    &constant.type_name
+                };
+                let (type_name, _) = builder.field_type(&constant.name, type_name)?;
+                file.constants.push(IdlConstant {
+                    name: constant.name.clone(),
+                    value: builder.integer(&constant.name, &type_name, value)?,
+                    type_name,
+                });
+            }
+            DefinitionItem::Enum(e) => {
+                let type_name = builder.enum_type(&e.name, &e.type_name)?;
+                file.enums.push(IdlEnum {
+                    name: e.name.clone(),
+                    bits: false,
+                    entries: builder.entries(&e.name, &type_name, &e.entries)?,
+                    type_name,
+                    description: e.description.clone(),
+                })
+            }
+            DefinitionItem::Flag(flag) => {
+                let type_name = builder.enum_type(&flag.name, &flag.type_name)?;
+                file.enums.push(IdlEnum {
+                    name: flag.name.clone(),
+                    bits: true,
+                    entries: builder.entries(&flag.name, &type_name, &flag.entries)?,
+                    type_name,
+                    description: Vec::new(),
+                })
+            }
+            DefinitionItem::Struct(s) if !headers.contains(s.common.name.as_str()) => {
+                file.structs.push(IdlStruct {
+                    name: s.common.name.clone(),
+                    fields: builder.fields(&s.common.name, s.common.members.iter())?,
+                    description: s.common.description.clone(),
+                })
+            }
+            DefinitionItem::Protocol(proto) => {
+                file.protocols.push(builder.protocol(proto, &responses)?)
+            }
+            _ => {}
+        }
+    }
+    Ok(file)
+}
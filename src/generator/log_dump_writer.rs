@@ -0,0 +1,45 @@
+// Copyright 2025 Google
+// SPDX-License-Identifier: MIT
+
+use std::io::Write;
+
+use minijinja::{context, Environment};
+
+use crate::common::utils::to_pascal_case;
+use crate::common::*;
+use crate::generator::types::{include_uses, instantiated_items, template_loader, Writer};
+
+/// Emits a module printing command log captures, naming the opcodes of the instantiated
+/// protocols.
+pub struct LogDumpWriter;
+
+impl Writer for LogDumpWriter {
+    fn write(
+        &self,
+        api: &Api,
+        gen_file: &GeneratedFile,
+        output: &mut dyn Write,
+    ) -> Result<(), ApiGenError> {
+        let mut env = Environment::new();
+        env.set_loader(template_loader(api));
+        env.add_filter("pascal_case", to_pascal_case);
+
+        let tmpl = env.get_template("log_dump/file.jinja")?;
+        write!(
+            output,
+            "{}",
+            tmpl.render(context! {
+                year => api.copyright().year,
+                holder => api.copyright().holder,
+                spdx => api.copyright().spdx,
+                banner => api.copyright().banner,
+                api_name => api.name(),
+                defs => instantiated_items(api, gen_file)?,
+                uses => include_uses(api, gen_file)?,
+                reexport => gen_file.reexport,
+            })?
+        )?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,103 @@
+// Copyright 2025 Google
+// SPDX-License-Identifier: MIT
+
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use crate::common::*;
+
+const MANIFEST_HEADER: &str = "# Files generated by apigen-xml, do not edit.\n\
+     # Files listed here but no longer generated are deleted by --prune.\n\
+     # path\tfile_type\tdefinitions\tfnv1a_64\n";
+
+/// A file written by the generator, as recorded in the manifest.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ManifestEntry {
+    /// Path relative to the output directory.
+    pub path: PathBuf,
+    pub file_type: String,
+    /// Definitions instantiated by the generated file the output comes from.
+    pub definitions: Vec<String>,
+    /// FNV-1a hash of the contents.
+    pub hash: u64,
+}
+
+impl ManifestEntry {
+    pub fn new(path: PathBuf, gen_file: &GeneratedFile, contents: &[u8]) -> Self {
+        ManifestEntry {
+            path,
+            file_type: gen_file.file_type.clone(),
+            definitions: gen_file.instantiations.clone(),
+            hash: fnv1a_64(contents),
+        }
+    }
+}
+
+/// Computes the 64-bit FNV-1a hash of some bytes.
+pub fn fnv1a_64(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, b| {
+        (hash ^ *b as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+/// Returns the path of the manifest recording the files generated for an API in `out_dir`. It is
+/// named after the API so several APIs can share an output directory.
+pub fn manifest_path(api: &Api, out_dir: &Path) -> PathBuf {
+    out_dir.join(format!("{}.apigen-manifest", api.name()))
+}
+
+/// Reads the entries of a manifest. A missing file yields an empty list.
+pub fn read_manifest(path: &Path) -> Result<Vec<ManifestEntry>, ApiGenError> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err.into()),
+    };
+
+    let mut entries = Vec::new();
+    for (index, line) in content.lines().enumerate() {
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let invalid = |message: &str| ApiGenError::InvalidManifest {
+            file: path.display().to_string(),
+            line: index + 1,
+            message: message.to_string(),
+        };
+        let fields: Vec<&str> = line.split('\t').collect();
+        let [path, file_type, definitions, hash] = fields.as_slice() else {
+            return Err(invalid("expected four tab-separated fields"));
+        };
+        let hash = hash
+            .strip_prefix("0x")
+            .and_then(|hash| u64::from_str_radix(hash, 16).ok())
+            .ok_or_else(|| invalid("hash is not a hexadecimal u64"))?;
+        entries.push(ManifestEntry {
+            path: PathBuf::from(path),
+            file_type: file_type.to_string(),
+            definitions: definitions
+                .split(',')
+                .filter(|name| !name.is_empty())
+                .map(str::to_string)
+                .collect(),
+            hash,
+        });
+    }
+    Ok(entries)
+}
+
+/// Renders the manifest contents, one tab-separated line per entry.
+pub fn render_manifest(entries: &[ManifestEntry]) -> String {
+    let mut content = MANIFEST_HEADER.to_string();
+    for entry in entries {
+        content.push_str(&format!(
+            "{}\t{}\t{}\t{:#018x}\n",
+            entry.path.display(),
+            entry.file_type,
+            entry.definitions.join(","),
+            entry.hash
+        ));
+    }
+    content
+}
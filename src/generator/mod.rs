@@ -1,14 +1,24 @@
 // Copyright 2025 Google
 // SPDX-License-Identifier: MIT
 
+mod aidl_writer;
+mod conformance_writer;
+mod constants_writer;
 mod decoder_writer;
+mod dump_writer;
 mod encoder_writer;
 mod ffi_writer;
+mod fidl_writer;
+mod fixture_writer;
 mod header_writer;
+mod idl;
+mod log_dump_writer;
+mod manifest;
 mod protocol_writer;
 mod rust_writer;
+mod state_diagram_writer;
 mod types;
 
 mod writer;
 
-pub use writer::generate_api;
+pub use writer::{check_api, generate_api, generate_api_to_map};
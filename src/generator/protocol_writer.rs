@@ -1,14 +1,17 @@
 // Copyright 2025 Google
 // SPDX-License-Identifier: MIT
 
-use std::fs::File;
 use std::io::Write;
 
 use minijinja::{context, Environment};
 
-use crate::common::utils::{split, to_pascal_case};
+use crate::common::utils::{
+    escape_rust_keyword, split, split_array_type, to_pascal_case, to_snake_case,
+};
 use crate::common::*;
-use crate::generator::types::Writer;
+use crate::generator::types::{
+    include_uses, instantiated_items, part_declarations, template_loader, Writer,
+};
 
 pub struct ProtocolWriter;
 
@@ -17,27 +20,54 @@ impl Writer for ProtocolWriter {
         &self,
         api: &Api,
         gen_file: &GeneratedFile,
-        output: &mut File,
+        output: &mut dyn Write,
     ) -> Result<(), ApiGenError> {
         let mut env = Environment::new();
-        env.set_loader(minijinja::path_loader("src/generator/templates"));
+        env.set_loader(template_loader(api));
+        env.add_filter("ident", escape_rust_keyword);
+        env.add_global("strict_units", api.strict_units());
+        env.add_global("target", minijinja::Value::from_serialize(gen_file.target));
+        env.add_global(
+            "small_command_limit",
+            minijinja::Value::from_serialize(gen_file.small_command_limit),
+        );
+        env.add_global(
+            "doc_module",
+            minijinja::Value::from_serialize(&gen_file.doc_module),
+        );
         env.add_filter("pascal_case", to_pascal_case);
+        env.add_filter("snake_case", to_snake_case);
         env.add_filter("split", split);
 
         let tmpl = env.get_template("protocol/file.jinja")?;
 
-        let defs: Vec<&DefinitionItem> = gen_file
-            .instantiations
-            .iter()
-            .filter_map(|def_name| {
-                api.definitions().get(def_name).map(|def| {
-                    def.items
-                        .iter()
-                        .filter_map(|item_name| api.definition_items().get(item_name))
-                })
-            })
-            .flatten()
-            .collect();
+        let mut defs = instantiated_items(api, gen_file)?;
+        for def in &mut defs {
+            raw_enum_members(api, def);
+        }
+
+        // Flags, and enums instantiated in another file, are still members of the structs
+        // instantiated here and need their Endian conversion from this file's trait.
+        let mut referenced: Vec<&str> =
+            defs.iter().flat_map(|def| def.referenced_names()).collect();
+        referenced.sort_unstable();
+        referenced.dedup();
+        let mut endian_flags = Vec::new();
+        let mut endian_enums = Vec::new();
+        for name in referenced {
+            match api.definition_items().get(name) {
+                Some(DefinitionItem::Flag(flag)) => endian_flags.push(flag),
+                Some(DefinitionItem::Enum(e))
+                    if e.unknown_values == UnknownValues::Variant
+                        && !defs.iter().any(
+                            |def| matches!(def, DefinitionItem::Enum(d) if d.name == e.name),
+                        ) =>
+                {
+                    endian_enums.push(e)
+                }
+                _ => {}
+            }
+        }
 
         write!(
             output,
@@ -46,9 +76,61 @@ impl Writer for ProtocolWriter {
                 year => api.copyright().year,
                 holder => api.copyright().holder,
                 spdx => api.copyright().spdx,
+                banner => api.copyright().banner,
+                api_name => api.name(),
+                version => api.version(),
+                has_deprecations => api.has_deprecations(),
+                has_constraints => defs.iter().any(|def| def.has_constraints()),
+                has_enum_members => defs.iter().any(|def| def.has_enum_members()),
+                has_handles => defs.iter().any(|def| def.has_handles()),
+                has_payloads => defs.iter().any(|def| def.has_payloads()),
+                has_state_machines => defs.iter().any(|def| def.has_state_machine()),
                 defs => defs,
+                endian_flags => endian_flags,
+                endian_enums => endian_enums,
+                uses => include_uses(api, gen_file)?,
+                reexport => gen_file.reexport,
+                part => gen_file.part,
+                parts => part_declarations(api, gen_file),
+                command_log => gen_file.command_log,
+                client => gen_file.client,
+                connection => gen_file.connection && gen_file.target != RustTarget::NoStd,
             })?
         )?;
         Ok(())
     }
 }
+
+/// Keeps struct members declared with an enum type as the enum's integer, like command members,
+/// with accessors converting to the enum. Their `Endian` conversion then byte-swaps an integer
+/// instead of building the enum from a byte-swapped value, which is not a variant on big-endian
+/// hosts.
+fn raw_enum_members(api: &Api, def: &mut DefinitionItem) {
+    let member_lists: Vec<&mut Vec<Member>> = match def {
+        DefinitionItem::Struct(s) => vec![&mut s.common.members],
+        DefinitionItem::ExtensibleStruct(s) => vec![&mut s.common.members],
+        DefinitionItem::ExtensibleStructs(collection) => {
+            let mut lists = vec![&mut collection.protocol_struct.members];
+            lists.extend(collection.structs.iter_mut().map(|s| &mut s.common.members));
+            lists
+        }
+        _ => return,
+    };
+    let repr = |type_name: &str| match api.definition_items().get(type_name) {
+        Some(DefinitionItem::Enum(e)) => Some(e.type_name.clone()),
+        Some(DefinitionItem::ExtensibleStructs(_)) => Some("u32".to_string()),
+        _ => None,
+    };
+    for member in member_lists.into_iter().flatten() {
+        if let Some((element, len)) = split_array_type(&member.type_name) {
+            if let Some(repr) = repr(element) {
+                member.type_name = format!("[{}; {}]", repr, len);
+            }
+        } else if let Some(repr) = repr(&member.type_name) {
+            if let Some(default) = &mut member.default {
+                *default = format!("{}::from({})", repr, default);
+            }
+            member.enum_name = Some(std::mem::replace(&mut member.type_name, repr));
+        }
+    }
+}
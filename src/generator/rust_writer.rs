@@ -1,40 +1,79 @@
 // Copyright 2025 Google
 // SPDX-License-Identifier: MIT
 
-use std::fs::File;
+use std::collections::HashSet;
 use std::io::Write;
 
 use minijinja::{context, Environment};
 
+use crate::common::utils::{escape_rust_keyword, split_array_type};
 use crate::common::*;
-use crate::generator::types::Writer;
+use crate::generator::types::{
+    include_uses, instantiated_items, part_declarations, template_loader, Writer,
+};
 
 pub struct RustWriter;
 
+/// Spells a member type in a builder struct: builders carry an `'a` lifetime, so struct and union
+/// types and typedefs of them, including array elements, are named with it.
+fn builder_type(structs: &HashSet<String>, type_name: &str) -> String {
+    match split_array_type(type_name) {
+        Some((element, len)) => format!("[{}; {}]", builder_type(structs, element), len),
+        None if structs.contains(type_name) => format!("{}<'a>", type_name),
+        None => type_name.to_string(),
+    }
+}
+
 impl Writer for RustWriter {
     fn write(
         &self,
         api: &Api,
         gen_file: &GeneratedFile,
-        output: &mut File,
+        output: &mut dyn Write,
     ) -> Result<(), ApiGenError> {
         let mut env = Environment::new();
-        env.set_loader(minijinja::path_loader("src/generator/templates"));
-
-        let tmpl = env.get_template("rust/file.jinja")?;
-
-        let defs: Vec<&DefinitionItem> = gen_file
-            .instantiations
+        env.set_loader(template_loader(api));
+        env.add_filter("ident", escape_rust_keyword);
+        let mut structs: HashSet<String> = api
+            .definition_items()
             .iter()
-            .filter_map(|def_name| {
-                api.definitions().get(def_name).map(|def| {
-                    def.items
-                        .iter()
-                        .filter_map(|item_name| api.definition_items().get(item_name))
-                })
+            .filter(|(_, item)| {
+                matches!(
+                    item,
+                    DefinitionItem::Struct(_)
+                        | DefinitionItem::Union(_)
+                        | DefinitionItem::ExtensibleStruct(_)
+                )
             })
-            .flatten()
+            .map(|(name, _)| name.clone())
             .collect();
+        // Typedefs of such types carry the lifetime too, including typedefs of typedefs.
+        loop {
+            let aliases: Vec<String> = api
+                .definition_items()
+                .values()
+                .filter_map(|item| match item {
+                    DefinitionItem::Typedef(t)
+                        if !structs.contains(&t.name)
+                            && builder_type(&structs, &t.type_name) != t.type_name =>
+                    {
+                        Some(t.name.clone())
+                    }
+                    _ => None,
+                })
+                .collect();
+            if aliases.is_empty() {
+                break;
+            }
+            structs.extend(aliases);
+        }
+        env.add_filter("builder_type", move |type_name: String| {
+            builder_type(&structs, &type_name)
+        });
+
+        let tmpl = env.get_template("rust/file.jinja")?;
+
+        let defs = instantiated_items(api, gen_file)?;
 
         write!(
             output,
@@ -43,7 +82,14 @@ impl Writer for RustWriter {
                 year => api.copyright().year,
                 holder => api.copyright().holder,
                 spdx => api.copyright().spdx,
+                banner => api.copyright().banner,
+                has_deprecations => api.has_deprecations(),
+                has_constraints => defs.iter().any(|def| def.has_constraints()),
                 defs => defs,
+                uses => include_uses(api, gen_file)?,
+                reexport => gen_file.reexport,
+                part => gen_file.part,
+                parts => part_declarations(api, gen_file),
             })?
         )?;
         Ok(())
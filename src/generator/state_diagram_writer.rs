@@ -0,0 +1,40 @@
+// Copyright 2025 Google
+// SPDX-License-Identifier: MIT
+
+use std::io::Write;
+
+use minijinja::{context, Environment};
+
+use crate::common::*;
+use crate::generator::types::{instantiated_items, template_loader, Writer};
+
+/// Emits a Graphviz diagram of the state machines of the instantiated protocols.
+pub struct StateDiagramWriter;
+
+impl Writer for StateDiagramWriter {
+    fn write(
+        &self,
+        api: &Api,
+        gen_file: &GeneratedFile,
+        output: &mut dyn Write,
+    ) -> Result<(), ApiGenError> {
+        let mut env = Environment::new();
+        env.set_loader(template_loader(api));
+
+        let tmpl = env.get_template("state_diagram/file.jinja")?;
+        write!(
+            output,
+            "{}",
+            tmpl.render(context! {
+                year => api.copyright().year,
+                holder => api.copyright().holder,
+                spdx => api.copyright().spdx,
+                banner => api.copyright().banner,
+                api_name => api.name(),
+                defs => instantiated_items(api, gen_file)?,
+            })?
+        )?;
+
+        Ok(())
+    }
+}
@@ -1,10 +1,53 @@
 // Copyright 2025 Google
 // SPDX-License-Identifier: MIT
 
-use std::fs::File;
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
 
+use serde::Serialize;
+
+use crate::common::utils::{parse_int_literal, to_snake_case};
 use crate::common::*;
 
+/// The built-in templates by name, embedded by the build script so that the generator runs from
+/// any working directory and without its source tree.
+static BUILTIN_TEMPLATES: &[(&str, &str)] = include!(concat!(env!("OUT_DIR"), "/templates.rs"));
+
+fn builtin_template(name: &str) -> Result<Option<String>, minijinja::Error> {
+    Ok(BUILTIN_TEMPLATES
+        .iter()
+        .find(|(builtin, _)| *builtin == name)
+        .map(|(_, source)| source.to_string()))
+}
+
+/// Prefix naming a built-in template even when the template directory of the API overrides it, so
+/// that an override can extend the template it replaces.
+const BUILTIN_PREFIX: &str = "builtin/";
+
+/// Loads templates from the template directory of the API, if any, falling back to the built-in
+/// templates for those it does not have. Names starting with `builtin/` always load a built-in
+/// template.
+pub fn template_loader(
+    api: &Api,
+) -> impl Fn(&str) -> Result<Option<String>, minijinja::Error> + Send + Sync + 'static {
+    let overrides = api
+        .template_dir()
+        .map(|dir| minijinja::path_loader(dir.to_path_buf()));
+    move |name| {
+        if let Some(name) = name.strip_prefix(BUILTIN_PREFIX) {
+            return builtin_template(name);
+        }
+        if let Some(overrides) = &overrides {
+            if let Some(source) = overrides(name)? {
+                return Ok(Some(source));
+            }
+        }
+        builtin_template(name)
+    }
+}
+
 pub enum FileType {
     Protocol,
     Header,
@@ -12,9 +55,18 @@ pub enum FileType {
     Rust,
     Encoder,
     Decoder,
+    Constants,
+    Fixtures,
+    Conformance,
+    LogDump,
+    Dump,
+    Fidl,
+    Aidl,
+    StateDiagram,
 }
 
 impl FileType {
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(s: &str) -> Option<FileType> {
         match s {
             "protocol" => Some(FileType::Protocol),
@@ -23,12 +75,325 @@ impl FileType {
             "Rust" => Some(FileType::Rust),
             "encoder" => Some(FileType::Encoder),
             "decoder" => Some(FileType::Decoder),
+            "constants" => Some(FileType::Constants),
+            "fixtures" => Some(FileType::Fixtures),
+            "conformance" => Some(FileType::Conformance),
+            "log_dump" => Some(FileType::LogDump),
+            "dump" => Some(FileType::Dump),
+            "fidl" => Some(FileType::Fidl),
+            "aidl" => Some(FileType::Aidl),
+            "state_diagram" => Some(FileType::StateDiagram),
             _ => None,
         }
     }
 }
 
 pub trait Writer {
-    fn write(&self, api: &Api, gen_file: &GeneratedFile, out: &mut File)
-        -> Result<(), ApiGenError>;
+    fn write(
+        &self,
+        api: &Api,
+        gen_file: &GeneratedFile,
+        out: &mut dyn io::Write,
+    ) -> Result<(), ApiGenError>;
+
+    /// Writes the main output like `write` and returns the files emitted alongside it, keyed by
+    /// their path relative to the generated file's `out_path`. Writers deriving both from one
+    /// model override this to build the model once.
+    fn write_with_extra_files(
+        &self,
+        api: &Api,
+        gen_file: &GeneratedFile,
+        out: &mut dyn io::Write,
+    ) -> Result<Vec<(PathBuf, Vec<u8>)>, ApiGenError> {
+        self.write(api, gen_file, out)?;
+        Ok(Vec::new())
+    }
+}
+
+fn file_path(gen_file: &GeneratedFile) -> PathBuf {
+    Path::new(&gen_file.out_path).join(&gen_file.file_name)
+}
+
+/// Resolves the `<include>` entries of a generated file. An include names another generated file
+/// of the same API, either by `file_name` or, when that is ambiguous, by `out_path/file_name`.
+pub fn included_files<'a>(
+    api: &'a Api,
+    gen_file: &GeneratedFile,
+) -> Result<Vec<&'a GeneratedFile>, ApiGenError> {
+    let invalid = |include: &str, reason: &str| ApiGenError::InvalidInclude {
+        file: gen_file.file_name.clone(),
+        include: include.to_string(),
+        reason: reason.to_string(),
+    };
+
+    gen_file
+        .includes
+        .iter()
+        .map(|include| {
+            let matches: Vec<&GeneratedFile> = api
+                .generated_files()
+                .iter()
+                .filter(|f| f.file_name == *include || file_path(f) == Path::new(include))
+                .collect();
+            match matches.as_slice() {
+                [] => Err(invalid(include, "no generated file with that name")),
+                [f] if file_path(f) == file_path(gen_file) => {
+                    Err(invalid(include, "a file cannot include itself"))
+                }
+                [f] => Ok(*f),
+                _ => Err(invalid(include, "ambiguous, use out_path/file_name")),
+            }
+        })
+        .collect()
+}
+
+/// Returns the `crate::` path of a generated Rust module. `out_path` is taken to be relative to
+/// the crate root, with sources under `src/`.
+pub fn rust_module_path(gen_file: &GeneratedFile) -> Option<String> {
+    let path = file_path(gen_file);
+    let relative = path.strip_prefix("src").ok()?;
+    let mut segments = vec!["crate".to_string()];
+    for component in relative.parent()?.components() {
+        segments.push(component.as_os_str().to_str()?.to_string());
+    }
+    match relative.file_stem()?.to_str()? {
+        "lib" | "main" | "mod" => {}
+        stem => segments.push(stem.to_string()),
+    }
+    Some(segments.join("::"))
+}
+
+/// Returns the module names of a split file's parts, one per instantiated definition. Files that
+/// are not split have none.
+pub fn part_modules(gen_file: &GeneratedFile) -> Vec<String> {
+    match gen_file.split {
+        SplitMode::Single => Vec::new(),
+        SplitMode::PerDefinition => gen_file
+            .instantiations
+            .iter()
+            .map(|def_name| to_snake_case(def_name))
+            .collect(),
+    }
+}
+
+/// A `mod` declaration of a split file, compiled only with one of `features`, or `full`, enabled
+/// when it is not empty.
+#[derive(Serialize)]
+pub struct PartModule {
+    pub name: String,
+    pub features: Vec<String>,
+}
+
+/// Returns the `mod` declarations of a split file's parts. A definition holding protocol
+/// extensions and no base protocol is gated behind a cargo feature per extension, named after it
+/// in snake case, so that crates can compile out the extensions they do not support.
+pub fn part_declarations(api: &Api, gen_file: &GeneratedFile) -> Vec<PartModule> {
+    gen_file
+        .instantiations
+        .iter()
+        .zip(part_modules(gen_file))
+        .map(|(def_name, name)| {
+            let protocols: Vec<&Protocol> = api
+                .definitions()
+                .get(def_name)
+                .into_iter()
+                .flat_map(|def| &def.items)
+                .filter_map(|item| match api.definition_items().get(item) {
+                    Some(DefinitionItem::Protocol(proto)) => Some(proto),
+                    _ => None,
+                })
+                .collect();
+            let features = if protocols.iter().all(|proto| proto.extends.is_some()) {
+                protocols
+                    .iter()
+                    .map(|proto| to_snake_case(&proto.name))
+                    .collect()
+            } else {
+                Vec::new()
+            };
+            PartModule { name, features }
+        })
+        .collect()
+}
+
+/// Returns the cargo features gating the definitions of `gen_file`'s dependencies, keyed by
+/// definition, for files that must compile out the same protocol extensions.
+pub fn gated_definitions(
+    api: &Api,
+    gen_file: &GeneratedFile,
+) -> Result<HashMap<String, Vec<String>>, ApiGenError> {
+    let mut gated = HashMap::new();
+    for file in dependencies(api, gen_file)? {
+        for (def_name, part) in file.instantiations.iter().zip(part_declarations(api, file)) {
+            if !part.features.is_empty() {
+                gated.insert(def_name.clone(), part.features);
+            }
+        }
+    }
+    Ok(gated)
+}
+
+/// Derives the part files of a split generated file. Parts live where the file's `mod`
+/// declarations look for them and keep its includes, so items shared with included files are
+/// still left to those files.
+pub fn split_parts(gen_file: &GeneratedFile) -> Result<Vec<GeneratedFile>, ApiGenError> {
+    let modules = part_modules(gen_file);
+    if modules.is_empty() {
+        return Ok(Vec::new());
+    }
+    if !matches!(
+        FileType::from_str(&gen_file.file_type),
+        Some(FileType::Rust | FileType::Protocol)
+    ) {
+        return Err(ApiGenError::InvalidAttributeValue {
+            attribute: format!(
+                "split of {} file {}",
+                gen_file.file_type, gen_file.file_name
+            ),
+            value: "per-definition".to_string(),
+        });
+    }
+
+    let out_path = Path::new(&gen_file.out_path);
+    let dir = match Path::new(&gen_file.file_name).file_stem() {
+        Some(stem) if !matches!(stem.to_str(), Some("lib" | "main" | "mod")) => out_path.join(stem),
+        _ => out_path.to_path_buf(),
+    };
+    Ok(gen_file
+        .instantiations
+        .iter()
+        .zip(modules)
+        .map(|(def_name, module)| GeneratedFile {
+            out_path: dir.to_string_lossy().into_owned(),
+            file_name: format!("{}.rs", module),
+            references: Vec::new(),
+            instantiations: vec![def_name.clone()],
+            split: SplitMode::Single,
+            part: true,
+            ..gen_file.clone()
+        })
+        .collect())
+}
+
+/// Returns the files a generated file depends on: its includes, then the files instantiating the
+/// types it references, without duplicates.
+pub fn dependencies<'a>(
+    api: &'a Api,
+    gen_file: &GeneratedFile,
+) -> Result<Vec<&'a GeneratedFile>, ApiGenError> {
+    let mut files = included_files(api, gen_file)?;
+    for reference in &gen_file.references {
+        let referenced = api
+            .generated_files()
+            .iter()
+            .filter(|f| file_path(f) == Path::new(reference));
+        for file in referenced {
+            if !files.iter().any(|f| file_path(f) == file_path(file)) {
+                files.push(file);
+            }
+        }
+    }
+    Ok(files)
+}
+
+/// Returns what a Rust output must `use`: the items of its dependencies' modules, and the
+/// external types its items refer to, under their declared names.
+pub fn include_uses(api: &Api, gen_file: &GeneratedFile) -> Result<Vec<String>, ApiGenError> {
+    let mut uses = dependencies(api, gen_file)?
+        .into_iter()
+        .map(|included| {
+            rust_module_path(included)
+                .map(|module| format!("{}::*", module))
+                .ok_or_else(|| ApiGenError::InvalidInclude {
+                    file: gen_file.file_name.clone(),
+                    include: included.file_name.clone(),
+                    reason: "out_path is not under src/".to_string(),
+                })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let referenced: HashSet<&str> = api
+        .instantiated_item_names(gen_file)
+        .filter_map(|name| api.definition_items().get(name))
+        .flat_map(DefinitionItem::referenced_names)
+        .collect();
+    uses.extend(
+        api.external_types()
+            .iter()
+            .filter(|t| t.rust != t.name && referenced.contains(t.name.as_str()))
+            .map(|t| format!("{} as {}", t.rust, t.name)),
+    );
+    Ok(uses)
+}
+
+/// Returns the `#include` paths of a C header's dependencies, relative to the header itself.
+pub fn header_includes(api: &Api, gen_file: &GeneratedFile) -> Result<Vec<String>, ApiGenError> {
+    let from: Vec<_> = Path::new(&gen_file.out_path).components().collect();
+    Ok(dependencies(api, gen_file)?
+        .into_iter()
+        .map(|included| {
+            let to: Vec<_> = Path::new(&included.out_path).components().collect();
+            let common = from.iter().zip(&to).take_while(|(a, b)| a == b).count();
+            let mut path = PathBuf::new();
+            for _ in common..from.len() {
+                path.push("..");
+            }
+            path.extend(&to[common..]);
+            path.push(&included.file_name);
+            path.to_string_lossy().into_owned()
+        })
+        .collect())
+}
+
+/// Returns an item as a generated file sees it: `None` when the item is unknown or introduced after
+/// the file's `max_version`, otherwise the item without the opcodes and values introduced later.
+pub fn versioned_item(
+    api: &Api,
+    gen_file: &GeneratedFile,
+    item_name: &str,
+) -> Option<DefinitionItem> {
+    let mut item = api.definition_items().get(item_name)?.clone();
+    if let Some(max_version) = gen_file.max_version {
+        if api.item_since(item_name) > max_version {
+            return None;
+        }
+        item.restrict_to_version(max_version);
+    }
+    Some(item)
+}
+
+/// Collects the items instantiated by a generated file, in the file's configured order. Items
+/// already emitted by an included file of the same type are left to that file.
+pub fn instantiated_items(
+    api: &Api,
+    gen_file: &GeneratedFile,
+) -> Result<Vec<DefinitionItem>, ApiGenError> {
+    let shared: HashSet<&String> = included_files(api, gen_file)?
+        .into_iter()
+        .filter(|included| included.file_type == gen_file.file_type)
+        .flat_map(|included| api.instantiated_item_names(included))
+        .collect();
+
+    let mut items: Vec<DefinitionItem> = api
+        .instantiated_item_names(gen_file)
+        .filter(|item_name| !shared.contains(item_name))
+        .filter_map(|item_name| versioned_item(api, gen_file, item_name))
+        .collect();
+
+    // All sorts are stable, so equal items keep their declaration order.
+    match gen_file.order {
+        ItemOrder::Declaration => {}
+        ItemOrder::Alphabetical => items.sort_by(|a, b| a.name().cmp(b.name())),
+        ItemOrder::Kind => items.sort_by_key(|item| item.kind_rank()),
+        ItemOrder::Opcode => {
+            let value = |opcode: &Opcode| parse_int_literal(&opcode.value).unwrap_or_default();
+            for item in &mut items {
+                if let DefinitionItem::Protocol(proto) = item {
+                    proto.requests.sort_by_key(|r| value(&r.opcode));
+                    proto.responses.sort_by_key(|r| value(&r.opcode));
+                    proto.events.sort_by_key(|e| value(&e.opcode));
+                }
+            }
+        }
+    }
+    Ok(items)
 }
@@ -1,40 +1,236 @@
 // Copyright 2025 Google
 // SPDX-License-Identifier: MIT
 
-use std::fs::{create_dir_all, File};
-use std::path::Path;
+use std::collections::HashMap;
+use std::env;
+use std::fs::{self, create_dir_all};
+use std::path::{Component, Path, PathBuf};
+use std::process::{self, Command};
 
 use crate::common::*;
+use crate::generator::aidl_writer::AidlWriter;
+use crate::generator::conformance_writer::ConformanceWriter;
+use crate::generator::constants_writer::ConstantsWriter;
 use crate::generator::decoder_writer::DecoderWriter;
+use crate::generator::dump_writer::DumpWriter;
 use crate::generator::encoder_writer::EncoderWriter;
 use crate::generator::ffi_writer::FfiWriter;
+use crate::generator::fidl_writer::FidlWriter;
+use crate::generator::fixture_writer::FixtureWriter;
 use crate::generator::header_writer::HeaderWriter;
+use crate::generator::log_dump_writer::LogDumpWriter;
+use crate::generator::manifest::{manifest_path, read_manifest, render_manifest, ManifestEntry};
 use crate::generator::protocol_writer::ProtocolWriter;
 use crate::generator::rust_writer::RustWriter;
-use crate::generator::types::{FileType, Writer};
+use crate::generator::state_diagram_writer::StateDiagramWriter;
+use crate::generator::types::{split_parts, FileType, Writer};
 
-pub fn generate_api(api: &Api, out_dir: &Path) -> Result<(), ApiGenError> {
+fn writer_for(file_type: &str) -> Option<Box<dyn Writer>> {
+    match FileType::from_str(file_type)? {
+        FileType::Protocol => Some(Box::new(ProtocolWriter)),
+        FileType::Header => Some(Box::new(HeaderWriter)),
+        FileType::Ffi => Some(Box::new(FfiWriter)),
+        FileType::Rust => Some(Box::new(RustWriter)),
+        FileType::Encoder => Some(Box::new(EncoderWriter)),
+        FileType::Decoder => Some(Box::new(DecoderWriter)),
+        FileType::Constants => Some(Box::new(ConstantsWriter)),
+        FileType::Fixtures => Some(Box::new(FixtureWriter)),
+        FileType::Conformance => Some(Box::new(ConformanceWriter)),
+        FileType::LogDump => Some(Box::new(LogDumpWriter)),
+        FileType::Dump => Some(Box::new(DumpWriter)),
+        FileType::Fidl => Some(Box::new(FidlWriter)),
+        FileType::Aidl => Some(Box::new(AidlWriter)),
+        FileType::StateDiagram => Some(Box::new(StateDiagramWriter)),
+    }
+}
+
+/// Temporary directory removed with its contents when dropped, whether post-processing succeeded
+/// or not.
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new() -> Result<Self, ApiGenError> {
+        let dir = env::temp_dir().join(format!("apigen-xml-{}", process::id()));
+        create_dir_all(&dir)?;
+        Ok(TempDir(dir))
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Runs `command` through `sh -c` with the path of a temporary copy of `contents` appended and
+/// returns the copy as the command left it, so formatters can rewrite it in place and checkers can
+/// reject it. The shell handles quoting, e.g. `rustfmt --config "max_width=120"`.
+fn post_process(command: &str, path: &Path, contents: Vec<u8>) -> Result<Vec<u8>, ApiGenError> {
+    let failure = |message: String| ApiGenError::PostProcess {
+        file: path.display().to_string(),
+        message,
+    };
+    if command.trim().is_empty() {
+        return Err(failure("empty command".to_string()));
+    }
+
+    // Keep the file name, tools often pick their behavior from the extension.
+    let dir = TempDir::new()?;
+    let tmp_path = dir.0.join(path.file_name().unwrap_or_default());
+    fs::write(&tmp_path, contents)?;
+
+    // The path is passed as `$1` rather than spliced into the command, so it needs no quoting.
+    let output = Command::new("sh")
+        .arg("-c")
+        .arg(format!("{} \"$1\"", command))
+        .arg("sh")
+        .arg(&tmp_path)
+        .output();
+    match output {
+        Ok(output) if output.status.success() => Ok(fs::read(&tmp_path)?),
+        Ok(output) => Err(failure(format!(
+            "`{}` exited with {}: {}",
+            command,
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))),
+        Err(e) => Err(failure(format!("cannot run `{}`: {}", command, e))),
+    }
+}
+
+/// Renders every generated file in memory along with its manifest entry. Split files are
+/// rendered along with their parts. The API's post-processing command, if any, runs on each main
+/// output and part; extra files such as fixtures are kept as rendered.
+fn render_outputs(api: &Api) -> Result<Vec<(ManifestEntry, Vec<u8>)>, ApiGenError> {
+    let mut files = Vec::new();
     for gen_file in api.generated_files() {
-        let full_out_path = out_dir.join(&gen_file.out_path);
-        create_dir_all(&full_out_path)?;
-        let output_path = full_out_path.join(&gen_file.file_name);
-        let mut file = File::create(output_path)?;
-
-        let writer: Box<dyn Writer> = match FileType::from_str(&gen_file.file_type) {
-            Some(FileType::Protocol) => Box::new(ProtocolWriter),
-            Some(FileType::Header) => Box::new(HeaderWriter),
-            Some(FileType::Ffi) => Box::new(FfiWriter),
-            Some(FileType::Rust) => Box::new(RustWriter),
-            Some(FileType::Encoder) => Box::new(EncoderWriter),
-            Some(FileType::Decoder) => Box::new(DecoderWriter),
-            None => {
-                // Handle unknown file type
-                continue;
-            }
+        // Unknown file types are skipped.
+        let Some(writer) = writer_for(&gen_file.file_type) else {
+            continue;
         };
+        let named = api.named_for(&gen_file.file_type)?;
+        let api = named.as_ref().unwrap_or(api);
+
+        let mut extra_files = Vec::new();
+        for file in std::iter::once(gen_file.clone()).chain(split_parts(gen_file)?) {
+            let mut contents = Vec::new();
+            if file.part {
+                writer.write(api, &file, &mut contents)?;
+            } else {
+                extra_files = writer.write_with_extra_files(api, &file, &mut contents)?;
+            }
+            let path = Path::new(&file.out_path).join(&file.file_name);
+            if let Some(command) = api.post_process() {
+                contents = post_process(command, &path, contents)?;
+            }
+            files.push((ManifestEntry::new(path, &file, &contents), contents));
+        }
+        let out_path = Path::new(&gen_file.out_path);
+        for (path, contents) in extra_files {
+            let entry = ManifestEntry::new(out_path.join(path), gen_file, &contents);
+            files.push((entry, contents));
+        }
+    }
+    Ok(files)
+}
+
+/// Renders every generated file in memory, keyed by its path relative to the output directory.
+pub fn generate_api_to_map(api: &Api) -> Result<HashMap<PathBuf, Vec<u8>>, ApiGenError> {
+    Ok(render_outputs(api)?
+        .into_iter()
+        .map(|(entry, contents)| (entry.path, contents))
+        .collect())
+}
+
+/// Returns true if `path` stays under the directory it is joined to: it is relative and has no
+/// `..` component.
+fn is_contained(path: &Path) -> bool {
+    path.components()
+        .all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+}
+
+/// Returns the entries of the API's manifest under `out_dir` whose files still exist but are not
+/// among `paths`. Entries pointing outside `out_dir`, which generation never writes, are skipped
+/// so that an edited manifest cannot make a prune delete other files.
+fn removed_outputs<'a>(
+    api: &Api,
+    out_dir: &Path,
+    paths: impl Iterator<Item = &'a PathBuf> + Clone,
+) -> Result<Vec<ManifestEntry>, ApiGenError> {
+    Ok(read_manifest(&manifest_path(api, out_dir))?
+        .into_iter()
+        .filter(|entry| {
+            is_contained(&entry.path)
+                && !paths.clone().any(|path| *path == entry.path)
+                && out_dir.join(&entry.path).exists()
+        })
+        .collect())
+}
+
+/// Writes every generated file under `out_dir` and records them in the API's manifest, along
+/// with their file types, source definitions and content hashes. Returns the files an earlier
+/// run generated that are no longer produced. With `prune` they are deleted, along with
+/// directories left empty; otherwise they stay in the manifest so that a later prune still finds
+/// them.
+pub fn generate_api(api: &Api, out_dir: &Path, prune: bool) -> Result<Vec<PathBuf>, ApiGenError> {
+    let files = render_outputs(api)?;
+    let removed = removed_outputs(api, out_dir, files.iter().map(|(entry, _)| &entry.path))?;
+    let mut listed = Vec::new();
 
-        writer.write(api, gen_file, &mut file)?;
+    for (entry, contents) in files {
+        let output_path = out_dir.join(&entry.path);
+        if let Some(parent) = output_path.parent() {
+            create_dir_all(parent)?;
+        }
+        fs::write(output_path, contents)?;
+        listed.push(entry);
     }
 
-    Ok(())
+    if prune {
+        for entry in &removed {
+            let output_path = out_dir.join(&entry.path);
+            fs::remove_file(&output_path)?;
+            // Only empty directories can be removed, so failures are expected and ignored.
+            for dir in output_path.ancestors().skip(1) {
+                if dir == out_dir || fs::remove_dir(dir).is_err() {
+                    break;
+                }
+            }
+        }
+    } else {
+        listed.extend(removed.iter().cloned());
+    }
+    listed.sort_by(|a, b| a.path.cmp(&b.path));
+    fs::write(manifest_path(api, out_dir), render_manifest(&listed))?;
+
+    Ok(removed
+        .into_iter()
+        .map(|entry| out_dir.join(entry.path))
+        .collect())
+}
+
+/// Returns the paths under `out_dir` whose contents are missing or differ from the freshly
+/// generated output, followed by files an earlier run generated that are no longer produced.
+/// With `fail_fast`, checking stops at the first stale file.
+pub fn check_api(api: &Api, out_dir: &Path, fail_fast: bool) -> Result<Vec<PathBuf>, ApiGenError> {
+    let files = generate_api_to_map(api)?;
+    let removed = removed_outputs(api, out_dir, files.keys())?;
+    let mut files: Vec<_> = files.into_iter().collect();
+    files.sort();
+
+    let mut stale = Vec::new();
+    for (path, expected) in files {
+        let output_path = out_dir.join(path);
+        if fs::read(&output_path).ok().as_deref() != Some(expected.as_slice()) {
+            stale.push(output_path);
+            if fail_fast {
+                return Ok(stale);
+            }
+        }
+    }
+    stale.extend(removed.into_iter().map(|entry| out_dir.join(entry.path)));
+    if fail_fast {
+        stale.truncate(1);
+    }
+    Ok(stale)
 }
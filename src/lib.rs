@@ -0,0 +1,6 @@
+// Copyright 2025 Google
+// SPDX-License-Identifier: MIT
+
+pub mod common;
+pub mod generator;
+pub mod parser;
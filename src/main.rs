@@ -2,18 +2,25 @@
 // SPDX-License-Identifier: MIT
 
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitCode};
 
 use clap::Parser;
 
-mod common;
-mod generator;
-mod parser;
+use apigen_xml::common::changelog::changelog;
+use apigen_xml::common::config::CopyrightOverrides;
+use apigen_xml::common::lockfile::{
+    frozen_lock_path, frozen_lock_up_to_date, opcode_lock_path, opcode_lock_up_to_date,
+    stype_lock_path, stype_lock_up_to_date, write_frozen_lock, write_opcode_lock, write_stype_lock,
+};
+use apigen_xml::common::ApiGenError;
+use apigen_xml::{generator, parser};
 
-#[allow(dead_code)]
+#[allow(dead_code, clippy::enum_variant_names)]
 mod generated_protocols;
 
-use common::ApiGenError;
+/// Marker identifying pre-commit hooks written by `--install-hook`.
+const HOOK_MARKER: &str = "# Installed by apigen-xml --install-hook";
 
 #[derive(Parser, Debug)]
 #[command(version, about = None, long_about = None)]
@@ -25,12 +32,206 @@ struct Args {
     /// The output directory for the generated Rust files
     #[arg(long)]
     out_dir: PathBuf,
+
+    /// Verify that the files in the output directory are up to date instead of writing them
+    #[arg(long)]
+    check: bool,
+
+    /// Like --check, but stops at the first stale file with a one-line message
+    #[arg(long, conflicts_with = "check")]
+    hook: bool,
+
+    /// Install a git pre-commit hook running --hook with the given filename and output directory
+    #[arg(long, conflicts_with_all = ["check", "hook"])]
+    install_hook: bool,
+
+    /// Command run on each generated file, with the file's path appended
+    #[arg(long)]
+    post_process: Option<String>,
+
+    /// Directory of templates used instead of the built-in ones of the same name
+    #[arg(long)]
+    template_dir: Option<PathBuf>,
+
+    /// Delete files an earlier run generated that are no longer produced
+    #[arg(long, conflicts_with_all = ["check", "hook"])]
+    prune: bool,
+
+    /// Print a Markdown changelog of the API since an earlier version of its XML file instead of
+    /// generating files
+    #[arg(long, value_name = "OLD_FILENAME", conflicts_with_all = ["check", "hook", "install_hook"])]
+    changelog: Option<PathBuf>,
+
+    #[command(flatten)]
+    copyright: CopyrightOverrides,
 }
 
-fn main() -> Result<(), ApiGenError> {
-    let args = Args::parse();
-    fs::create_dir_all(&args.out_dir)?;
-    let api_data = parser::parse_api(&args.filename)?;
-    generator::generate_api(&api_data, &args.out_dir)?;
+/// Quotes `arg` for the POSIX shell running the hook script.
+fn shell_quote(arg: &str) -> String {
+    format!("'{}'", arg.replace('\'', r"'\''"))
+}
+
+/// Arguments of the `--hook` run of the pre-commit hook: the options of this invocation that
+/// affect the generated files, with paths made absolute since git runs hooks from the top of the
+/// work tree.
+fn hook_args(args: &Args) -> Result<Vec<String>, ApiGenError> {
+    let mut hook_args = vec![
+        "--filename".to_string(),
+        fs::canonicalize(&args.filename)?.display().to_string(),
+        "--out-dir".to_string(),
+        std::path::absolute(&args.out_dir)?.display().to_string(),
+    ];
+    let copyright = &args.copyright;
+    if let Some(holder) = &copyright.holder {
+        hook_args.extend(["--copyright-holder".to_string(), holder.clone()]);
+    }
+    if let Some(year) = copyright.year {
+        hook_args.extend(["--copyright-year".to_string(), year.to_string()]);
+    }
+    if let Some(spdx) = &copyright.spdx {
+        hook_args.extend(["--spdx".to_string(), spdx.clone()]);
+    }
+    if let Some(banner) = &copyright.banner {
+        hook_args.extend([
+            "--banner".to_string(),
+            fs::canonicalize(banner)?.display().to_string(),
+        ]);
+    }
+    if let Some(command) = &args.post_process {
+        hook_args.extend(["--post-process".to_string(), command.clone()]);
+    }
+    if let Some(template_dir) = &args.template_dir {
+        hook_args.extend([
+            "--template-dir".to_string(),
+            fs::canonicalize(template_dir)?.display().to_string(),
+        ]);
+    }
+    hook_args.push("--hook".to_string());
+    Ok(hook_args)
+}
+
+fn install_hook(hook_args: &[String]) -> Result<(), ApiGenError> {
+    let output = Command::new("git")
+        .args(["rev-parse", "--git-path", "hooks"])
+        .output()?;
+    if !output.status.success() {
+        return Err(ApiGenError::HookInstall(
+            "not inside a git repository".to_string(),
+        ));
+    }
+    let hooks_dir = PathBuf::from(String::from_utf8_lossy(&output.stdout).trim());
+    let hook_path = hooks_dir.join("pre-commit");
+
+    if let Ok(existing) = fs::read_to_string(&hook_path) {
+        if !existing.contains(HOOK_MARKER) {
+            return Err(ApiGenError::HookInstall(format!(
+                "{} already exists",
+                hook_path.display()
+            )));
+        }
+    }
+
+    let mut command = vec![shell_quote(&std::env::current_exe()?.display().to_string())];
+    command.extend(hook_args.iter().map(|arg| shell_quote(arg)));
+    let script = format!("#!/bin/sh\n{}\nexec {}\n", HOOK_MARKER, command.join(" "));
+    fs::create_dir_all(&hooks_dir)?;
+    fs::write(&hook_path, script)?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&hook_path, fs::Permissions::from_mode(0o755))?;
+    }
     Ok(())
 }
+
+fn report_removed(path: &Path, pruned: bool) {
+    if pruned {
+        eprintln!("removed: {}", path.display());
+    } else {
+        eprintln!(
+            "warning: {} is no longer generated, --prune deletes it",
+            path.display()
+        );
+    }
+}
+
+fn main() -> ExitCode {
+    match run(&Args::parse()) {
+        Ok(code) => code,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(args: &Args) -> Result<ExitCode, ApiGenError> {
+    if args.install_hook {
+        install_hook(&hook_args(args)?)?;
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let mut api_data = parser::parse_api(&args.filename)?;
+    for warning in api_data.warnings() {
+        eprintln!("warning: {}", warning);
+    }
+    args.copyright.apply(&mut api_data)?;
+    api_data.set_post_process(args.post_process.clone());
+    api_data.set_template_dir(args.template_dir.clone());
+
+    if let Some(old_filename) = &args.changelog {
+        let old_api = parser::parse_api(old_filename)?;
+        print!("{}", changelog(&old_api, &api_data));
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    if args.check || args.hook {
+        let mut stale = generator::check_api(&api_data, &args.out_dir, args.hook)?;
+        let lock_path = stype_lock_path(&args.filename);
+        if (stale.is_empty() || !args.hook)
+            && !stype_lock_up_to_date(&lock_path, api_data.stype_lock())
+        {
+            stale.push(lock_path);
+        }
+        let opcode_path = opcode_lock_path(&args.filename);
+        if (stale.is_empty() || !args.hook)
+            && !opcode_lock_up_to_date(&opcode_path, api_data.opcode_lock())
+        {
+            stale.push(opcode_path);
+        }
+        let frozen_path = frozen_lock_path(&args.filename);
+        if (stale.is_empty() || !args.hook)
+            && !frozen_lock_up_to_date(&frozen_path, api_data.frozen_lock())
+        {
+            stale.push(frozen_path);
+        }
+        if args.hook {
+            if let Some(path) = stale.first() {
+                eprintln!(
+                    "apigen-xml: {} is out of date, regenerate from {}",
+                    path.display(),
+                    args.filename.display()
+                );
+            }
+        } else {
+            for path in &stale {
+                eprintln!("out of date: {}", path.display());
+            }
+        }
+        return Ok(if stale.is_empty() {
+            ExitCode::SUCCESS
+        } else {
+            ExitCode::FAILURE
+        });
+    }
+
+    fs::create_dir_all(&args.out_dir)?;
+    for path in generator::generate_api(&api_data, &args.out_dir, args.prune)? {
+        report_removed(&path, args.prune);
+    }
+    write_stype_lock(&stype_lock_path(&args.filename), api_data.stype_lock())?;
+    write_opcode_lock(&opcode_lock_path(&args.filename), api_data.opcode_lock())?;
+    write_frozen_lock(&frozen_lock_path(&args.filename), api_data.frozen_lock())?;
+    Ok(ExitCode::SUCCESS)
+}
@@ -1,5 +1,6 @@
 // Copyright 2025 Google
 // SPDX-License-Identifier: MIT
 
+mod schema;
 mod xml;
 pub use xml::parse_api;
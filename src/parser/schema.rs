@@ -0,0 +1,509 @@
+// Copyright 2025 Google
+// SPDX-License-Identifier: MIT
+
+use std::path::Path;
+
+use xml::common::Position;
+use xml::reader::{EventReader, XmlEvent};
+
+use crate::common::ApiGenError;
+
+/// Structure of one element of the apigen dialect.
+struct Element {
+    name: &'static str,
+    /// Parent the rule is limited to, for element names with different meanings.
+    parent: Option<&'static str>,
+    /// Attributes that must be present. `a|b` accepts either spelling.
+    attributes: &'static [&'static str],
+    /// Child elements that must be present.
+    required: &'static [&'static str],
+    /// Child elements allowed. Elements without any hold text.
+    children: &'static [&'static str],
+}
+
+const MEMBER_CHILDREN: &[&str] = &[
+    "name",
+    "type",
+    "qualifier",
+    "description",
+    "default",
+    "bits",
+    "struct",
+];
+const COMMAND_CHILDREN: &[&str] = &["opcode", "description", "member"];
+
+const ELEMENTS: &[Element] = &[
+    Element {
+        name: "api",
+        parent: None,
+        attributes: &[],
+        required: &[],
+        children: &[
+            "copyright",
+            "version",
+            "define",
+            "include",
+            "naming",
+            "typemap",
+            "generated_file",
+        ],
+    },
+    Element {
+        name: "typemap",
+        parent: None,
+        attributes: &[],
+        required: &[],
+        children: &["type"],
+    },
+    Element {
+        name: "type",
+        parent: Some("typemap"),
+        attributes: &["name", "size"],
+        required: &[],
+        children: &[],
+    },
+    Element {
+        name: "copyright",
+        parent: None,
+        attributes: &[],
+        required: &[],
+        children: &["spdx", "holder", "year"],
+    },
+    Element {
+        name: "naming",
+        parent: None,
+        attributes: &["file_type"],
+        required: &[],
+        children: &[],
+    },
+    Element {
+        name: "include",
+        parent: Some("generated_file"),
+        attributes: &[],
+        required: &[],
+        children: &[],
+    },
+    Element {
+        name: "include",
+        parent: None,
+        attributes: &["path"],
+        required: &[],
+        children: &[],
+    },
+    Element {
+        name: "define",
+        parent: None,
+        attributes: &[],
+        required: &[],
+        children: &[
+            "name",
+            "enum",
+            "flags",
+            "constants",
+            "structs",
+            "typedefs",
+            "aliases",
+            "unions",
+            "extensible_structs",
+            "objects",
+            "function",
+            "protocol",
+        ],
+    },
+    Element {
+        name: "enum",
+        parent: None,
+        attributes: &[],
+        required: &["enum_name"],
+        children: &[
+            "enum_name",
+            "type",
+            "description",
+            "attributes",
+            "item",
+            "reserved",
+        ],
+    },
+    Element {
+        name: "flags",
+        parent: None,
+        attributes: &[],
+        required: &[],
+        children: &["flag"],
+    },
+    Element {
+        name: "flag",
+        parent: None,
+        attributes: &[],
+        required: &["flag_name"],
+        children: &["flag_name", "type", "attributes", "item"],
+    },
+    Element {
+        name: "constants",
+        parent: None,
+        attributes: &[],
+        required: &[],
+        children: &["constant"],
+    },
+    Element {
+        name: "constant",
+        parent: None,
+        attributes: &[],
+        required: &["item"],
+        children: &["type", "item"],
+    },
+    Element {
+        name: "item",
+        parent: None,
+        attributes: &["name", "value"],
+        required: &[],
+        children: &[],
+    },
+    Element {
+        name: "reserved",
+        parent: None,
+        attributes: &["from|start", "to|end"],
+        required: &[],
+        children: &[],
+    },
+    Element {
+        name: "structs",
+        parent: None,
+        attributes: &[],
+        required: &[],
+        children: &["struct"],
+    },
+    // Inline structs are named after their member unless they have a name of their own.
+    Element {
+        name: "struct",
+        parent: Some("member"),
+        attributes: &[],
+        required: &[],
+        children: &["name", "description", "attributes", "member"],
+    },
+    Element {
+        name: "struct",
+        parent: None,
+        attributes: &[],
+        required: &["name"],
+        children: &["name", "description", "attributes", "member"],
+    },
+    Element {
+        name: "member",
+        parent: None,
+        attributes: &[],
+        required: &["name"],
+        children: MEMBER_CHILDREN,
+    },
+    Element {
+        name: "typedefs",
+        parent: None,
+        attributes: &[],
+        required: &[],
+        children: &["typedef"],
+    },
+    Element {
+        name: "aliases",
+        parent: None,
+        attributes: &[],
+        required: &[],
+        children: &["alias"],
+    },
+    Element {
+        name: "typedef",
+        parent: None,
+        attributes: &[],
+        required: &["name", "type"],
+        children: &["name", "type"],
+    },
+    Element {
+        name: "alias",
+        parent: None,
+        attributes: &[],
+        required: &["name", "type"],
+        children: &["name", "type"],
+    },
+    Element {
+        name: "unions",
+        parent: None,
+        attributes: &[],
+        required: &[],
+        children: &["union"],
+    },
+    Element {
+        name: "union",
+        parent: None,
+        attributes: &[],
+        required: &["name"],
+        children: &["name", "description", "attributes", "member"],
+    },
+    Element {
+        name: "extensible_structs",
+        parent: None,
+        attributes: &[],
+        required: &["stypes"],
+        children: &["stypes", "extensible_struct"],
+    },
+    Element {
+        name: "extensible_struct",
+        parent: None,
+        attributes: &[],
+        required: &["name", "stype"],
+        children: &["name", "description", "attributes", "stype", "member"],
+    },
+    Element {
+        name: "stype",
+        parent: None,
+        attributes: &["name"],
+        required: &[],
+        children: &[],
+    },
+    Element {
+        name: "objects",
+        parent: None,
+        attributes: &[],
+        required: &[],
+        children: &["object"],
+    },
+    Element {
+        name: "object",
+        parent: None,
+        attributes: &[],
+        required: &["ffi", "rust"],
+        children: &["ffi", "rust"],
+    },
+    Element {
+        name: "function",
+        parent: None,
+        attributes: &[],
+        required: &["name"],
+        children: &["name", "return", "panic_return", "description", "member"],
+    },
+    Element {
+        name: "protocol",
+        parent: None,
+        attributes: &[],
+        required: &["protocol_name"],
+        children: &[
+            "protocol_name",
+            "request",
+            "response",
+            "event",
+            "reserved",
+            "state_machine",
+        ],
+    },
+    Element {
+        name: "state_machine",
+        parent: None,
+        attributes: &[],
+        required: &["state"],
+        children: &["state", "transition"],
+    },
+    Element {
+        name: "state",
+        parent: None,
+        attributes: &["name"],
+        required: &[],
+        children: &[],
+    },
+    Element {
+        name: "transition",
+        parent: None,
+        attributes: &["request"],
+        required: &[],
+        children: &[],
+    },
+    Element {
+        name: "request",
+        parent: None,
+        attributes: &[],
+        required: &["opcode"],
+        children: COMMAND_CHILDREN,
+    },
+    Element {
+        name: "response",
+        parent: None,
+        attributes: &[],
+        required: &["opcode"],
+        children: COMMAND_CHILDREN,
+    },
+    Element {
+        name: "event",
+        parent: None,
+        attributes: &[],
+        required: &["opcode"],
+        children: COMMAND_CHILDREN,
+    },
+    Element {
+        name: "opcode",
+        parent: None,
+        attributes: &["name"],
+        required: &[],
+        children: &[],
+    },
+    Element {
+        name: "generated_file",
+        parent: None,
+        attributes: &[],
+        required: &["file_name", "file_type"],
+        children: &[
+            "out_path",
+            "file_name",
+            "file_type",
+            "include",
+            "instantiate",
+            "namespace",
+            "decode_mode",
+            "order",
+            "target",
+            "reexport",
+            "coverage",
+            "decode_arena",
+            "command_log",
+            "client",
+            "connection",
+            "doc_module",
+            "max_version",
+            "small_command_limit",
+            "freestanding",
+            "typedef_header",
+        ],
+    },
+];
+
+/// Finds the rule of element `name` inside `parent`. Elements that only hold text have none.
+fn element(parent: &str, name: &str) -> Option<&'static Element> {
+    ELEMENTS
+        .iter()
+        .find(|e| e.name == name && e.parent.is_none_or(|p| p == parent))
+}
+
+/// An element being checked, with the children seen so far.
+struct Open {
+    name: String,
+    rule: Option<&'static Element>,
+    seen: Vec<String>,
+    line: u64,
+    column: u64,
+}
+
+/// Checks the element structure of `file` against the apigen dialect, reporting every unknown or
+/// misplaced element and every missing attribute or child at once. Malformed XML stops the check
+/// and is left to the parser, which reports it with its position.
+pub fn validate<R: std::io::Read>(
+    parser: &mut EventReader<R>,
+    file: &Path,
+) -> Result<(), ApiGenError> {
+    let mut problems = Vec::new();
+    let mut report = |line: u64, column: u64, message: String| {
+        problems.push(format!(
+            "{}:{}:{}: {}",
+            file.display(),
+            line,
+            column,
+            message
+        ))
+    };
+    let mut stack: Vec<Open> = Vec::new();
+    // Elements inside an unknown one are not checked.
+    let mut skipped = 0;
+    while let Ok(event) = parser.next() {
+        let position = parser.position();
+        let (line, column) = (position.row + 1, position.column + 1);
+        match event {
+            XmlEvent::StartElement {
+                name, attributes, ..
+            } => {
+                let name = name.local_name;
+                if skipped > 0 {
+                    skipped += 1;
+                    continue;
+                }
+                let rule = match stack.last_mut() {
+                    None if name != "api" => {
+                        report(
+                            line,
+                            column,
+                            format!("root element <{}> is not <api>", name),
+                        );
+                        skipped = 1;
+                        continue;
+                    }
+                    None => element("", &name),
+                    Some(parent) => {
+                        parent.seen.push(name.clone());
+                        let Some(parent_rule) = parent.rule else {
+                            report(
+                                line,
+                                column,
+                                format!(
+                                    "<{}> holds text and cannot contain <{}>",
+                                    parent.name, name
+                                ),
+                            );
+                            skipped = 1;
+                            continue;
+                        };
+                        if !parent_rule.children.contains(&name.as_str()) {
+                            let known = ELEMENTS.iter().any(|e| e.name == name)
+                                || ELEMENTS.iter().any(|e| e.children.contains(&name.as_str()));
+                            let message = if known {
+                                format!("<{}> is not allowed in <{}>", name, parent.name)
+                            } else {
+                                format!("unknown element <{}> in <{}>", name, parent.name)
+                            };
+                            report(line, column, message);
+                            skipped = 1;
+                            continue;
+                        }
+                        element(&parent.name, &name)
+                    }
+                };
+                for &attribute in rule.map_or(&[][..], |r| r.attributes) {
+                    let present = attribute
+                        .split('|')
+                        .any(|a| attributes.iter().any(|attr| attr.name.local_name == a));
+                    if !present {
+                        let attribute = attribute.replace('|', "' or '");
+                        report(
+                            line,
+                            column,
+                            format!("<{}> is missing '{}'", name, attribute),
+                        );
+                    }
+                }
+                stack.push(Open {
+                    name,
+                    rule,
+                    seen: Vec::new(),
+                    line,
+                    column,
+                });
+            }
+            XmlEvent::EndElement { .. } if skipped > 0 => skipped -= 1,
+            XmlEvent::EndElement { .. } => {
+                let Some(open) = stack.pop() else { break };
+                for &child in open.rule.map_or(&[][..], |r| r.required) {
+                    if !open.seen.iter().any(|seen| seen == child) {
+                        report(
+                            open.line,
+                            open.column,
+                            format!("<{}> has no <{}>", open.name, child),
+                        );
+                    }
+                }
+            }
+            XmlEvent::EndDocument => break,
+            _ => {}
+        }
+    }
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(ApiGenError::SchemaViolations {
+            file: file.display().to_string(),
+            problems,
+        })
+    }
+}
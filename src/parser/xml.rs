@@ -1,14 +1,19 @@
 // Copyright 2025 Google
 // SPDX-License-Identifier: MIT
 
-use std::fs::File;
+use std::collections::HashSet;
+use std::fs::{self, File};
 use std::io::BufReader;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 use xml::attribute::OwnedAttribute;
-use xml::reader::{EventReader, XmlEvent};
+use xml::common::Position;
+use xml::reader::{EventReader, ParserConfig, XmlEvent};
 
-use crate::common::utils::to_pascal_case;
+use super::schema;
+use crate::common::api::PRIMITIVE_TYPES;
+use crate::common::lockfile::{frozen_lock_path, opcode_lock_path, read_lock, stype_lock_path};
+use crate::common::utils::{is_identifier, parse_int_literal, split_array_type, to_pascal_case};
 use crate::common::*;
 
 /// Helper to find a specific attribute's value from a list of attributes.
@@ -30,6 +35,74 @@ fn read_text_content<R: std::io::Read>(parser: &mut EventReader<R>) -> Result<St
     }
 }
 
+/// Splits the text of an XML comment into trimmed lines, dropping leading and trailing blank lines.
+fn comment_lines(text: &str) -> Vec<String> {
+    let lines: Vec<&str> = text.lines().map(str::trim).collect();
+    let start = lines
+        .iter()
+        .position(|line| !line.is_empty())
+        .unwrap_or(lines.len());
+    let end = lines
+        .iter()
+        .rposition(|line| !line.is_empty())
+        .map_or(start, |last| last + 1);
+    lines[start..end]
+        .iter()
+        .map(|line| line.to_string())
+        .collect()
+}
+
+/// Fails unless `name`, read from an `<element>`, is an identifier in every output language.
+fn check_identifier(element: &str, name: &str) -> Result<(), ApiGenError> {
+    if is_identifier(name) {
+        Ok(())
+    } else {
+        Err(ApiGenError::InvalidIdentifier {
+            element: element.to_string(),
+            name: name.to_string(),
+        })
+    }
+}
+
+/// Reads the text of an element naming something, which must be an identifier.
+fn read_identifier<R: std::io::Read>(
+    parser: &mut EventReader<R>,
+    element: &str,
+) -> Result<String, ApiGenError> {
+    let name = read_text_content(parser)?;
+    check_identifier(element, &name)?;
+    Ok(name)
+}
+
+/// Reads the text of a <description> element as lines, like a preserved comment.
+fn read_description<R: std::io::Read>(
+    parser: &mut EventReader<R>,
+) -> Result<Vec<String>, ApiGenError> {
+    Ok(comment_lines(&read_text_content(parser)?))
+}
+
+/// Reads the Rust attributes of an <attributes> element, one `#[...]` per line.
+fn read_attributes<R: std::io::Read>(
+    parser: &mut EventReader<R>,
+) -> Result<Vec<String>, ApiGenError> {
+    let attributes: Vec<String> = read_text_content(parser)?
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+        .collect();
+    match attributes
+        .iter()
+        .find(|attribute| !attribute.starts_with("#[") || !attribute.ends_with(']'))
+    {
+        Some(invalid) => Err(ApiGenError::InvalidAttributeValue {
+            attribute: "attributes".to_string(),
+            value: invalid.clone(),
+        }),
+        None => Ok(attributes),
+    }
+}
+
 /// Parses a single <constant> element.
 fn parse_constant<R: std::io::Read>(parser: &mut EventReader<R>) -> Result<Constant, ApiGenError> {
     let mut constant = Constant::default();
@@ -43,6 +116,7 @@ fn parse_constant<R: std::io::Read>(parser: &mut EventReader<R>) -> Result<Const
                     constant.name = find_attribute_value(&attributes, "name").ok_or_else(|| {
                         ApiGenError::MissingAttribute("<item> missing 'name'".to_string())
                     })?;
+                    check_identifier("item", &constant.name)?;
                     constant.value =
                         find_attribute_value(&attributes, "value").ok_or_else(|| {
                             ApiGenError::MissingAttribute("<item> missing 'value'".to_string())
@@ -58,31 +132,312 @@ fn parse_constant<R: std::io::Read>(parser: &mut EventReader<R>) -> Result<Const
 }
 
 /// Parses a single <member> element.
-fn parse_member<R: std::io::Read>(parser: &mut EventReader<R>) -> Result<Member, ApiGenError> {
-    let mut member = Member::default();
+fn parse_member<R: std::io::Read>(
+    parser: &mut EventReader<R>,
+    attributes: &[OwnedAttribute],
+    allow_pointers: bool,
+) -> Result<Member, ApiGenError> {
+    let mut member = Member {
+        min: find_attribute_value(attributes, "min"),
+        max: find_attribute_value(attributes, "max"),
+        power_of_two: parse_bool_attribute(attributes, "power_of_two")?,
+        units: parse_units(attributes)?,
+        count: find_attribute_value(attributes, "count"),
+        atomic: parse_bool_attribute(attributes, "atomic")?,
+        dynamic: parse_bool_attribute(attributes, "dynamic")?,
+        deprecated: find_attribute_value(attributes, "deprecated"),
+        platform: parse_platform(attributes)?,
+        sensitive: parse_bool_attribute(attributes, "sensitive")?,
+        ..Default::default()
+    };
     loop {
         match parser.next()? {
-            XmlEvent::StartElement { name, .. } => match name.local_name.as_str() {
+            XmlEvent::StartElement {
+                name,
+                attributes: struct_attributes,
+                ..
+            } => match name.local_name.as_str() {
                 "type" => member.type_name = read_text_content(parser)?,
+                "struct" => {
+                    member.inline_struct = Some(Box::new(parse_struct(parser, &struct_attributes)?))
+                }
                 "qualifier" => member.qualifier = read_text_content(parser)?,
-                "name" => member.name = read_text_content(parser)?,
+                "name" => member.name = read_identifier(parser, "name")?,
+                "description" => member.description = read_description(parser)?,
+                "default" => member.default = Some(read_text_content(parser)?),
+                "bits" => member.bits = Some(read_text_content(parser)?.parse()?),
                 _ => {}
             },
             XmlEvent::EndElement { name } if name.local_name == "member" => break,
             _ => {}
         }
     }
-    Ok(member)
+
+    if member.inline_struct.is_some() {
+        let reason = if allow_pointers {
+            "function parameters cannot declare structs"
+        } else if !member.type_name.is_empty() {
+            "an inline struct replaces <type>"
+        } else {
+            ""
+        };
+        if !reason.is_empty() {
+            return Err(ApiGenError::InvalidInlineStruct {
+                member: member.name,
+                reason: reason.to_string(),
+            });
+        }
+    }
+
+    // Strings are stored as NUL-padded byte arrays of their declared capacity.
+    if member.type_name == "string" {
+        let max_len = find_attribute_value(attributes, "max_len")
+            .ok_or_else(|| ApiGenError::MissingAttribute(format!("max_len of {}", member.name)))?;
+        // Constant names are resolved along with the sizes of other arrays.
+        let valid = match max_len.parse::<usize>() {
+            Ok(len) => len > 0,
+            Err(_) => max_len
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_'),
+        };
+        if !valid || max_len.is_empty() {
+            return Err(ApiGenError::InvalidAttributeValue {
+                attribute: format!("max_len of {}", member.name),
+                value: max_len,
+            });
+        }
+        member.type_name = format!("[u8; {}]", max_len);
+        member.max_len = Some(max_len);
+    }
+
+    member.type_name = qualify_primitive(&member.type_name);
+
+    // Handles travel as u64 ids; the API resolves the object they name once it is declared.
+    if member.type_name == "handle" {
+        let object = find_attribute_value(attributes, "object")
+            .ok_or_else(|| ApiGenError::MissingAttribute(format!("object of {}", member.name)))?;
+        member.type_name = if allow_pointers {
+            object.clone()
+        } else {
+            "u64".to_string()
+        };
+        member.handle = Some(Object {
+            name: object,
+            ..Default::default()
+        });
+    }
+
+    check_constraints(&member)?;
+    check_platform(&member, allow_pointers)?;
+
+    // Only function parameters may be pointers; wire structs hold plain values.
+    match Qualifier::from_str(&member.qualifier) {
+        Some(qualifier) if allow_pointers || qualifier.pointer_depth == 0 => {
+            member.qualifier = member
+                .qualifier
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" ");
+            Ok(member)
+        }
+        _ => Err(ApiGenError::InvalidQualifier {
+            member: member.name,
+            qualifier: member.qualifier,
+        }),
+    }
+}
+
+/// Spells built-in types such as `c_char`, alone or as the element type of an array, with their
+/// path, so that Rust outputs need no import for them.
+fn qualify_primitive(type_name: &str) -> String {
+    match split_array_type(type_name) {
+        Some((element, len)) => format!("[{}; {}]", qualify_primitive(element), len),
+        None => PRIMITIVE_TYPES
+            .iter()
+            .find(|t| t.name == type_name)
+            .map_or(type_name, |t| t.rust)
+            .to_string(),
+    }
+}
+
+/// Parses the optional `units` attribute of a member.
+fn parse_units(attributes: &[OwnedAttribute]) -> Result<Option<Units>, ApiGenError> {
+    match find_attribute_value(attributes, "units") {
+        Some(units) => {
+            Units::from_str(&units)
+                .map(Some)
+                .ok_or_else(|| ApiGenError::InvalidAttributeValue {
+                    attribute: "units".to_string(),
+                    value: units,
+                })
+        }
+        None => Ok(None),
+    }
+}
+
+/// Parses the optional `platform` attribute of a member.
+fn parse_platform(attributes: &[OwnedAttribute]) -> Result<Option<Platform>, ApiGenError> {
+    match find_attribute_value(attributes, "platform") {
+        Some(platform) => Platform::from_str(&platform).map(Some).ok_or_else(|| {
+            ApiGenError::InvalidAttributeValue {
+                attribute: "platform".to_string(),
+                value: platform,
+            }
+        }),
+        None => Ok(None),
+    }
+}
+
+/// Fails for a platform-specific member that is anything but a plain field: the generated
+/// accessors, defaults and bitfields all assume their member exists everywhere.
+fn check_platform(member: &Member, allow_pointers: bool) -> Result<(), ApiGenError> {
+    if member.platform.is_none() {
+        return Ok(());
+    }
+    let reason = if allow_pointers {
+        "function parameters cannot be platform-specific"
+    } else if member.bits.is_some() {
+        "bitfields cannot be platform-specific"
+    } else if member.atomic || member.dynamic {
+        "atomic members and dynamic arrays cannot be platform-specific"
+    } else if member.max_len.is_some() || member.inline_struct.is_some() {
+        "strings and inline structs cannot be platform-specific"
+    } else if member.min.is_some() || member.max.is_some() || member.power_of_two {
+        "platform-specific members cannot be constrained"
+    } else if member.default.is_some() || member.count.is_some() {
+        "platform-specific members cannot have a default or a count"
+    } else if !member.qualifier.trim().is_empty() {
+        "platform-specific members cannot be qualified"
+    } else {
+        return Ok(());
+    };
+    Err(ApiGenError::InvalidPlatformMember {
+        member: member.name.clone(),
+        reason: reason.to_string(),
+    })
+}
+
+/// Validates the `min`, `max`, `power_of_two` and `units` annotations of a member.
+fn check_constraints(member: &Member) -> Result<(), ApiGenError> {
+    const INTEGER_TYPES: &[&str] = &[
+        "u8", "i8", "u16", "i16", "u32", "i32", "u64", "i64", "usize", "isize",
+    ];
+    let invalid = |reason: &str| ApiGenError::InvalidConstraint {
+        member: member.name.clone(),
+        reason: reason.to_string(),
+    };
+    if member.units.is_some() && !INTEGER_TYPES.contains(&member.type_name.as_str()) {
+        return Err(invalid("units require an integer type"));
+    }
+    if member.atomic {
+        if !INTEGER_TYPES.contains(&member.type_name.as_str()) {
+            return Err(invalid("atomic requires an integer type"));
+        }
+        if !member.qualifier.is_empty() {
+            return Err(invalid("atomic members cannot be qualified"));
+        }
+        if member.min.is_some() || member.max.is_some() || member.power_of_two {
+            return Err(invalid("atomic members cannot be constrained"));
+        }
+    }
+    if member.min.is_none() && member.max.is_none() && !member.power_of_two {
+        return Ok(());
+    }
+    if !INTEGER_TYPES.contains(&member.type_name.as_str()) {
+        return Err(invalid("constraints require an integer type"));
+    }
+    if member.power_of_two && member.type_name.starts_with('i') {
+        return Err(invalid("power_of_two requires an unsigned type"));
+    }
+    let bound = |value: &Option<String>| match value {
+        Some(value) => parse_int_literal(value)
+            .map(Some)
+            .ok_or_else(|| invalid(&format!("{} is not an integer", value))),
+        None => Ok(None),
+    };
+    if let (Some(min), Some(max)) = (bound(&member.min)?, bound(&member.max)?) {
+        if min > max {
+            return Err(invalid("min is greater than max"));
+        }
+    }
+    Ok(())
+}
+
+/// Parses an optional boolean attribute, defaulting to `false` when absent.
+fn parse_bool_attribute(attributes: &[OwnedAttribute], name: &str) -> Result<bool, ApiGenError> {
+    match find_attribute_value(attributes, name).as_deref() {
+        Some("true") => Ok(true),
+        Some("false") | None => Ok(false),
+        Some(value) => Err(ApiGenError::InvalidAttributeValue {
+            attribute: name.to_string(),
+            value: value.to_string(),
+        }),
+    }
+}
+
+/// Parses an optional literal format attribute of `<api>`, such as `flag_literals="hex:8"`.
+fn parse_literal_format(
+    attributes: &[OwnedAttribute],
+    name: &str,
+) -> Result<Option<LiteralFormat>, ApiGenError> {
+    find_attribute_value(attributes, name)
+        .map(|value| {
+            LiteralFormat::from_str(&value).ok_or_else(|| ApiGenError::InvalidAttributeValue {
+                attribute: name.to_string(),
+                value,
+            })
+        })
+        .transpose()
+}
+
+/// Parses the optional `align` attribute of a struct-like element.
+fn parse_alignment(attributes: &[OwnedAttribute]) -> Result<Option<usize>, ApiGenError> {
+    match find_attribute_value(attributes, "align") {
+        Some(align) if align == "cacheline" => Ok(Some(CACHELINE_SIZE)),
+        Some(align) => Ok(Some(align.parse()?)),
+        None => Ok(None),
+    }
+}
+
+/// Parses the `visibility` attribute of a struct, union or enum.
+fn parse_visibility(attributes: &[OwnedAttribute]) -> Result<Visibility, ApiGenError> {
+    match find_attribute_value(attributes, "visibility") {
+        Some(visibility) => {
+            Visibility::from_str(&visibility).ok_or(ApiGenError::InvalidAttributeValue {
+                attribute: "visibility".to_string(),
+                value: visibility,
+            })
+        }
+        None => Ok(Visibility::Pub),
+    }
 }
 
 /// Parses a <struct> element.
-fn parse_struct<R: std::io::Read>(parser: &mut EventReader<R>) -> Result<StructDef, ApiGenError> {
+fn parse_struct<R: std::io::Read>(
+    parser: &mut EventReader<R>,
+    attributes: &[OwnedAttribute],
+) -> Result<StructDef, ApiGenError> {
     let mut struct_def = StructDef::default();
+    struct_def.common.align = parse_alignment(attributes)?;
+    struct_def.common.non_exhaustive = parse_bool_attribute(attributes, "non_exhaustive")?;
+    struct_def.common.per_heap = parse_bool_attribute(attributes, "per_heap")?;
+    struct_def.common.owned = parse_bool_attribute(attributes, "owned")?;
+    struct_def.common.frozen = parse_bool_attribute(attributes, "frozen")?;
+    struct_def.common.visibility = parse_visibility(attributes)?;
     loop {
         match parser.next()? {
-            XmlEvent::StartElement { name, .. } => match name.local_name.as_str() {
-                "name" => struct_def.common.name = read_text_content(parser)?,
-                "member" => struct_def.common.members.push(parse_member(parser)?),
+            XmlEvent::StartElement {
+                name, attributes, ..
+            } => match name.local_name.as_str() {
+                "name" => struct_def.common.name = read_identifier(parser, "name")?,
+                "description" => struct_def.common.description = read_description(parser)?,
+                "attributes" => struct_def.common.attributes = read_attributes(parser)?,
+                "member" => {
+                    struct_def
+                        .common
+                        .members
+                        .push(parse_member(parser, &attributes, false)?)
+                }
                 _ => {}
             },
             XmlEvent::EndElement { name } if name.local_name == "struct" => break,
@@ -92,25 +447,162 @@ fn parse_struct<R: std::io::Read>(parser: &mut EventReader<R>) -> Result<StructD
     Ok(struct_def)
 }
 
-/// Parses a single <request> element.
-fn parse_request<R: std::io::Read>(parser: &mut EventReader<R>) -> Result<Request, ApiGenError> {
-    let mut request = Request::default();
+/// Registers the inline structs of `members` under the name of their owner followed by the
+/// member's, e.g. `MagmaImageInfoExtent`, unless they are named, and makes them the members'
+/// types. Inner structs are registered first, so every struct is sized before its users.
+fn add_inline_structs(
+    api: &mut Api,
+    def: &mut Definition,
+    owner: &str,
+    members: &mut [Member],
+) -> Result<(), ApiGenError> {
+    for member in members {
+        let Some(mut inline) = member.inline_struct.take() else {
+            continue;
+        };
+        if inline.common.name.is_empty() {
+            inline.common.name =
+                format!("{}{}", to_pascal_case(owner), to_pascal_case(&member.name));
+        }
+        let name = inline.common.name.clone();
+        add_inline_structs(api, def, &name, &mut inline.common.members)?;
+        member.type_name = name.clone();
+        def.items.push(name);
+        api.add_struct(*inline)?;
+    }
+    Ok(())
+}
+
+/// Parses a <typedef> or <alias> element, which end with `element`.
+fn parse_typedef<R: std::io::Read>(
+    parser: &mut EventReader<R>,
+    element: &str,
+) -> Result<Typedef, ApiGenError> {
+    let mut typedef = Typedef::default();
+    loop {
+        match parser.next()? {
+            XmlEvent::StartElement { name, .. } => match name.local_name.as_str() {
+                "name" => typedef.name = read_identifier(parser, "name")?,
+                "type" => typedef.type_name = qualify_primitive(&read_text_content(parser)?),
+                _ => {}
+            },
+            XmlEvent::EndElement { name } if name.local_name == element => break,
+            _ => {}
+        }
+    }
+    Ok(typedef)
+}
+
+/// Parses a <union> element. Members are declared as in a struct.
+fn parse_union<R: std::io::Read>(
+    parser: &mut EventReader<R>,
+    attributes: &[OwnedAttribute],
+) -> Result<UnionDef, ApiGenError> {
+    let mut union_def = UnionDef::default();
+    union_def.common.align = parse_alignment(attributes)?;
+    union_def.common.frozen = parse_bool_attribute(attributes, "frozen")?;
+    union_def.common.visibility = parse_visibility(attributes)?;
     loop {
         match parser.next()? {
             XmlEvent::StartElement {
                 name, attributes, ..
             } => match name.local_name.as_str() {
-                "opcode" => {
-                    request.opcode.name =
-                        find_attribute_value(&attributes, "name").ok_or_else(|| {
-                            ApiGenError::MissingAttribute("<opcode> missing 'name'".to_string())
-                        })?;
-                    request.opcode.value =
-                        find_attribute_value(&attributes, "value").ok_or_else(|| {
-                            ApiGenError::MissingAttribute("<opcode> missing 'value'".to_string())
-                        })?;
+                "name" => union_def.common.name = read_identifier(parser, "name")?,
+                "description" => union_def.common.description = read_description(parser)?,
+                "attributes" => union_def.common.attributes = read_attributes(parser)?,
+                "member" => {
+                    union_def
+                        .common
+                        .members
+                        .push(parse_member(parser, &attributes, false)?)
                 }
-                "member" => request.members.push(parse_member(parser)?),
+                _ => {}
+            },
+            XmlEvent::EndElement { name } if name.local_name == "union" => break,
+            _ => {}
+        }
+    }
+    Ok(union_def)
+}
+
+/// Parses the attributes of a <reserved> element.
+fn parse_reserved(attributes: &[OwnedAttribute]) -> Result<ReservedRange, ApiGenError> {
+    // `start` and `end` are accepted for `from` and `to`.
+    let from = find_attribute_value(attributes, "from")
+        .or_else(|| find_attribute_value(attributes, "start"))
+        .ok_or_else(|| ApiGenError::MissingAttribute("<reserved> missing 'from'".to_string()))?;
+    let to = find_attribute_value(attributes, "to")
+        .or_else(|| find_attribute_value(attributes, "end"))
+        .ok_or_else(|| ApiGenError::MissingAttribute("<reserved> missing 'to'".to_string()))?;
+    let reason = find_attribute_value(attributes, "reason").unwrap_or_default();
+    Ok(ReservedRange { from, to, reason })
+}
+
+/// Parses the `since` attribute of an item or value: the API version introducing it, 0 if it was
+/// always there.
+fn parse_since(attributes: &[OwnedAttribute]) -> Result<u32, ApiGenError> {
+    match find_attribute_value(attributes, "since") {
+        Some(version) => Ok(version.parse()?),
+        None => Ok(0),
+    }
+}
+
+/// Records the `since` version of a definition item, if it declares one.
+fn record_since(
+    api: &mut Api,
+    item_name: &str,
+    attributes: &[OwnedAttribute],
+) -> Result<(), ApiGenError> {
+    let since = parse_since(attributes)?;
+    if since > 0 {
+        api.set_item_since(item_name, since);
+    }
+    Ok(())
+}
+
+/// Parses the attributes of an <opcode> element.
+fn parse_opcode(attributes: &[OwnedAttribute]) -> Result<Opcode, ApiGenError> {
+    let name = find_attribute_value(attributes, "name")
+        .ok_or_else(|| ApiGenError::MissingAttribute("<opcode> missing 'name'".to_string()))?;
+    check_identifier("opcode", &name)?;
+    // Opcodes without a value are assigned one by `Api::add_protocol`.
+    let value = find_attribute_value(attributes, "value").unwrap_or_default();
+    // `since` reads the same as on other items.
+    let min_version = match find_attribute_value(attributes, "min_version")
+        .or_else(|| find_attribute_value(attributes, "since"))
+    {
+        Some(version) => version.parse()?,
+        None => 0,
+    };
+    Ok(Opcode {
+        name,
+        value,
+        min_version,
+    })
+}
+
+/// Parses a single <request> element.
+fn parse_request<R: std::io::Read>(
+    parser: &mut EventReader<R>,
+    attributes: &[OwnedAttribute],
+) -> Result<Request, ApiGenError> {
+    let mut request = Request {
+        response: find_attribute_value(attributes, "response"),
+        oneway: parse_bool_attribute(attributes, "oneway")?,
+        deprecated: find_attribute_value(attributes, "deprecated"),
+        align: parse_alignment(attributes)?,
+        ..Default::default()
+    };
+    loop {
+        match parser.next()? {
+            XmlEvent::StartElement {
+                name, attributes, ..
+            } => match name.local_name.as_str() {
+                "opcode" => request.opcode = parse_opcode(&attributes)?,
+                "description" => request.description = read_description(parser)?,
+                "member" => request
+                    .members
+                    .push(parse_member(parser, &attributes, false)?),
                 _ => {}
             },
             XmlEvent::EndElement { name } if name.local_name == "request" => break,
@@ -121,24 +613,24 @@ fn parse_request<R: std::io::Read>(parser: &mut EventReader<R>) -> Result<Reques
 }
 
 /// Parses a single <response> element.
-fn parse_response<R: std::io::Read>(parser: &mut EventReader<R>) -> Result<Response, ApiGenError> {
-    let mut response = Response::default();
+fn parse_response<R: std::io::Read>(
+    parser: &mut EventReader<R>,
+    attributes: &[OwnedAttribute],
+) -> Result<Response, ApiGenError> {
+    let mut response = Response {
+        oneway: parse_bool_attribute(attributes, "oneway")?,
+        ..Default::default()
+    };
     loop {
         match parser.next()? {
             XmlEvent::StartElement {
                 name, attributes, ..
             } => match name.local_name.as_str() {
-                "opcode" => {
-                    response.opcode.name =
-                        find_attribute_value(&attributes, "name").ok_or_else(|| {
-                            ApiGenError::MissingAttribute("<opcode> missing 'name'".to_string())
-                        })?;
-                    response.opcode.value =
-                        find_attribute_value(&attributes, "value").ok_or_else(|| {
-                            ApiGenError::MissingAttribute("<opcode> missing 'value'".to_string())
-                        })?;
-                }
-                "member" => response.members.push(parse_member(parser)?),
+                "opcode" => response.opcode = parse_opcode(&attributes)?,
+                "description" => response.description = read_description(parser)?,
+                "member" => response
+                    .members
+                    .push(parse_member(parser, &attributes, false)?),
                 _ => {}
             },
             XmlEvent::EndElement { name } if name.local_name == "response" => break,
@@ -148,25 +640,69 @@ fn parse_response<R: std::io::Read>(parser: &mut EventReader<R>) -> Result<Respo
     Ok(response)
 }
 
+/// Parses a single <event> element.
+fn parse_event<R: std::io::Read>(parser: &mut EventReader<R>) -> Result<Event, ApiGenError> {
+    let mut event = Event::default();
+    loop {
+        match parser.next()? {
+            XmlEvent::StartElement {
+                name, attributes, ..
+            } => match name.local_name.as_str() {
+                "opcode" => event.opcode = parse_opcode(&attributes)?,
+                "description" => event.description = read_description(parser)?,
+                "member" => event
+                    .members
+                    .push(parse_member(parser, &attributes, false)?),
+                _ => {}
+            },
+            XmlEvent::EndElement { name } if name.local_name == "event" => break,
+            _ => {}
+        }
+    }
+    Ok(event)
+}
+
 /// Parses a single <enum> block.
-fn parse_enum<R: std::io::Read>(parser: &mut EventReader<R>) -> Result<Enum, ApiGenError> {
-    let mut new_enum = Enum::default();
+fn parse_enum<R: std::io::Read>(
+    parser: &mut EventReader<R>,
+    attributes: &[OwnedAttribute],
+) -> Result<Enum, ApiGenError> {
+    let mut new_enum = Enum {
+        non_exhaustive: parse_bool_attribute(attributes, "non_exhaustive")?,
+        deprecated: find_attribute_value(attributes, "deprecated"),
+        frozen: parse_bool_attribute(attributes, "frozen")?,
+        visibility: parse_visibility(attributes)?,
+        ..Default::default()
+    };
+    if let Some(unknown) = find_attribute_value(attributes, "unknown") {
+        new_enum.unknown_values = UnknownValues::from_str(&unknown).ok_or_else(|| {
+            ApiGenError::InvalidAttributeValue {
+                attribute: "unknown".to_string(),
+                value: unknown.clone(),
+            }
+        })?;
+    }
     loop {
         match parser.next()? {
             XmlEvent::StartElement {
                 name, attributes, ..
             } => match name.local_name.as_str() {
-                "enum_name" => new_enum.name = read_text_content(parser)?,
+                "enum_name" => new_enum.name = read_identifier(parser, "enum_name")?,
                 "type" => new_enum.type_name = read_text_content(parser)?,
+                "description" => new_enum.description = read_description(parser)?,
+                "attributes" => new_enum.attributes = read_attributes(parser)?,
                 "item" => {
                     let name = find_attribute_value(&attributes, "name").ok_or_else(|| {
                         ApiGenError::MissingAttribute("Enum <item> missing 'name'".to_string())
                     })?;
+                    check_identifier("item", &name)?;
                     let value = find_attribute_value(&attributes, "value").ok_or_else(|| {
                         ApiGenError::MissingAttribute("Enum <item> missing 'value'".to_string())
                     })?;
-                    new_enum.entries.push(EnumEntry { name, value });
+                    let since = parse_since(&attributes)?;
+                    new_enum.entries.push(EnumEntry { name, value, since });
                 }
+                "reserved" => new_enum.reserved.push(parse_reserved(&attributes)?),
                 _ => {}
             },
             XmlEvent::EndElement { name } if name.local_name == "enum" => break,
@@ -177,23 +713,32 @@ fn parse_enum<R: std::io::Read>(parser: &mut EventReader<R>) -> Result<Enum, Api
 }
 
 /// Parses a single <flag> block.
-fn parse_flag<R: std::io::Read>(parser: &mut EventReader<R>) -> Result<Flag, ApiGenError> {
-    let mut new_flag = Flag::default();
+fn parse_flag<R: std::io::Read>(
+    parser: &mut EventReader<R>,
+    attributes: &[OwnedAttribute],
+) -> Result<Flag, ApiGenError> {
+    let mut new_flag = Flag {
+        frozen: parse_bool_attribute(attributes, "frozen")?,
+        ..Default::default()
+    };
     loop {
         match parser.next()? {
             XmlEvent::StartElement {
                 name, attributes, ..
             } => match name.local_name.as_str() {
-                "flag_name" => new_flag.name = read_text_content(parser)?,
+                "flag_name" => new_flag.name = read_identifier(parser, "flag_name")?,
                 "type" => new_flag.type_name = read_text_content(parser)?,
+                "attributes" => new_flag.attributes = read_attributes(parser)?,
                 "item" => {
                     let name = find_attribute_value(&attributes, "name").ok_or_else(|| {
                         ApiGenError::MissingAttribute("Flag <item> missing 'name'".to_string())
                     })?;
+                    check_identifier("item", &name)?;
                     let value = find_attribute_value(&attributes, "value").ok_or_else(|| {
                         ApiGenError::MissingAttribute("Flag <item> missing 'value'".to_string())
                     })?;
-                    new_flag.entries.push(EnumEntry { name, value });
+                    let since = parse_since(&attributes)?;
+                    new_flag.entries.push(EnumEntry { name, value, since });
                 }
                 _ => {}
             },
@@ -227,25 +772,36 @@ fn parse_copyright<R: std::io::Read>(
 /// Parses an <extensible_struct> element.
 fn parse_extensible_struct<R: std::io::Read>(
     parser: &mut EventReader<R>,
+    attributes: &[OwnedAttribute],
 ) -> Result<ExtensibleStruct, ApiGenError> {
     let mut struct_def = ExtensibleStruct::default();
+    struct_def.common.align = parse_alignment(attributes)?;
+    struct_def.common.non_exhaustive = parse_bool_attribute(attributes, "non_exhaustive")?;
+    struct_def.common.frozen = parse_bool_attribute(attributes, "frozen")?;
     loop {
         match parser.next()? {
             XmlEvent::StartElement {
                 name, attributes, ..
             } => match name.local_name.as_str() {
-                "name" => struct_def.common.name = read_text_content(parser)?,
+                "name" => struct_def.common.name = read_identifier(parser, "name")?,
+                "description" => struct_def.common.description = read_description(parser)?,
+                "attributes" => struct_def.common.attributes = read_attributes(parser)?,
                 "stype" => {
                     struct_def.stype.name =
                         find_attribute_value(&attributes, "name").ok_or_else(|| {
                             ApiGenError::MissingAttribute("<stype> missing 'name'".to_string())
                         })?;
-                    struct_def.stype.value = find_attribute_value(&attributes, "value")
-                        .ok_or_else(|| {
-                            ApiGenError::MissingAttribute("<stype> missing 'value'".to_string())
-                        })?;
+                    check_identifier("stype", &struct_def.stype.name)?;
+                    // A missing value is auto-assigned when the structs are added to the API.
+                    struct_def.stype.value =
+                        find_attribute_value(&attributes, "value").unwrap_or_default();
+                }
+                "member" => {
+                    struct_def
+                        .common
+                        .members
+                        .push(parse_member(parser, &attributes, false)?)
                 }
-                "member" => struct_def.common.members.push(parse_member(parser)?),
                 _ => {}
             },
             XmlEvent::EndElement { name } if name.local_name == "extensible_struct" => break,
@@ -258,16 +814,25 @@ fn parse_extensible_struct<R: std::io::Read>(
 /// Parses an <extensible_structs> element.
 fn parse_extensible_structs<R: std::io::Read>(
     parser: &mut EventReader<R>,
+    keep_comments: bool,
 ) -> Result<(String, Vec<ExtensibleStruct>), ApiGenError> {
     let mut stypes_name = String::new();
     let mut parsed_structs: Vec<ExtensibleStruct> = Vec::new();
+    let mut comment = Vec::new();
 
     loop {
         match parser.next()? {
-            XmlEvent::StartElement { name, .. } => match name.local_name.as_str() {
-                "stypes" => stypes_name = read_text_content(parser)?,
+            XmlEvent::Comment(text) => comment.extend(comment_lines(&text)),
+            XmlEvent::StartElement {
+                name, attributes, ..
+            } => match name.local_name.as_str() {
+                "stypes" => stypes_name = read_identifier(parser, "stypes")?,
                 "extensible_struct" => {
-                    parsed_structs.push(parse_extensible_struct(parser)?);
+                    let mut s = parse_extensible_struct(parser, &attributes)?;
+                    if keep_comments {
+                        s.common.comment = std::mem::take(&mut comment);
+                    }
+                    parsed_structs.push(s);
                 }
                 _ => {}
             },
@@ -284,7 +849,7 @@ fn parse_object<R: std::io::Read>(parser: &mut EventReader<R>) -> Result<Object,
     loop {
         match parser.next()? {
             XmlEvent::StartElement { name, .. } => match name.local_name.as_str() {
-                "ffi" => object.ffi = read_text_content(parser)?,
+                "ffi" => object.ffi = read_identifier(parser, "ffi")?,
                 "rust" => object.rust = read_text_content(parser)?,
                 _ => {}
             },
@@ -300,10 +865,16 @@ fn parse_function<R: std::io::Read>(parser: &mut EventReader<R>) -> Result<Funct
     let mut function = Function::default();
     loop {
         match parser.next()? {
-            XmlEvent::StartElement { name, .. } => match name.local_name.as_str() {
-                "name" => function.name = read_text_content(parser)?,
+            XmlEvent::StartElement {
+                name, attributes, ..
+            } => match name.local_name.as_str() {
+                "name" => function.name = read_identifier(parser, "name")?,
                 "return" => function.ret = read_text_content(parser)?,
-                "member" => function.members.push(parse_member(parser)?),
+                "panic_return" => function.panic_ret = read_text_content(parser)?,
+                "description" => function.description = read_description(parser)?,
+                "member" => function
+                    .members
+                    .push(parse_member(parser, &attributes, true)?),
                 _ => {}
             },
             XmlEvent::EndElement { name } if name.local_name == "function" => break,
@@ -313,21 +884,124 @@ fn parse_function<R: std::io::Read>(parser: &mut EventReader<R>) -> Result<Funct
     Ok(function)
 }
 
+/// Parses a <typemap> block and registers its types with the api. Types without a `rust` path or
+/// `c` spelling keep their name in that language.
+fn parse_typemap<R: std::io::Read>(
+    parser: &mut EventReader<R>,
+    api: &mut Api,
+) -> Result<(), ApiGenError> {
+    loop {
+        match parser.next()? {
+            XmlEvent::StartElement {
+                name, attributes, ..
+            } if name.local_name == "type" => {
+                let missing = |attribute: &str| {
+                    ApiGenError::MissingAttribute(format!("<type> missing '{}'", attribute))
+                };
+                let name =
+                    find_attribute_value(&attributes, "name").ok_or_else(|| missing("name"))?;
+                check_identifier("type", &name)?;
+                let size =
+                    find_attribute_value(&attributes, "size").ok_or_else(|| missing("size"))?;
+                let size = parse_int_literal(&size)
+                    .and_then(|size| usize::try_from(size).ok())
+                    .ok_or(ApiGenError::InvalidIntegerValue(size))?;
+                api.add_external_type(ExternalType {
+                    rust: find_attribute_value(&attributes, "rust").unwrap_or_else(|| name.clone()),
+                    c: find_attribute_value(&attributes, "c").unwrap_or_else(|| name.clone()),
+                    header: find_attribute_value(&attributes, "header"),
+                    name,
+                    size,
+                })?;
+            }
+            XmlEvent::EndElement { name } if name.local_name == "typemap" => break,
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Parses the <state_machine> of a protocol.
+fn parse_state_machine<R: std::io::Read>(
+    parser: &mut EventReader<R>,
+) -> Result<StateMachine, ApiGenError> {
+    let mut state_machine = StateMachine::default();
+    let state_name = |attributes: &[OwnedAttribute], attribute: &str| {
+        find_attribute_value(attributes, attribute)
+            .map(|name| check_identifier("state", &name).map(|()| name))
+            .transpose()
+    };
+    loop {
+        match parser.next()? {
+            XmlEvent::StartElement {
+                name, attributes, ..
+            } => match name.local_name.as_str() {
+                "state" => state_machine.states.push(State {
+                    name: state_name(&attributes, "name")?.ok_or_else(|| {
+                        ApiGenError::MissingAttribute("<state> missing 'name'".to_string())
+                    })?,
+                    is_final: parse_bool_attribute(&attributes, "final")?,
+                }),
+                "transition" => state_machine.transitions.push(Transition {
+                    from: state_name(&attributes, "from")?,
+                    request: find_attribute_value(&attributes, "request").ok_or_else(|| {
+                        ApiGenError::MissingAttribute("<transition> missing 'request'".to_string())
+                    })?,
+                    to: state_name(&attributes, "to")?,
+                }),
+                _ => {}
+            },
+            XmlEvent::EndElement { name } if name.local_name == "state_machine" => break,
+            _ => {}
+        }
+    }
+    Ok(state_machine)
+}
+
 /// Parses a <protocol> element.
-fn parse_protocol<R: std::io::Read>(parser: &mut EventReader<R>) -> Result<Protocol, ApiGenError> {
-    let mut protocol = Protocol::default();
+fn parse_protocol<R: std::io::Read>(
+    parser: &mut EventReader<R>,
+    attributes: &[OwnedAttribute],
+) -> Result<Protocol, ApiGenError> {
+    let mut protocol = Protocol {
+        extends: find_attribute_value(attributes, "extends"),
+        ring: parse_bool_attribute(attributes, "ring")?,
+        dense: parse_bool_attribute(attributes, "dense")?,
+        cancellable: parse_bool_attribute(attributes, "cancellable")?,
+        ..Default::default()
+    };
+    if let Some(id) = find_attribute_value(attributes, "id") {
+        let parsed = parse_int_literal(&id).and_then(|id| u32::try_from(id).ok());
+        protocol.proto_id = Some(parsed.ok_or(ApiGenError::InvalidIntegerValue(id))?);
+    }
+    if let Some(credits) = find_attribute_value(attributes, "credits") {
+        let parsed = parse_int_literal(&credits)
+            .and_then(|credits| u32::try_from(credits).ok())
+            .filter(|&credits| credits > 0);
+        protocol.credit_window = Some(parsed.ok_or(ApiGenError::InvalidIntegerValue(credits))?);
+    }
     loop {
         match parser.next()? {
-            XmlEvent::StartElement { name, .. } => match name.local_name.as_str() {
-                "protocol_name" => protocol.name = read_text_content(parser)?,
-                "request" => protocol.requests.push(parse_request(parser)?),
-                "response" => protocol.responses.push(parse_response(parser)?),
+            XmlEvent::StartElement {
+                name, attributes, ..
+            } => match name.local_name.as_str() {
+                "protocol_name" => protocol.name = read_identifier(parser, "protocol_name")?,
+                "request" => protocol.requests.push(parse_request(parser, &attributes)?),
+                "response" => protocol
+                    .responses
+                    .push(parse_response(parser, &attributes)?),
+                "event" => protocol.events.push(parse_event(parser)?),
+                "reserved" => protocol.reserved.push(parse_reserved(&attributes)?),
+                "state_machine" => protocol.state_machine = Some(parse_state_machine(parser)?),
                 _ => {}
             },
             XmlEvent::EndElement { name } if name.local_name == "protocol" => break,
             _ => {}
         }
     }
+    if parse_bool_attribute(attributes, "namespaced")? {
+        protocol.type_prefix = to_pascal_case(&protocol.name);
+    }
     Ok(protocol)
 }
 
@@ -337,97 +1011,227 @@ fn parse_define<R: std::io::Read>(
     api: &mut Api,
 ) -> Result<(), ApiGenError> {
     let mut def = Definition::default();
+    let keep_comments = api.preserve_comments();
+    let mut comment = Vec::new();
     loop {
         match parser.next()? {
-            XmlEvent::StartElement { name, .. } => match name.local_name.as_str() {
-                "name" => {
-                    if def.name.is_empty() {
-                        def.name = read_text_content(parser)?;
-                    } else {
-                        // This is a name of a member or something else, so we need to consume it
-                        // without assigning to def.name.
-                        read_text_content(parser)?;
+            XmlEvent::Comment(text) => comment.extend(comment_lines(&text)),
+            XmlEvent::StartElement {
+                name, attributes, ..
+            } => {
+                // Only a comment directly preceding an item belongs to it.
+                let item_comment = std::mem::take(&mut comment);
+                match name.local_name.as_str() {
+                    "name" => {
+                        if def.name.is_empty() {
+                            def.name = read_identifier(parser, "name")?;
+                        } else {
+                            // This is a name of a member or something else, so we need to consume
+                            // it without assigning to def.name.
+                            read_text_content(parser)?;
+                        }
                     }
-                }
-                "enum" => {
-                    let new_enum = parse_enum(parser)?;
-                    def.items.push(new_enum.name.clone());
-                    api.add_enum(new_enum)?;
-                }
-                "flags" => {
-                    while let Some(_) =
-                        parse_block_item(parser, "flags", "flag", |p| -> Result<(), ApiGenError> {
-                            let flag = parse_flag(p)?;
-                            def.items.push(flag.name.clone());
-                            api.add_flag(flag)?;
-                            Ok(())
-                        })?
-                    {}
-                }
-                "constants" => {
-                    while let Some(_) = parse_block_item(
-                        parser,
-                        "constants",
-                        "constant",
-                        |p| -> Result<(), ApiGenError> {
-                            let constant = parse_constant(p)?;
-                            def.items.push(constant.name.clone());
-                            api.add_constant(constant)?;
-                            Ok(())
-                        },
-                    )? {}
-                }
-                "structs" => {
-                    while let Some(_) = parse_block_item(
-                        parser,
-                        "structs",
-                        "struct",
-                        |p| -> Result<(), ApiGenError> {
-                            let new_struct = parse_struct(p)?;
-                            def.items.push(new_struct.common.name.clone());
-                            api.add_struct(new_struct)?;
-                            Ok(())
-                        },
-                    )? {}
-                }
-                "extensible_structs" => {
-                    let (stypes_name, parsed_structs) = parse_extensible_structs(parser)?;
-                    for s in &parsed_structs {
-                        def.items.push(s.common.name.clone());
+                    "enum" => {
+                        let mut new_enum = parse_enum(parser, &attributes)?;
+                        if keep_comments {
+                            new_enum.comment = item_comment;
+                        }
+                        def.items.push(new_enum.name.clone());
+                        record_since(api, &new_enum.name, &attributes)?;
+                        api.add_enum(new_enum)?;
                     }
-                    def.items.push(stypes_name.clone());
-                    api.add_extensible_structs(stypes_name, parsed_structs)?;
-                }
-                "objects" => {
-                    while let Some(_) = parse_block_item(
-                        parser,
-                        "objects",
-                        "object",
-                        |p| -> Result<(), ApiGenError> {
-                            let mut object = parse_object(p)?;
-                            // Hack, object doesn't have a name.
-                            object.name = object.ffi.clone();
-                            def.items.push(object.name.clone());
-                            api.add_object(object);
-                            Ok(())
-                        },
-                    )? {}
-                }
-                "function" => {
-                    let function = parse_function(parser)?;
-                    def.items.push(function.name.clone());
-                    api.add_function(function);
-                }
-                "protocol" => {
-                    let protocol = parse_protocol(parser)?;
-                    let protocol_struct_name =
-                        format!("{}CommandHdr", to_pascal_case(&protocol.name));
-                    def.items.push(protocol_struct_name);
-                    def.items.push(protocol.name.clone());
-                    api.add_protocol(protocol)?;
+                    "flags" => {
+                        while parse_block_item(
+                            parser,
+                            "flags",
+                            "flag",
+                            |p, attributes, item_comment| -> Result<(), ApiGenError> {
+                                let mut flag = parse_flag(p, attributes)?;
+                                if keep_comments {
+                                    flag.comment = item_comment;
+                                }
+                                def.items.push(flag.name.clone());
+                                record_since(api, &flag.name, attributes)?;
+                                api.add_flag(flag)?;
+                                Ok(())
+                            },
+                        )?
+                        .is_some()
+                        {}
+                    }
+                    "constants" => {
+                        while parse_block_item(
+                            parser,
+                            "constants",
+                            "constant",
+                            |p, attributes, item_comment| -> Result<(), ApiGenError> {
+                                let mut constant = parse_constant(p)?;
+                                if keep_comments {
+                                    constant.comment = item_comment;
+                                }
+                                def.items.push(constant.name.clone());
+                                record_since(api, &constant.name, attributes)?;
+                                api.add_constant(constant)?;
+                                Ok(())
+                            },
+                        )?
+                        .is_some()
+                        {}
+                    }
+                    "structs" => {
+                        while parse_block_item(
+                            parser,
+                            "structs",
+                            "struct",
+                            |p, attributes, item_comment| -> Result<(), ApiGenError> {
+                                let mut new_struct = parse_struct(p, attributes)?;
+                                if keep_comments {
+                                    new_struct.common.comment = item_comment;
+                                }
+                                add_inline_structs(
+                                    api,
+                                    &mut def,
+                                    &new_struct.common.name.clone(),
+                                    &mut new_struct.common.members,
+                                )?;
+                                def.items.push(new_struct.common.name.clone());
+                                record_since(api, &new_struct.common.name, attributes)?;
+                                api.add_struct(new_struct)?;
+                                Ok(())
+                            },
+                        )?
+                        .is_some()
+                        {}
+                    }
+                    "typedefs" | "aliases" => {
+                        let (block, element) = match name.local_name.as_str() {
+                            "typedefs" => ("typedefs", "typedef"),
+                            _ => ("aliases", "alias"),
+                        };
+                        while parse_block_item(
+                            parser,
+                            block,
+                            element,
+                            |p, attributes, item_comment| -> Result<(), ApiGenError> {
+                                let mut typedef = parse_typedef(p, element)?;
+                                if keep_comments {
+                                    typedef.comment = item_comment;
+                                }
+                                def.items.push(typedef.name.clone());
+                                record_since(api, &typedef.name, attributes)?;
+                                api.add_typedef(typedef)?;
+                                Ok(())
+                            },
+                        )?
+                        .is_some()
+                        {}
+                    }
+                    "unions" => {
+                        while parse_block_item(
+                            parser,
+                            "unions",
+                            "union",
+                            |p, attributes, item_comment| -> Result<(), ApiGenError> {
+                                let mut new_union = parse_union(p, attributes)?;
+                                if keep_comments {
+                                    new_union.common.comment = item_comment;
+                                }
+                                add_inline_structs(
+                                    api,
+                                    &mut def,
+                                    &new_union.common.name.clone(),
+                                    &mut new_union.common.members,
+                                )?;
+                                def.items.push(new_union.common.name.clone());
+                                record_since(api, &new_union.common.name, attributes)?;
+                                api.add_union(new_union)?;
+                                Ok(())
+                            },
+                        )?
+                        .is_some()
+                        {}
+                    }
+                    "extensible_structs" => {
+                        let (stypes_name, mut parsed_structs) =
+                            parse_extensible_structs(parser, keep_comments)?;
+                        for s in &mut parsed_structs {
+                            let owner = s.common.name.clone();
+                            add_inline_structs(api, &mut def, &owner, &mut s.common.members)?;
+                        }
+                        for s in &parsed_structs {
+                            def.items.push(s.common.name.clone());
+                        }
+                        def.items.push(stypes_name.clone());
+                        api.add_extensible_structs(stypes_name, parsed_structs)?;
+                    }
+                    "objects" => {
+                        while parse_block_item(
+                            parser,
+                            "objects",
+                            "object",
+                            |p, _, _| -> Result<(), ApiGenError> {
+                                let mut object = parse_object(p)?;
+                                // Hack, object doesn't have a name.
+                                object.name = object.ffi.clone();
+                                def.items.push(object.name.clone());
+                                api.add_object(object);
+                                Ok(())
+                            },
+                        )?
+                        .is_some()
+                        {}
+                    }
+                    "function" => {
+                        let mut function = parse_function(parser)?;
+                        if keep_comments {
+                            function.comment = item_comment;
+                        }
+                        def.items.push(function.name.clone());
+                        record_since(api, &function.name, &attributes)?;
+                        api.add_function(function)?;
+                    }
+                    "protocol" => {
+                        let mut protocol = parse_protocol(parser, &attributes)?;
+                        if keep_comments {
+                            protocol.comment = item_comment;
+                        }
+                        for req in &mut protocol.requests {
+                            let owner = format!(
+                                "{}{}Req",
+                                protocol.type_prefix,
+                                to_pascal_case(&req.opcode.name)
+                            );
+                            add_inline_structs(api, &mut def, &owner, &mut req.members)?;
+                        }
+                        for resp in &mut protocol.responses {
+                            let owner = format!(
+                                "{}{}Resp",
+                                protocol.type_prefix,
+                                to_pascal_case(&resp.opcode.name)
+                            );
+                            add_inline_structs(api, &mut def, &owner, &mut resp.members)?;
+                        }
+                        for event in &mut protocol.events {
+                            let owner = format!(
+                                "{}{}Event",
+                                protocol.type_prefix,
+                                to_pascal_case(&event.opcode.name)
+                            );
+                            add_inline_structs(api, &mut def, &owner, &mut event.members)?;
+                        }
+                        // Extensions reuse the header of their base protocol.
+                        if protocol.extends.is_none() {
+                            let protocol_struct_name =
+                                format!("{}CommandHdr", to_pascal_case(&protocol.name));
+                            def.items.push(protocol_struct_name);
+                        }
+                        def.items.push(protocol.name.clone());
+                        record_since(api, &protocol.name, &attributes)?;
+                        api.add_protocol(protocol)?;
+                    }
+                    _ => {}
                 }
-                _ => {}
-            },
+            }
             XmlEvent::EndElement { name } if name.local_name == "define" => break,
             _ => {}
         }
@@ -436,11 +1240,35 @@ fn parse_define<R: std::io::Read>(
     Ok(())
 }
 
+/// Reads the text of an element holding `true` or `false`.
+fn read_bool_content<R: std::io::Read>(
+    parser: &mut EventReader<R>,
+    element: &str,
+) -> Result<bool, ApiGenError> {
+    let value = read_text_content(parser)?;
+    match value.as_str() {
+        "true" => Ok(true),
+        "false" => Ok(false),
+        _ => Err(ApiGenError::InvalidAttributeValue {
+            attribute: element.to_string(),
+            value,
+        }),
+    }
+}
+
 /// Parses a <generated_file> block.
 fn parse_generated_file<R: std::io::Read>(
     parser: &mut EventReader<R>,
+    attributes: &[OwnedAttribute],
 ) -> Result<GeneratedFile, ApiGenError> {
     let mut gen_file = GeneratedFile::default();
+    if let Some(split) = find_attribute_value(attributes, "split") {
+        gen_file.split =
+            SplitMode::from_str(&split).ok_or_else(|| ApiGenError::InvalidAttributeValue {
+                attribute: "split".to_string(),
+                value: split.clone(),
+            })?;
+    }
     loop {
         match parser.next()? {
             XmlEvent::StartElement { name, .. } => match name.local_name.as_str() {
@@ -449,6 +1277,66 @@ fn parse_generated_file<R: std::io::Read>(
                 "file_type" => gen_file.file_type = read_text_content(parser)?,
                 "include" => gen_file.includes.push(read_text_content(parser)?),
                 "instantiate" => gen_file.instantiations.push(read_text_content(parser)?),
+                "namespace" => gen_file.namespace = Some(read_text_content(parser)?),
+                "decode_mode" => {
+                    let mode = read_text_content(parser)?;
+                    if mode != "stream" && mode != "slice" {
+                        return Err(ApiGenError::InvalidAttributeValue {
+                            attribute: "decode_mode".to_string(),
+                            value: mode,
+                        });
+                    }
+                    gen_file.decode_mode = mode;
+                }
+                "order" => {
+                    let order = read_text_content(parser)?;
+                    gen_file.order = ItemOrder::from_str(&order).ok_or_else(|| {
+                        ApiGenError::InvalidAttributeValue {
+                            attribute: "order".to_string(),
+                            value: order.clone(),
+                        }
+                    })?;
+                }
+                "target" => {
+                    let target = read_text_content(parser)?;
+                    gen_file.target = RustTarget::from_str(&target).ok_or_else(|| {
+                        ApiGenError::InvalidAttributeValue {
+                            attribute: "target".to_string(),
+                            value: target.clone(),
+                        }
+                    })?;
+                }
+                "reexport" => gen_file.reexport = read_bool_content(parser, "reexport")?,
+                "coverage" => gen_file.coverage = read_bool_content(parser, "coverage")?,
+                "decode_arena" => {
+                    gen_file.decode_arena = read_bool_content(parser, "decode_arena")?
+                }
+                "command_log" => gen_file.command_log = read_bool_content(parser, "command_log")?,
+                "client" => gen_file.client = read_bool_content(parser, "client")?,
+                "connection" => gen_file.connection = read_bool_content(parser, "connection")?,
+                "doc_module" => gen_file.doc_module = Some(read_text_content(parser)?),
+                "max_version" => {
+                    let version = read_text_content(parser)?;
+                    gen_file.max_version = Some(version.trim().parse().map_err(|_| {
+                        ApiGenError::InvalidAttributeValue {
+                            attribute: "max_version".to_string(),
+                            value: version.clone(),
+                        }
+                    })?);
+                }
+                "small_command_limit" => {
+                    let limit = read_text_content(parser)?;
+                    let parsed = limit.trim().parse::<usize>().ok().filter(|&l| l > 0);
+                    gen_file.small_command_limit =
+                        Some(parsed.ok_or_else(|| ApiGenError::InvalidAttributeValue {
+                            attribute: "small_command_limit".to_string(),
+                            value: limit.clone(),
+                        })?);
+                }
+                "freestanding" => {
+                    gen_file.freestanding = read_bool_content(parser, "freestanding")?
+                }
+                "typedef_header" => gen_file.typedef_headers.push(read_text_content(parser)?),
                 _ => {}
             },
             XmlEvent::EndElement { name } if name.local_name == "generated_file" => break,
@@ -458,10 +1346,141 @@ fn parse_generated_file<R: std::io::Read>(
     Ok(gen_file)
 }
 
-/// Parses the entire <api> block.
-fn parse_api_internal<R: std::io::Read>(parser: &mut EventReader<R>) -> Result<Api, ApiGenError> {
-    let mut api = Api::new();
+/// XML files of an include chain: the ones being parsed, innermost last, and every file parsed so
+/// far, which later includes skip.
+#[derive(Default)]
+struct IncludeState {
+    stack: Vec<PathBuf>,
+    seen: HashSet<PathBuf>,
+}
+
+impl IncludeState {
+    /// Starts parsing `path`. Returns false for a file that was already parsed.
+    fn enter(&mut self, path: &Path) -> Result<bool, ApiGenError> {
+        let invalid = |reason: String| ApiGenError::InvalidInclude {
+            file: self
+                .stack
+                .last()
+                .map(|file| file.display().to_string())
+                .unwrap_or_default(),
+            include: path.display().to_string(),
+            reason,
+        };
+        let canonical = fs::canonicalize(path).map_err(|e| invalid(e.to_string()))?;
+        if self.stack.contains(&canonical) {
+            return Err(invalid("the file includes itself".to_string()));
+        }
+        if !self.seen.insert(canonical.clone()) {
+            return Ok(false);
+        }
+        self.stack.push(canonical);
+        Ok(true)
+    }
+
+    fn leave(&mut self) {
+        self.stack.pop();
+    }
+}
 
+fn create_parser(path: &Path) -> Result<EventReader<BufReader<File>>, ApiGenError> {
+    let reader = BufReader::new(File::open(path)?);
+    // Comments are always read; whether they are kept is up to the <api> element.
+    Ok(ParserConfig::new()
+        .ignore_comments(false)
+        .create_reader(reader))
+}
+
+/// Attaches the position `parser` reached in `file` to an error raised while parsing it. Errors
+/// of included files keep the position they already carry, and schema violations list their own.
+fn at_position<R: std::io::Read>(
+    parser: &EventReader<R>,
+    file: &Path,
+    err: ApiGenError,
+) -> ApiGenError {
+    if let ApiGenError::AtPosition { .. } | ApiGenError::SchemaViolations { .. } = err {
+        return err;
+    }
+    let position = parser.position();
+    ApiGenError::AtPosition {
+        file: file.display().to_string(),
+        line: position.row + 1,
+        column: position.column + 1,
+        source: Box::new(err),
+    }
+}
+
+/// Resolves the `path` of an `<include>` element against the directory of the including file.
+fn include_path(file: &Path, attributes: &[OwnedAttribute]) -> Result<PathBuf, ApiGenError> {
+    let path = find_attribute_value(attributes, "path").ok_or_else(|| {
+        ApiGenError::MissingAttribute(format!("<include> in {} missing 'path'", file.display()))
+    })?;
+    Ok(file.parent().unwrap_or(Path::new("")).join(path))
+}
+
+/// Parses an included XML file, merging its defines and, recursively, its own includes into
+/// `api`. Other elements of the included file are ignored.
+fn parse_include(
+    path: &Path,
+    api: &mut Api,
+    includes: &mut IncludeState,
+) -> Result<(), ApiGenError> {
+    if !includes.enter(path)? {
+        return Ok(());
+    }
+    schema::validate(&mut create_parser(path)?, path)?;
+    let mut parser = create_parser(path)?;
+    parse_include_items(&mut parser, path, api, includes)
+        .map_err(|err| at_position(&parser, path, err))?;
+    includes.leave();
+    Ok(())
+}
+
+fn parse_include_items<R: std::io::Read>(
+    parser: &mut EventReader<R>,
+    path: &Path,
+    api: &mut Api,
+    includes: &mut IncludeState,
+) -> Result<(), ApiGenError> {
+    loop {
+        match parser.next()? {
+            XmlEvent::StartElement {
+                name, attributes, ..
+            } => match name.local_name.as_str() {
+                "define" => parse_define(parser, api)?,
+                "include" => parse_include(&include_path(path, &attributes)?, api, includes)?,
+                _ => {}
+            },
+            XmlEvent::EndDocument => return Ok(()),
+            _ => {}
+        }
+    }
+}
+
+/// Parses a <naming> element, returning the file type it applies to.
+fn parse_naming(attributes: &[OwnedAttribute]) -> Result<(String, Naming), ApiGenError> {
+    let file_type = find_attribute_value(attributes, "file_type")
+        .ok_or_else(|| ApiGenError::MissingAttribute("<naming> missing 'file_type'".to_string()))?;
+    let mut naming = Naming {
+        strip_type_prefix: find_attribute_value(attributes, "strip_type_prefix"),
+        ..Default::default()
+    };
+    if let Some(case) = find_attribute_value(attributes, "constant_case") {
+        naming.constant_case =
+            ConstantCase::from_str(&case).ok_or_else(|| ApiGenError::InvalidAttributeValue {
+                attribute: "constant_case".to_string(),
+                value: case.clone(),
+            })?;
+    }
+    Ok((file_type, naming))
+}
+
+/// Parses the entire <api> block of `file`.
+fn parse_api_internal<R: std::io::Read>(
+    parser: &mut EventReader<R>,
+    mut api: Api,
+    file: &Path,
+    includes: &mut IncludeState,
+) -> Result<Api, ApiGenError> {
     loop {
         match parser.next()? {
             XmlEvent::StartElement {
@@ -471,6 +1490,21 @@ fn parse_api_internal<R: std::io::Read>(parser: &mut EventReader<R>) -> Result<A
                     let name = find_attribute_value(&attributes, "name")
                         .unwrap_or_else(|| "unknown".to_string());
                     api.set_name(name);
+                    api.set_strict_units(parse_bool_attribute(&attributes, "strict_units")?);
+                    api.set_preserve_comments(parse_bool_attribute(
+                        &attributes,
+                        "preserve_comments",
+                    )?);
+                    api.set_literal_formats(
+                        parse_literal_format(&attributes, "constant_literals")?,
+                        parse_literal_format(&attributes, "enum_literals")?,
+                        parse_literal_format(&attributes, "flag_literals")?,
+                    );
+                    if let Some(patterns) = find_attribute_value(&attributes, "count_members") {
+                        api.set_count_patterns(
+                            patterns.split(',').map(|p| p.trim().to_string()).collect(),
+                        );
+                    }
                 }
                 "copyright" => {
                     let copyright = parse_copyright(parser)?;
@@ -481,8 +1515,14 @@ fn parse_api_internal<R: std::io::Read>(parser: &mut EventReader<R>) -> Result<A
                     api.set_version(version);
                 }
                 "define" => parse_define(parser, &mut api)?,
+                "include" => parse_include(&include_path(file, &attributes)?, &mut api, includes)?,
+                "naming" => {
+                    let (file_type, naming) = parse_naming(&attributes)?;
+                    api.set_naming(file_type, naming);
+                }
+                "typemap" => parse_typemap(parser, &mut api)?,
                 "generated_file" => {
-                    let gen_file = parse_generated_file(parser)?;
+                    let gen_file = parse_generated_file(parser, &attributes)?;
                     api.add_generated_file(gen_file);
                 }
                 _ => {}
@@ -495,7 +1535,8 @@ fn parse_api_internal<R: std::io::Read>(parser: &mut EventReader<R>) -> Result<A
     Ok(api)
 }
 
-/// Generic helper to parse items within a block.
+/// Generic helper to parse items within a block. The comment directly preceding an item is
+/// passed to `parse_fn` as lines.
 fn parse_block_item<R, T, F>(
     parser: &mut EventReader<R>,
     block_name: &str,
@@ -504,32 +1545,41 @@ fn parse_block_item<R, T, F>(
 ) -> Result<Option<T>, ApiGenError>
 where
     R: std::io::Read,
-    F: FnMut(&mut EventReader<R>) -> Result<T, ApiGenError>,
+    F: FnMut(&mut EventReader<R>, &[OwnedAttribute], Vec<String>) -> Result<T, ApiGenError>,
 {
-    match parser.next()? {
-        XmlEvent::StartElement { name, .. } if name.local_name == item_name => {
-            Ok(Some(parse_fn(parser)?))
-        }
-        XmlEvent::EndElement { name } if name.local_name == block_name => Ok(None), // Sentinel
-        XmlEvent::Characters(s) if s.trim().is_empty() => {
-            // It's whitespace, try the next event.
-            parse_block_item(parser, block_name, item_name, parse_fn)
-        }
-        XmlEvent::Whitespace(_) => {
+    let mut comment = Vec::new();
+    loop {
+        match parser.next()? {
+            XmlEvent::StartElement {
+                name, attributes, ..
+            } if name.local_name == item_name => {
+                return Ok(Some(parse_fn(parser, &attributes, comment)?))
+            }
+            XmlEvent::EndElement { name } if name.local_name == block_name => return Ok(None), // Sentinel
             // It's whitespace, try the next event.
-            parse_block_item(parser, block_name, item_name, parse_fn)
-        }
-        XmlEvent::Comment(_) => {
-            // It's a comment, try the next event.
-            parse_block_item(parser, block_name, item_name, parse_fn)
+            XmlEvent::Characters(s) if s.trim().is_empty() => {}
+            XmlEvent::Whitespace(_) => {}
+            XmlEvent::Comment(text) => comment.extend(comment_lines(&text)),
+            _ => return Ok(None),
         }
-        _ => Ok(None),
     }
 }
 
 pub fn parse_api(filename: &Path) -> Result<Api, ApiGenError> {
-    let file = File::open(filename)?;
-    let reader = BufReader::new(file);
-    let mut parser = EventReader::new(reader);
-    parse_api_internal(&mut parser)
+    // Structural problems are all reported before the parser stops at the first one.
+    schema::validate(&mut create_parser(filename)?, filename)?;
+    let mut parser = create_parser(filename)?;
+    let mut api = Api::new();
+    api.set_stype_lock(read_lock(&stype_lock_path(filename))?);
+    api.set_frozen_lock(read_lock(&frozen_lock_path(filename))?);
+    api.set_opcode_lock(read_lock(&opcode_lock_path(filename))?);
+    let mut includes = IncludeState::default();
+    includes.enter(filename)?;
+    let mut api = parse_api_internal(&mut parser, api, filename, &mut includes)
+        .map_err(|err| at_position(&parser, filename, err))?;
+    api.link_generated_files()?;
+    api.check_command_types()?;
+    api.check_frozen()?;
+    api.check_keywords();
+    Ok(api)
 }